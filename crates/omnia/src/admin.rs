@@ -0,0 +1,348 @@
+//! # Admin Endpoint
+//!
+//! An optional HTTP listener, separate from the guest's routes, for
+//! orchestrators to probe the runtime:
+//!
+//! - `/healthz` reports the process is alive.
+//! - `/readyz` reports ready once every backend has connected, all servers
+//!   are listening and every backend's [`Backend::health`] check passes. It
+//!   reports not ready once shutdown has been signalled so traffic is drained
+//!   away from the process.
+//! - `/info` reports the runtime's version, loaded components and linked
+//!   hosts.
+//!
+//! The listener is enabled by setting `ADMIN_ADDR`. Runtimes start it before
+//! connecting to backends, so probes are answered during startup.
+
+#![allow(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use fromenv::FromEnv;
+use futures::future::{BoxFuture, join_all};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use tokio::net::TcpListener;
+
+use crate::shutdown::Shutdown;
+use crate::traits::{self, Backend};
+
+/// Admin endpoint configuration.
+#[derive(Debug, Clone, FromEnv)]
+pub struct AdminOptions {
+    /// The address the admin endpoint listens on. The endpoint is disabled
+    /// when unset.
+    #[env(from = "ADMIN_ADDR")]
+    pub addr: Option<String>,
+
+    /// The maximum time (in seconds) a backend health check may take before
+    /// the backend is reported unhealthy.
+    #[env(from = "ADMIN_CHECK_TIMEOUT_SECS", default = "5")]
+    pub check_timeout_secs: u64,
+}
+
+impl traits::FromEnv for AdminOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading admin endpoint options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        traits::requirement_vars(&Self::requirements())
+    }
+}
+
+type HealthCheck = Arc<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// Tracks whether the runtime is ready to accept work.
+#[derive(Clone, Default)]
+pub struct Health {
+    inner: Arc<HealthInner>,
+}
+
+#[derive(Default)]
+struct HealthInner {
+    connected: AtomicBool,
+    servers: Mutex<Vec<(String, Shutdown)>>,
+    backends: Mutex<Vec<(String, HealthCheck)>>,
+}
+
+impl Health {
+    /// Record that every backend has connected.
+    pub fn connected(&self) {
+        self.inner.connected.store(true, Ordering::Release);
+    }
+
+    /// Register a backend's health check.
+    pub fn add_backend<B: Backend + Clone + 'static>(&self, name: &str, backend: &B) {
+        let backend = backend.clone();
+        self.add_check(
+            name,
+            Arc::new(move || {
+                let backend = backend.clone();
+                Box::pin(async move { backend.health().await })
+            }),
+        );
+    }
+
+    fn add_check(&self, name: &str, check: HealthCheck) {
+        self.inner
+            .backends
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((name.to_string(), check));
+    }
+
+    /// Register a server, returning the shutdown signal it should be started
+    /// with. The runtime is not ready until the server calls
+    /// [`Shutdown::listening`].
+    #[must_use]
+    pub fn register(&self, name: &str, shutdown: &Shutdown) -> Shutdown {
+        let shutdown = shutdown.for_server();
+        self.inner
+            .servers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((name.to_string(), shutdown.clone()));
+        shutdown
+    }
+
+    /// Check whether the runtime is ready, running each backend's health
+    /// check for up to `timeout`.
+    pub async fn readiness(&self, timeout: Duration) -> Readiness {
+        let servers = self
+            .inner
+            .servers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(name, shutdown)| (name.clone(), shutdown.clone()))
+            .collect::<Vec<_>>();
+        let backends = self.inner.backends.lock().unwrap_or_else(PoisonError::into_inner).clone();
+
+        let checks = backends.iter().map(|(name, check)| async move {
+            let status = match tokio::time::timeout(timeout, check()).await {
+                Ok(Ok(())) => "ok".to_string(),
+                Ok(Err(e)) => format!("{e:#}"),
+                Err(_elapsed) => format!("health check timed out after {timeout:?}"),
+            };
+            (name.clone(), status)
+        });
+        let backends = join_all(checks).await.into_iter().collect::<BTreeMap<_, _>>();
+
+        let connected = self.inner.connected.load(Ordering::Acquire);
+        let shutting_down = servers.iter().any(|(_, shutdown)| shutdown.is_signalled());
+        let servers = servers
+            .into_iter()
+            .map(|(name, shutdown)| (name, shutdown.is_listening()))
+            .collect::<BTreeMap<_, _>>();
+
+        Readiness {
+            ready: connected
+                && !shutting_down
+                && servers.values().all(|listening| *listening)
+                && backends.values().all(|status| status == "ok"),
+            connected,
+            shutting_down,
+            servers,
+            backends,
+        }
+    }
+}
+
+/// The runtime's readiness, as reported by `/readyz`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    /// Whether the runtime is ready to accept work.
+    pub ready: bool,
+
+    /// Whether every backend has connected.
+    pub connected: bool,
+
+    /// Whether shutdown has been signalled.
+    pub shutting_down: bool,
+
+    /// Whether each server is listening.
+    pub servers: BTreeMap<String, bool>,
+
+    /// Each backend's health: `ok`, or the reason the check failed.
+    pub backends: BTreeMap<String, String>,
+}
+
+/// Details of the runtime, as reported by `/info`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeInfo {
+    /// The runtime's package name.
+    pub runtime: String,
+
+    /// The runtime's package version.
+    pub version: String,
+
+    /// The version of omnia the runtime was built with.
+    pub omnia_version: String,
+
+    /// The names of the loaded components.
+    pub components: Vec<String>,
+
+    /// The hosts linked by the runtime.
+    pub hosts: Vec<String>,
+}
+
+impl RuntimeInfo {
+    /// Describe a runtime built from the named package.
+    #[must_use]
+    pub fn new(runtime: &str, version: &str, components: Vec<String>, hosts: &[&str]) -> Self {
+        Self {
+            runtime: runtime.to_string(),
+            version: version.to_string(),
+            omnia_version: env!("CARGO_PKG_VERSION").to_string(),
+            components,
+            hosts: hosts.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// Serve the admin endpoint until the returned future is dropped.
+///
+/// Returns immediately if the endpoint is disabled.
+///
+/// # Errors
+///
+/// Returns an error if the admin options are invalid or the listener cannot
+/// be bound.
+pub async fn serve_admin(health: &Health, info: RuntimeInfo) -> Result<()> {
+    let options = <AdminOptions as traits::FromEnv>::from_env()?;
+    let Some(addr) = options.addr else {
+        return Ok(());
+    };
+
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("binding admin endpoint to {addr}"))?;
+    tracing::info!("admin endpoint listening on: {addr}");
+
+    let admin = Arc::new(Admin {
+        health: health.clone(),
+        info,
+        check_timeout: Duration::from_secs(options.check_timeout_secs),
+    });
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let admin = Arc::clone(&admin);
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let admin = Arc::clone(&admin);
+                async move { Ok::<_, Infallible>(admin.handle(&request).await) }
+            });
+            if let Err(e) =
+                http1::Builder::new().serve_connection(TokioIo::new(stream), service).await
+            {
+                tracing::debug!("admin connection error: {e}");
+            }
+        });
+    }
+}
+
+struct Admin {
+    health: Health,
+    info: RuntimeInfo,
+    check_timeout: Duration,
+}
+
+impl Admin {
+    async fn handle(&self, request: &Request<Incoming>) -> Response<Full<Bytes>> {
+        if request.method() != Method::GET {
+            return text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+        }
+        match request.uri().path() {
+            "/healthz" => text(StatusCode::OK, "ok"),
+            "/readyz" => {
+                let readiness = self.health.readiness(self.check_timeout).await;
+                let status =
+                    if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+                json(status, &readiness)
+            }
+            "/info" => json(StatusCode::OK, &self.info),
+            _ => text(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+}
+
+fn text(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+    *response.status_mut() = status;
+    response
+}
+
+fn json(status: StatusCode, value: &impl Serialize) -> Response<Full<Bytes>> {
+    let Ok(body) = serde_json::to_vec(value) else {
+        return text(StatusCode::INTERNAL_SERVER_ERROR, "serialization failed");
+    };
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response.headers_mut().insert(CONTENT_TYPE, "application/json".parse().expect("valid header"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn check(healthy: bool) -> HealthCheck {
+        Arc::new(move || {
+            Box::pin(async move {
+                if healthy { Ok(()) } else { Err(anyhow::anyhow!("connection refused")) }
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn ready_once_started() {
+        let health = Health::default();
+        let (shutdown, trigger) = Shutdown::new(Duration::from_secs(1));
+        let server = health.register("WasiHttp", &shutdown);
+        health.add_check("SqlDefault", check(true));
+        assert!(!health.readiness(TIMEOUT).await.ready);
+
+        // backends connected but server not yet listening
+        health.connected();
+        let readiness = health.readiness(TIMEOUT).await;
+        assert!(!readiness.ready);
+        assert!(!readiness.servers["WasiHttp"]);
+
+        server.listening();
+        let readiness = health.readiness(TIMEOUT).await;
+        assert!(readiness.ready);
+        assert_eq!(readiness.backends["SqlDefault"], "ok");
+
+        // not ready while draining
+        trigger.trigger();
+        let readiness = health.readiness(TIMEOUT).await;
+        assert!(readiness.shutting_down);
+        assert!(!readiness.ready);
+    }
+
+    #[tokio::test]
+    async fn unhealthy_backend() {
+        let health = Health::default();
+        health.connected();
+        health.add_check("SqlDefault", check(false));
+
+        let readiness = health.readiness(TIMEOUT).await;
+        assert!(!readiness.ready);
+        assert_eq!(readiness.backends["SqlDefault"], "connection refused");
+    }
+}
@@ -0,0 +1,89 @@
+//! # Named Backends
+//!
+//! Routes the names a guest opens resources by (e.g. a SQL connection or a
+//! key-value bucket) to different backends within a single runtime, falling
+//! back to a default backend for unlisted names.
+//!
+//! Hosts supporting named backends implement [`NamedHost`](crate::NamedHost),
+//! and implement their context trait for `Backends<dyn Ctx>` by dispatching
+//! to [`Backends::get`].
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+
+/// Backends selected by resource name.
+pub struct Backends<C: ?Sized> {
+    named: Arc<HashMap<String, Arc<C>>>,
+    default: Option<Arc<C>>,
+}
+
+impl<C: ?Sized> Backends<C> {
+    /// Create backends for the `named` resources, using `default` for any
+    /// other name.
+    pub fn new(named: impl IntoIterator<Item = (String, Arc<C>)>, default: Option<Arc<C>>) -> Self {
+        Self {
+            named: Arc::new(named.into_iter().collect()),
+            default,
+        }
+    }
+
+    /// Returns the backend for the named resource.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no backend is configured for the name and there is
+    /// no default.
+    pub fn get(&self, name: &str) -> Result<&Arc<C>> {
+        self.named
+            .get(name)
+            .or(self.default.as_ref())
+            .ok_or_else(|| anyhow!("no backend is configured for `{name}`"))
+    }
+
+    /// The names with a dedicated backend.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.named.keys().map(String::as_str)
+    }
+}
+
+impl<C: ?Sized> Clone for Backends<C> {
+    fn clone(&self) -> Self {
+        Self {
+            named: Arc::clone(&self.named),
+            default: self.default.clone(),
+        }
+    }
+}
+
+impl<C: ?Sized> Debug for Backends<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.names().collect::<Vec<_>>();
+        names.sort_unstable();
+        f.debug_struct("Backends")
+            .field("named", &names)
+            .field("default", &self.default.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_by_name() {
+        let backends = Backends::<str>::new(
+            [("orders".to_string(), Arc::from("postgres"))],
+            Some(Arc::from("sqlite")),
+        );
+        assert_eq!(&**backends.get("orders").expect("should route"), "postgres");
+        assert_eq!(&**backends.get("cache").expect("should fall back"), "sqlite");
+
+        let backends = Backends::<str>::new([("orders".to_string(), Arc::from("postgres"))], None);
+        let err = backends.get("cache").expect_err("should have no default");
+        assert_eq!(err.to_string(), "no backend is configured for `cache`");
+    }
+}
@@ -0,0 +1,315 @@
+//! # Compilation Cache
+//!
+//! Caches components compiled from `wasm32` files so the runtime does not
+//! recompile them on every start.
+//!
+//! Entries are content-addressed: the key is a hash of the wasm file and the
+//! engine's compatibility settings, so changing either results in a fresh
+//! compilation rather than loading a stale or incompatible artifact. Once
+//! the cache exceeds its size limit, the least recently used entries are
+//! removed.
+//!
+//! The cache is stored in `COMPILE_CACHE_DIR`, defaulting to `omnia` in the
+//! user's cache directory, and can be disabled by setting `COMPILE_CACHE` to
+//! `false`. Cached components are loaded as native code, so the cache is
+//! disabled rather than falling back to a shared directory (such as `/tmp`)
+//! when the user has no cache directory.
+
+#![allow(missing_docs)]
+
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{env, process};
+
+use anyhow::{Context, Result, bail};
+use fromenv::FromEnv;
+use sha2::{Digest, Sha256};
+use wasmtime::Engine;
+use wasmtime::component::Component;
+
+use crate::traits;
+
+/// Compilation cache configuration.
+#[derive(Debug, Clone, FromEnv)]
+pub struct CacheOptions {
+    /// Whether compiled components are cached.
+    #[env(from = "COMPILE_CACHE", default = "true")]
+    pub enabled: bool,
+
+    /// The cache directory. Defaults to `omnia` in the user's cache
+    /// directory.
+    #[env(from = "COMPILE_CACHE_DIR")]
+    pub dir: Option<PathBuf>,
+
+    /// The maximum total size (in bytes) of cached components.
+    #[env(from = "COMPILE_CACHE_MAX_BYTES", default = "1073741824")]
+    pub max_bytes: u64,
+}
+
+impl traits::FromEnv for CacheOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading compilation cache options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        traits::requirement_vars(&Self::requirements())
+    }
+}
+
+/// A content-addressed cache of compiled components.
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+    max_bytes: u64,
+}
+
+impl Cache {
+    /// Create a cache using the specified options.
+    #[must_use]
+    pub fn new(options: &CacheOptions) -> Self {
+        if !options.enabled {
+            return Self::disabled();
+        }
+        let Some(dir) = options.dir.clone().or_else(default_dir) else {
+            tracing::warn!("compilation cache disabled: set `COMPILE_CACHE_DIR` or `HOME`");
+            return Self::disabled();
+        };
+        Self {
+            dir: Some(dir),
+            max_bytes: options.max_bytes,
+        }
+    }
+
+    /// A cache that never stores compiled components.
+    #[must_use]
+    pub const fn disabled() -> Self {
+        Self {
+            dir: None,
+            max_bytes: 0,
+        }
+    }
+
+    /// The cache directory, if caching is enabled.
+    #[must_use]
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Load a `wasm32` component, reusing a cached compilation when
+    /// available and caching a fresh compilation otherwise.
+    ///
+    /// Failing to read or write the cache is logged rather than returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the wasm file cannot be read or compiled.
+    pub fn load(&self, engine: &Engine, wasm: &Path) -> Result<Component> {
+        let bytes = fs::read(wasm).with_context(|| format!("reading {}", wasm.display()))?;
+        let Some(dir) = &self.dir else {
+            return Ok(Component::new(engine, &bytes)?);
+        };
+
+        let entry = dir.join(format!("{}.bin", key(engine, &bytes)));
+        if entry.exists() {
+            // SAFETY: cache entries are only written by `store`, and are keyed
+            // by the compatibility settings of the engine that compiled them.
+            match unsafe { Component::deserialize_file(engine, &entry) } {
+                Ok(component) => {
+                    tracing::debug!(
+                        monotonic_counter.compile_cache_hits = 1,
+                        "loaded {} from compilation cache",
+                        wasm.display()
+                    );
+                    touch(&entry);
+                    return Ok(component);
+                }
+                Err(e) => {
+                    tracing::warn!("discarding cached component {}: {e}", entry.display());
+                    let _ = fs::remove_file(&entry);
+                }
+            }
+        }
+
+        tracing::info!(monotonic_counter.compile_cache_misses = 1, "compiling {}", wasm.display());
+        let component = Component::new(engine, &bytes)?;
+        if let Err(e) = self.store(&component, &entry) {
+            tracing::warn!("issue caching compiled component: {e:#}");
+        }
+        Ok(component)
+    }
+
+    /// Compile a `wasm32` component into the cache ahead of time, returning
+    /// the path of the cache entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if caching is disabled, or the component cannot be
+    /// compiled or stored.
+    pub fn warm(&self, engine: &Engine, wasm: &Path) -> Result<PathBuf> {
+        let Some(dir) = &self.dir else {
+            bail!("compilation cache is disabled");
+        };
+        let bytes = fs::read(wasm).with_context(|| format!("reading {}", wasm.display()))?;
+        let entry = dir.join(format!("{}.bin", key(engine, &bytes)));
+        let component = Component::new(engine, &bytes)?;
+        self.store(&component, &entry)?;
+        Ok(entry)
+    }
+
+    // Write a compiled component to the cache, then evict old entries.
+    fn store(&self, component: &Component, entry: &Path) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)
+            .with_context(|| format!("creating cache directory {}", dir.display()))?;
+
+        // write to a temporary file so readers never see a partial entry
+        let tmp = entry.with_extension(format!("{}.tmp", process::id()));
+        fs::write(&tmp, component.serialize()?)?;
+        fs::rename(&tmp, entry)?;
+
+        evict(dir, self.max_bytes, entry)
+    }
+}
+
+// Remove the least recently used entries until the cache fits within
+// `max_bytes`, always keeping `keep`.
+fn evict(dir: &Path, max_bytes: u64, keep: &Path) -> Result<()> {
+    let mut entries = Vec::new();
+    for item in fs::read_dir(dir)? {
+        let path = item?.path();
+        if path.extension().is_none_or(|ext| ext != "bin") {
+            continue;
+        }
+        let metadata = path.metadata()?;
+        let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        entries.push((used, metadata.len(), path));
+    }
+
+    let mut total = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+    entries.sort_unstable();
+    for (_, size, path) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        if path == keep {
+            continue;
+        }
+        fs::remove_file(&path)?;
+        tracing::debug!("evicted {} from compilation cache", path.display());
+        total -= size;
+    }
+    Ok(())
+}
+
+// Record a cache hit so the entry is evicted last.
+fn touch(entry: &Path) {
+    let _ = File::options().write(true).open(entry).and_then(|f| f.set_modified(SystemTime::now()));
+}
+
+// The cache key for a wasm component compiled by the engine.
+fn key(engine: &Engine, wasm: &[u8]) -> String {
+    let mut hasher = KeyHasher(Sha256::new());
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    hasher.0.update(wasm);
+    format!("{:x}", hasher.0.finalize())
+}
+
+// The user's cache directory, if they have one.
+fn default_dir() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(dir.join("omnia"))
+}
+
+// Feeds `Hash` implementations into the key digest.
+struct KeyHasher(Sha256);
+
+impl Hasher for KeyHasher {
+    fn finish(&self) -> u64 {
+        unreachable!("only used to update the digest")
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (component)
+    const EMPTY: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+
+    fn cache(name: &str, max_bytes: u64) -> Cache {
+        let dir = env::temp_dir().join(format!("omnia-cache-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Cache {
+            dir: Some(dir),
+            max_bytes,
+        }
+    }
+
+    #[test]
+    fn caches_components() {
+        let cache = cache("hit", u64::MAX);
+        let dir = cache.dir().expect("should be enabled").to_path_buf();
+        let wasm = dir.with_extension("wasm");
+        fs::write(&wasm, EMPTY).expect("should write");
+
+        let engine = Engine::default();
+        cache.load(&engine, &wasm).expect("should compile");
+        let entries = fs::read_dir(&dir).expect("should read").count();
+        assert_eq!(entries, 1);
+
+        // same wasm and engine reuse the entry
+        cache.load(&engine, &wasm).expect("should load");
+        let entry = cache.warm(&engine, &wasm).expect("should warm");
+        assert!(entry.exists());
+        assert_eq!(fs::read_dir(&dir).expect("should read").count(), entries);
+
+        // a different engine configuration uses a new entry
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let fueled = Engine::new(&config).expect("should create engine");
+        cache.load(&fueled, &wasm).expect("should compile");
+        assert_eq!(fs::read_dir(&dir).expect("should read").count(), 2);
+
+        fs::remove_dir_all(&dir).expect("should remove");
+        fs::remove_file(&wasm).expect("should remove");
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = cache("evict", 10);
+        let dir = cache.dir().expect("should be enabled");
+        fs::create_dir_all(dir).expect("should create");
+
+        let now = SystemTime::now();
+        for (i, name) in ["old", "recent", "new"].iter().enumerate() {
+            let path = dir.join(format!("{name}.bin"));
+            fs::write(&path, [0; 6]).expect("should write");
+            let used = now - std::time::Duration::from_secs(60 * (3 - i as u64));
+            File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|f| f.set_modified(used))
+                .expect("should set");
+        }
+
+        evict(dir, 10, &dir.join("old.bin")).expect("should evict");
+        let mut remaining = fs::read_dir(dir)
+            .expect("should read")
+            .map(|e| e.expect("entry").file_name().into_string().expect("utf-8"))
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["old.bin"]);
+
+        fs::remove_dir_all(dir).expect("should remove");
+    }
+}
@@ -2,11 +2,52 @@
 
 use std::fs::{self, File};
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, anyhow};
+use clap::Args;
+use wasmtime::Engine;
 use wasmtime::component::Component;
-use wasmtime::{Config, Engine};
+
+use crate::cache::{Cache, CacheOptions};
+use crate::create;
+use crate::limits::{self, Limits};
+use crate::pool::Pooling;
+use crate::settings::{EngineSettings, OptLevel, WasmFeatures};
+use crate::traits::FromEnv;
+
+/// Options controlling ahead-of-time compilation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Args)]
+pub struct CompileOptions {
+    /// The target triple to compile for (e.g. `aarch64-unknown-linux-gnu`).
+    /// Defaults to the host. Compiling for another architecture requires
+    /// the `all-arch` feature.
+    #[arg(long)]
+    pub target: Option<String>,
+
+    /// The Cranelift optimization level.
+    #[arg(long, value_enum, default_value_t)]
+    pub opt_level: OptLevel,
+
+    /// Optional WebAssembly proposals.
+    #[command(flatten)]
+    pub features: WasmFeatures,
+}
+
+impl CompileOptions {
+    /// The engine settings used to compile with these options.
+    #[must_use]
+    pub fn settings(&self, limits: &Limits) -> EngineSettings {
+        let runtime = EngineSettings::runtime(limits);
+        EngineSettings {
+            target: self.target.clone().unwrap_or(runtime.target),
+            opt_level: self.opt_level,
+            fuel: runtime.fuel,
+            features: self.features,
+            core_instances: None,
+        }
+    }
+}
 
 /// Compile `wasm32-wasip2` component.
 ///
@@ -21,15 +62,39 @@ use wasmtime::{Config, Engine};
 /// Returns an error if the wasm component cannot be loaded from the specified
 /// path, cannot be compiled, or cannot be serialized to the specified output
 /// directory.
+///
+/// N.B. fuel metering (`GUEST_FUEL`) changes the generated code, so must be
+/// configured the same way when compiling and running a component.
 pub fn compile(wasm: &PathBuf, output: Option<PathBuf>) -> Result<()> {
+    compile_with(wasm, output, &CompileOptions::default())
+}
+
+/// Compile `wasm32-wasip2` component using the specified options.
+///
+/// When writing to a file, the engine settings used are recorded next to it
+/// in `<output>.toml` so the runtime can report incompatibilities when
+/// loading the component, along with the number of core instances the
+/// component creates.
+///
+/// # Errors
+///
+/// Returns an error if the options are invalid for the target, or the
+/// component cannot be loaded, compiled, or serialized to the specified
+/// output directory.
+pub fn compile_with(
+    wasm: &PathBuf, output: Option<PathBuf>, options: &CompileOptions,
+) -> Result<()> {
     let Some(file_name) = wasm.file_name() else {
         return Err(anyhow!("invalid file name"));
     };
 
     // compile component
-    let mut config = Config::new();
-    config.wasm_component_model_async(true);
-    let engine = Engine::new(&config)?;
+    let limits = <Limits as FromEnv>::from_env()?;
+    let settings = EngineSettings {
+        core_instances: limits::core_instances(&fs::read(wasm)?),
+        ..options.settings(&limits)
+    };
+    let engine = engine(options, &settings, &limits)?;
     let component = Component::from_file(&engine, wasm)?;
     let serialized = component.serialize()?;
 
@@ -49,6 +114,7 @@ pub fn compile(wasm: &PathBuf, output: Option<PathBuf>) -> Result<()> {
         }
 
         File::create(&out_path)?.write_all(&serialized)?;
+        settings.write(&out_path)?;
     } else {
         // output to stdout
         let mut stdout = io::stdout().lock();
@@ -58,4 +124,68 @@ pub fn compile(wasm: &PathBuf, output: Option<PathBuf>) -> Result<()> {
     Ok(())
 }
 
+/// Compile a `wasm32-wasip2` component into the runtime's compilation cache
+/// so the first `run` does not need to compile it, returning the path of
+/// the cache entry.
+///
+/// The component is compiled using the runtime's engine configuration, so
+/// guest limits and pooling must be configured as they will be at runtime.
+///
+/// # Errors
+///
+/// Returns an error if the cache is disabled, or the component cannot be
+/// compiled or stored.
+pub fn warm_cache(wasm: &Path) -> Result<PathBuf> {
+    let limits = <Limits as FromEnv>::from_env()?;
+    let pooling = <Pooling as FromEnv>::from_env()?;
+    let cache = Cache::new(&<CacheOptions as FromEnv>::from_env()?);
+    let engine = create::engine(&limits, &pooling)?;
+    cache.warm(&engine, wasm)
+}
+
+// Create the engine used to compile with the specified options.
+fn engine(options: &CompileOptions, settings: &EngineSettings, limits: &Limits) -> Result<Engine> {
+    let mut config = create::config(limits);
+    settings.apply(&mut config);
+
+    // setting the target explicitly disables inferring the host's CPU
+    // features, so is only done when cross-compiling
+    if let Some(target) = &options.target {
+        config.target(target).map_err(|e| anyhow!("unsupported target {target}: {e}"))?;
+    }
+
+    Engine::new(&config).map_err(|e| match &options.target {
+        Some(target) => anyhow!(
+            "cannot compile for {target}: {e} (compiling for other architectures requires the \
+             `all-arch` feature)"
+        ),
+        None => e.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use super::*;
+
+    fn compatibility(engine: &Engine) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn host_compatible() {
+        let limits = Limits::default();
+        let runtime = create::engine(&limits, &Pooling::default()).expect("should create engine");
+
+        // components compiled for the host use the runtime's CPU features
+        let options = CompileOptions::default();
+        let compiler =
+            engine(&options, &options.settings(&limits), &limits).expect("should create engine");
+        assert_eq!(compatibility(&compiler), compatibility(&runtime));
+    }
+}
+
 //
@@ -0,0 +1,274 @@
+//! # Concurrency Limits
+//!
+//! Caps the number of guest invocations in flight so a burst of work cannot
+//! exhaust the host.
+//!
+//! A runtime-wide limit is shared by every server, and the runtime's servers
+//! may each be given their own limit using `{SERVER}_MAX_IN_FLIGHT`, e.g.
+//! `HTTP_MAX_IN_FLIGHT`.
+//! An invocation holds a [`Permit`] counting against both limits while it
+//! runs. Servers able to refuse work (such as HTTP) shed invocations over the
+//! limit, while servers pulling work (such as messaging) wait for a permit
+//! before pulling more.
+//!
+//! In-flight and queued invocations are reported as the `in_flight` and
+//! `queued` up-down counters, labelled with the server's name.
+
+#![allow(missing_docs)]
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use fromenv::FromEnv;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::traits;
+
+/// Runtime-wide concurrency configuration.
+#[derive(Debug, Clone, FromEnv)]
+pub struct ConcurrencyOptions {
+    /// The maximum number of guest invocations in flight across all servers.
+    /// When unset, invocations are only limited per server.
+    #[env(from = "MAX_IN_FLIGHT")]
+    pub max_in_flight: Option<NonZeroUsize>,
+
+    /// The maximum number of guest invocations in flight for the HTTP server.
+    #[env(from = "HTTP_MAX_IN_FLIGHT")]
+    pub http_max_in_flight: Option<NonZeroUsize>,
+
+    /// The maximum number of guest invocations in flight for the messaging
+    /// server.
+    #[env(from = "MESSAGING_MAX_IN_FLIGHT")]
+    pub messaging_max_in_flight: Option<NonZeroUsize>,
+
+    /// The maximum number of guest invocations in flight for the WebSocket
+    /// server.
+    #[env(from = "WEBSOCKET_MAX_IN_FLIGHT")]
+    pub websocket_max_in_flight: Option<NonZeroUsize>,
+
+    /// The maximum number of guest invocations in flight for the cron server.
+    #[env(from = "CRON_MAX_IN_FLIGHT")]
+    pub cron_max_in_flight: Option<NonZeroUsize>,
+
+    /// The time (in seconds) callers turned away because too many
+    /// invocations are in flight are asked to wait before retrying.
+    #[env(from = "RETRY_AFTER_SECS", default = "1")]
+    pub retry_after_secs: u64,
+}
+
+impl traits::FromEnv for ConcurrencyOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading concurrency options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        traits::requirement_vars(&Self::requirements())
+    }
+}
+
+/// Limits on the guest invocations a server may have in flight.
+#[derive(Debug, Clone)]
+pub struct Concurrency {
+    server: Arc<str>,
+    global: Option<Arc<Semaphore>>,
+    limit: Option<Arc<Semaphore>>,
+    servers: Arc<HashMap<&'static str, NonZeroUsize>>,
+    retry_after: Duration,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Self {
+            server: Arc::from(""),
+            global: None,
+            limit: None,
+            servers: Arc::default(),
+            retry_after: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Concurrency {
+    /// Create runtime-wide limits shared by every server.
+    #[must_use]
+    pub fn new(options: &ConcurrencyOptions) -> Self {
+        let servers = [
+            ("http", options.http_max_in_flight),
+            ("messaging", options.messaging_max_in_flight),
+            ("websocket", options.websocket_max_in_flight),
+            ("cron", options.cron_max_in_flight),
+        ];
+        Self {
+            global: options.max_in_flight.map(|max| Arc::new(Semaphore::new(max.get()))),
+            servers: Arc::new(
+                servers.into_iter().filter_map(|(server, max)| Some((server, max?))).collect(),
+            ),
+            retry_after: Duration::from_secs(options.retry_after_secs),
+            ..Self::default()
+        }
+    }
+
+    /// Create runtime-wide limits from the environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the concurrency options cannot be loaded from the
+    /// environment.
+    pub fn from_env() -> Result<Self> {
+        let options = <ConcurrencyOptions as traits::FromEnv>::from_env()?;
+        Ok(Self::new(&options))
+    }
+
+    /// The limits for a single server, adding the server's own limit
+    /// configured using `{SERVER}_MAX_IN_FLIGHT`.
+    #[must_use]
+    pub fn for_server(&self, server: &str) -> Self {
+        self.with_limit(server, self.servers.get(server).copied())
+    }
+
+    /// The limits for a single server, adding `max_in_flight` as the
+    /// server's own limit.
+    #[must_use]
+    pub fn with_limit(&self, server: &str, max_in_flight: Option<NonZeroUsize>) -> Self {
+        Self {
+            server: Arc::from(server),
+            limit: max_in_flight.map(|max| Arc::new(Semaphore::new(max.get()))),
+            ..self.clone()
+        }
+    }
+
+    /// The time callers turned away should wait before retrying.
+    #[must_use]
+    pub const fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    /// Acquire a permit for an invocation, if one is available without
+    /// waiting.
+    #[must_use]
+    pub fn try_acquire(&self) -> Option<Permit> {
+        let limit = match &self.limit {
+            Some(limit) => Some(Arc::clone(limit).try_acquire_owned().ok()?),
+            None => None,
+        };
+        let global = match &self.global {
+            Some(global) => Some(Arc::clone(global).try_acquire_owned().ok()?),
+            None => None,
+        };
+        Some(Permit::new(&self.server, global, limit))
+    }
+
+    /// Acquire a permit for an invocation, waiting until one is available.
+    pub async fn acquire(&self) -> Permit {
+        if let Some(permit) = self.try_acquire() {
+            return permit;
+        }
+
+        tracing::info!(counter.queued = 1, server = %self.server);
+        // the semaphores are never closed
+        let limit = match &self.limit {
+            Some(limit) => Arc::clone(limit).acquire_owned().await.ok(),
+            None => None,
+        };
+        let global = match &self.global {
+            Some(global) => Arc::clone(global).acquire_owned().await.ok(),
+            None => None,
+        };
+        tracing::info!(counter.queued = -1, server = %self.server);
+
+        Permit::new(&self.server, global, limit)
+    }
+}
+
+/// Counts an invocation against its server's limits until dropped.
+#[derive(Debug)]
+pub struct Permit {
+    server: Arc<str>,
+    _global: Option<OwnedSemaphorePermit>,
+    _limit: Option<OwnedSemaphorePermit>,
+}
+
+impl Permit {
+    fn new(
+        server: &Arc<str>, global: Option<OwnedSemaphorePermit>,
+        limit: Option<OwnedSemaphorePermit>,
+    ) -> Self {
+        tracing::info!(counter.in_flight = 1, server = %server);
+        Self {
+            server: Arc::clone(server),
+            _global: global,
+            _limit: limit,
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        tracing::info!(counter.in_flight = -1, server = %self.server);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(global: usize, server: usize) -> Concurrency {
+        let options = ConcurrencyOptions {
+            max_in_flight: NonZeroUsize::new(global),
+            http_max_in_flight: NonZeroUsize::new(server),
+            messaging_max_in_flight: None,
+            websocket_max_in_flight: None,
+            cron_max_in_flight: None,
+            retry_after_secs: 1,
+        };
+        Concurrency::new(&options).for_server("http")
+    }
+
+    #[test]
+    fn server_limit() {
+        let concurrency = limited(0, 2);
+        let first = concurrency.try_acquire().expect("should acquire");
+        let _second = concurrency.try_acquire().expect("should acquire");
+        assert!(concurrency.try_acquire().is_none());
+
+        drop(first);
+        assert!(concurrency.try_acquire().is_some());
+    }
+
+    #[test]
+    fn limits_only_the_named_server() {
+        let messaging = limited(0, 1).for_server("messaging");
+        let _first = messaging.try_acquire().expect("should acquire");
+        assert!(messaging.try_acquire().is_some());
+    }
+
+    #[test]
+    fn global_limit_is_shared() {
+        let http = limited(1, 0);
+        let messaging = http.with_limit("messaging", None);
+
+        let permit = http.try_acquire().expect("should acquire");
+        assert!(messaging.try_acquire().is_none());
+        drop(permit);
+        assert!(messaging.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn waits_for_permit() {
+        let concurrency = limited(0, 1);
+        let permit = concurrency.try_acquire().expect("should acquire");
+
+        let waiting = tokio::spawn({
+            let concurrency = concurrency.clone();
+            async move { drop(concurrency.acquire().await) }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        drop(permit);
+        waiting.await.expect("should acquire once released");
+    }
+}
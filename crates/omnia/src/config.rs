@@ -0,0 +1,417 @@
+//! # Runtime Configuration File
+//!
+//! Supplies runtime and backend configuration from a single TOML file (e.g.
+//! `omnia run --config omnia.toml`) instead of scattered environment
+//! variables.
+//!
+//! Each table is a section for a host, backend or component, and each key
+//! supplies the environment variable named by joining the section and key in
+//! upper snake case. Top-level keys supply the variable of the same name:
+//!
+//! ```toml
+//! capability_policy = "policy.toml"  # CAPABILITY_POLICY
+//!
+//! [sql]
+//! database = "orders.db"             # SQL_DATABASE
+//!
+//! [identity]
+//! client_id = "orders"               # IDENTITY_CLIENT_ID
+//! client_secret = "${ORDERS_SECRET}" # IDENTITY_CLIENT_SECRET
+//! token_url = "https://login.example.com/token"
+//!
+//! [order-skill]
+//! http_prefix = "/orders"            # ORDER_SKILL_HTTP_PREFIX
+//! ```
+//!
+//! String values may reference environment variables as `${NAME}` or
+//! `${NAME:-default}`, with `$$` producing a literal `$`. Arrays are joined
+//! with commas. Variables already set in the environment take precedence over
+//! the file, so individual settings can still be overridden at deployment.
+//!
+//! Sections and keys must name variables read by the runtime, its hosts or
+//! its backends, so a misspelt key (e.g. `[sql] databse`) is reported rather
+//! than ignored. Sections named after a hosted component may supply any
+//! variable, as components read arbitrary configuration through
+//! `wasi:config`.
+//!
+//! Each backend's connection options are then loaded from its section as if
+//! set in the environment. Problems with the file and with every backend's
+//! options are reported together rather than one at a time.
+
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::{env, fs};
+
+use anyhow::{Context, Result};
+use toml::{Table, Value};
+
+use crate::admin::AdminOptions;
+use crate::cache::CacheOptions;
+use crate::concurrency::ConcurrencyOptions;
+use crate::guest::GuestOptions;
+use crate::limits::Limits;
+use crate::pool::Pooling;
+use crate::reload::ReloadOptions;
+use crate::routing::env_prefix;
+use crate::shutdown::ShutdownOptions;
+use crate::traits::FromEnv;
+
+/// The environment variables read by the runtime itself, excluding those
+/// read by its hosts and backends.
+#[must_use]
+pub fn runtime_vars() -> Vec<String> {
+    let mut vars = ["CAPABILITY_POLICY", "COMPONENT", "HOSTNAME", "OTEL_GRPC_URL", "RUST_LOG"]
+        .map(String::from)
+        .to_vec();
+    vars.extend(AdminOptions::env_vars());
+    vars.extend(CacheOptions::env_vars());
+    vars.extend(ConcurrencyOptions::env_vars());
+    vars.extend(GuestOptions::env_vars());
+    vars.extend(Limits::env_vars());
+    vars.extend(Pooling::env_vars());
+    vars.extend(ReloadOptions::env_vars());
+    vars.extend(ShutdownOptions::env_vars());
+    vars
+}
+
+/// Environment variables supplied by a runtime configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    vars: Vec<(String, String)>,
+
+    // the key supplying each variable, e.g. `sql.database`
+    keys: Vec<String>,
+}
+
+impl RuntimeConfig {
+    /// Load a configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or listing all
+    /// invalid entries.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("reading runtime configuration {}", path.display()))?;
+        Self::parse(&contents, |name| env::var(name).ok())
+            .with_context(|| format!("loading runtime configuration {}", path.display()))
+    }
+
+    /// Parse configuration, resolving `${NAME}` references with `lookup`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contents are not valid TOML, or listing all
+    /// invalid entries.
+    pub fn parse(contents: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let table: Table = toml::from_str(contents)?;
+        let mut vars = Vec::new();
+        let mut keys = Vec::new();
+        let mut errors = ConfigErrors::default();
+
+        let mut add = |var: String, key: &str, value: &Value| match to_env(value, &lookup) {
+            Ok(value) => {
+                vars.push((var, value));
+                keys.push(key.to_string());
+            }
+            Err(e) => errors.push(format!("`{key}`: {e}")),
+        };
+        for (name, value) in &table {
+            let Value::Table(section) = value else {
+                add(env_prefix(name), name, value);
+                continue;
+            };
+            for (key, value) in section {
+                add(
+                    format!("{}_{}", env_prefix(name), env_prefix(key)),
+                    &format!("{name}.{key}"),
+                    value,
+                );
+            }
+        }
+
+        errors.into_result()?;
+        Ok(Self { vars, keys })
+    }
+
+    /// Check the file only supplies `known` variables or configuration for
+    /// the named `components`, listing every unknown section and key.
+    ///
+    /// # Errors
+    ///
+    /// Returns the unknown sections and keys, if any.
+    pub fn check(&self, known: &[String], components: &[String]) -> Result<(), ConfigErrors> {
+        let prefixes = components.iter().map(|name| format!("{}_", env_prefix(name)));
+        let prefixes = prefixes.collect::<Vec<_>>();
+        let mut errors = ConfigErrors::default();
+        let mut unknown_sections = Vec::new();
+
+        for ((var, _), key) in self.vars.iter().zip(&self.keys) {
+            if known.contains(var) || prefixes.iter().any(|prefix| var.starts_with(prefix)) {
+                continue;
+            }
+            // a section no known variable belongs to is reported once
+            if let Some((section, _)) = key.split_once('.') {
+                let prefix = format!("{}_", env_prefix(section));
+                if !known.iter().any(|var| var.starts_with(&prefix)) {
+                    if !unknown_sections.contains(&section) {
+                        unknown_sections.push(section);
+                        errors.push(format!("`{section}`: unknown section"));
+                    }
+                    continue;
+                }
+            }
+            errors.push(format!("`{key}`: unknown key"));
+        }
+
+        errors.into_result()
+    }
+
+    /// The environment variables supplied by the file.
+    #[must_use]
+    pub fn vars(&self) -> &[(String, String)] {
+        &self.vars
+    }
+
+    /// Set the environment variables supplied by the file, leaving variables
+    /// that are already set unchanged.
+    ///
+    /// # Safety
+    ///
+    /// Setting environment variables is only sound while no other thread may
+    /// be reading or writing the environment, so this must be called at the
+    /// start of `main`, before the async runtime or any other thread is
+    /// started.
+    pub unsafe fn apply(&self) {
+        for (name, value) in &self.vars {
+            if env::var_os(name).is_none() {
+                // SAFETY: the caller guarantees no other thread is running.
+                unsafe {
+                    env::set_var(name, value);
+                }
+            }
+        }
+    }
+}
+
+// Convert a configuration value to an environment variable value.
+fn to_env(value: &Value, lookup: &impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    match value {
+        Value::String(s) => interpolate(s, lookup),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Datetime(d) => Ok(d.to_string()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Array(_) | Value::Table(_) => Err("arrays must contain values".to_string()),
+                item => to_env(item, lookup),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|items| items.join(",")),
+        Value::Table(_) => Err("nested tables are not supported".to_string()),
+    }
+}
+
+// Replace `${NAME}` and `${NAME:-default}` references with their values.
+fn interpolate(s: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let Some(reference) = rest.strip_prefix('{') else {
+            out.push('$');
+            continue;
+        };
+        let Some(end) = reference.find('}') else {
+            return Err(format!("unterminated reference in {s:?}"));
+        };
+        let (name, default) = match reference[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&reference[..end], None),
+        };
+        match lookup(name).or_else(|| default.map(ToString::to_string)) {
+            Some(value) => out.push_str(&value),
+            None => return Err(format!("environment variable `{name}` is not set")),
+        }
+        rest = &reference[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Configuration problems collected so they can be reported together.
+#[derive(Debug, Default)]
+pub struct ConfigErrors(Vec<String>);
+
+impl ConfigErrors {
+    /// Record a configuration problem.
+    pub fn push(&mut self, error: impl Into<String>) {
+        self.0.push(error.into());
+    }
+
+    /// Record the error from loading the named configuration, if any,
+    /// returning the loaded value otherwise.
+    pub fn check<T>(&mut self, name: &str, result: Result<T>) -> Option<T> {
+        result.map_err(|e| self.push(format!("{name}: {e:#}"))).ok()
+    }
+
+    /// Returns `true` if no problems have been recorded.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the recorded problems as an error, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns `self` if any problems have been recorded.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.0.len() == 1 { "" } else { "s" };
+        write!(f, "{} configuration error{plural}:", self.0.len())?;
+        for (i, error) in self.0.iter().enumerate() {
+            // indent multi-line errors (e.g. lists of missing variables)
+            write!(f, "\n  {}. {}", i + 1, error.replace('\n', "\n     "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "SECRET").then(|| "s3cr3t".to_string())
+    }
+
+    #[test]
+    fn sections() {
+        let config = RuntimeConfig::parse(
+            r#"
+            capability_policy = "policy.toml"
+
+            [sql]
+            database = "orders.db"
+
+            [http]
+            connect_timeout_secs = 5
+            egress_allow = ["api.example.com", "10.0.0.0/8"]
+
+            [order-skill]
+            http_prefix = "/orders"
+            "#,
+            lookup,
+        )
+        .expect("should parse");
+
+        assert_eq!(
+            config.vars(),
+            [
+                ("CAPABILITY_POLICY".into(), "policy.toml".into()),
+                ("HTTP_CONNECT_TIMEOUT_SECS".into(), "5".into()),
+                ("HTTP_EGRESS_ALLOW".into(), "api.example.com,10.0.0.0/8".into()),
+                ("ORDER_SKILL_HTTP_PREFIX".into(), "/orders".into()),
+                ("SQL_DATABASE".into(), "orders.db".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(interpolate("${SECRET}", &lookup).as_deref(), Ok("s3cr3t"));
+        assert_eq!(interpolate("a-${UNSET:-b}-c", &lookup).as_deref(), Ok("a-b-c"));
+        assert_eq!(interpolate("$$HOME $5", &lookup).as_deref(), Ok("$HOME $5"));
+        interpolate("${UNSET}", &lookup).expect_err("should require variable");
+        interpolate("${SECRET", &lookup).expect_err("should require closing brace");
+    }
+
+    #[test]
+    fn reports_all_errors() {
+        let err = RuntimeConfig::parse(
+            r#"
+            [identity]
+            client_secret = "${UNSET}"
+            token_url = "${ALSO_UNSET}"
+
+            [http.egress]
+            allow = "*"
+            "#,
+            lookup,
+        )
+        .expect_err("should fail");
+
+        let message = err.to_string();
+        assert!(message.starts_with("3 configuration errors:"), "{message}");
+        assert!(message.contains("`identity.client_secret`: environment variable `UNSET`"));
+        assert!(message.contains("`http.egress`: nested tables are not supported"));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let config = RuntimeConfig::parse(
+            r#"
+            capability_policy = "policy.toml"
+            log_level = "debug"
+
+            [sql]
+            database = "orders.db"
+            databse = "orders.db"
+
+            [sqll]
+            database = "orders.db"
+            timeout = 5
+
+            [order-skill]
+            http_prefix = "/orders"
+            api_url = "https://api.example.com"
+            "#,
+            lookup,
+        )
+        .expect("should parse");
+
+        let mut known = runtime_vars();
+        known.push("SQL_DATABASE".to_string());
+        let components = ["order-skill".to_string()];
+
+        let message = config.check(&known, &components).expect_err("should fail").to_string();
+        assert_eq!(
+            message,
+            "3 configuration errors:\n  1. `log_level`: unknown key\n  2. `sql.databse`: \
+             unknown key\n  3. `sqll`: unknown section"
+        );
+
+        // component sections accept any key
+        let config =
+            RuntimeConfig::parse("[order-skill]\napi_url = \"https://api.example.com\"", lookup)
+                .expect("should parse");
+        config.check(&known, &components).expect("should accept component keys");
+    }
+
+    #[test]
+    fn collects_errors() {
+        let mut errors = ConfigErrors::default();
+        assert_eq!(errors.check("sql", Ok(1)), Some(1));
+        assert_eq!(errors.check::<()>("identity", Err(anyhow::anyhow!("missing"))), None);
+        assert_eq!(
+            errors.into_result().expect_err("should fail").to_string(),
+            "1 configuration error:\n  1. identity: missing"
+        );
+    }
+}
@@ -1,16 +1,27 @@
 //! # WebAssembly Initiator
 
-use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, fs, slice};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use omnia_otel::Telemetry;
 use tracing::instrument;
 use wasmtime::component::{Component, InstancePre, Linker};
-use wasmtime::{Config, Engine};
+use wasmtime::{Config, Engine, Precompiled};
 use wasmtime_wasi::WasiView;
 
-use crate::traits::Host;
+use crate::cache::{Cache, CacheOptions};
+use crate::config::ConfigErrors;
+use crate::guest::{Guest, GuestOptions};
+use crate::inspect::Inspection;
+use crate::invocation::Attributes;
+use crate::limits::{self, Limits};
+use crate::policy::{Capabilities, Policy};
+use crate::pool::Pooling;
+use crate::routing::Routes;
+use crate::settings::EngineSettings;
+use crate::traits::{FromEnv, Host};
 
 /// Build the Wasmtime `Engine` and `Linker` for this runtime.
 ///
@@ -21,43 +32,245 @@ use crate::traits::Host;
 /// support.
 #[instrument]
 pub fn create<T: WasiView + 'static>(wasm: &PathBuf) -> Result<Compiled<T>> {
-    init_env(wasm)?;
+    let mut compiled = create_all(slice::from_ref(wasm))?;
+    compiled.pop().context("no component loaded")
+}
+
+/// Build the Wasmtime `Engine` and a `Linker` for each component hosted by
+/// this runtime.
+///
+/// Components share a single `Engine` and are named after their file stem.
+///
+/// # Errors
+///
+/// Will fail if no components are specified, component names are not unique,
+/// any `wasm` file cannot be compiled/deserialized as a `Component`, or a
+/// `Linker` cannot be initialized with WASI support.
+#[instrument]
+pub fn create_all<T: WasiView + 'static>(wasm: &[PathBuf]) -> Result<Vec<Compiled<T>>> {
+    create_all_with(wasm, ConfigErrors::default())
+}
+
+/// Build the runtime as for [`create_all`], reporting configuration problems
+/// already found by the caller (e.g. with backend connection options)
+/// together with any problems with the runtime's own settings.
+///
+/// # Errors
+///
+/// Will fail for the reasons given for [`create_all`], or if `errors` is not
+/// empty.
+#[instrument(skip(errors))]
+pub fn create_all_with<T: WasiView + 'static>(
+    wasm: &[PathBuf], mut errors: ConfigErrors,
+) -> Result<Vec<Compiled<T>>> {
+    let names = wasm.iter().map(|path| component_name(path)).collect::<Vec<_>>();
+    if names.is_empty() {
+        bail!("at least one component must be specified");
+    }
+    if let Some(name) =
+        names.iter().enumerate().find_map(|(i, n)| names[..i].contains(n).then_some(n))
+    {
+        bail!("component names must be unique: {name} is duplicated");
+    }
+
+    init_env(&names)?;
     tracing::info!("initializing runtime");
 
-    let mut config = Config::new();
-    config.wasm_component_model_async(true);
-    let engine = Engine::new(&config)?;
-
-    // TODO: cause executing WebAssembly to periodically yield
-    //  1. enable `Config::epoch_interruption`
-    //  2. Set `Store::epoch_deadline_async_yield_and_update`
-    //  3. Call `Engine::increment_epoch` periodically
-
-    // SAFETY: The caller should ensure only valid pre-compiled wasm files are provided.
-    let component = unsafe { Component::deserialize_file(&engine, wasm) }.or_else(|e| {
-        if cfg!(feature = "jit") {
-            Component::from_file(&engine, wasm)
-        } else {
-            Err(wasmtime::Error::msg(format!(
-                "Issue loading component: {e}. Enable `jit` feature to load wasm32 files."
-            )))
-        }
-    })?;
+    // load all runtime settings first, reporting problems together
+    let limits = errors.check("limits", <Limits as FromEnv>::from_env());
+    let pooling = errors.check("pooling", <Pooling as FromEnv>::from_env());
+    let policy = errors.check("capability policy", Policy::from_env());
+    let cache = errors.check("compilation cache", <CacheOptions as FromEnv>::from_env());
+    let guest = errors.check(
+        "guest",
+        <GuestOptions as FromEnv>::from_env().and_then(|options| Guest::new(&options)),
+    );
+    let attributes = names
+        .iter()
+        .map(|name| {
+            errors.check(&format!("{name} telemetry attributes"), Attributes::from_env(name))
+        })
+        .collect::<Vec<_>>();
+    let attributes = attributes.into_iter().collect::<Option<Vec<_>>>();
+    let (Some(limits), Some(pooling), Some(policy), Some(cache), Some(guest), Some(attributes)) =
+        (limits, pooling, policy, cache, guest, attributes)
+    else {
+        return Err(errors.into());
+    };
+    errors.into_result()?;
+    let cache = Cache::new(&cache);
+
+    if pooling.enabled() {
+        tracing::info!("using pooling instance allocator");
+    }
+    let engine = engine(&limits, &pooling)?;
 
-    // register services with runtime's Linker
-    let mut linker = Linker::new(&engine);
+    // cause executing WebAssembly to periodically yield
+    limits::start_epoch_ticker(&engine)?;
+
+    let mut compiled = Vec::with_capacity(wasm.len());
+    for ((path, name), attributes) in wasm.iter().zip(names).zip(attributes) {
+        let (component, core_instances) = load_component(&engine, path, &limits, &cache)
+            .with_context(|| format!("loading {}", path.display()))?;
+
+        // register services with component's Linker
+        let linker = wasi_linker(&engine)?;
+
+        compiled.push(Compiled {
+            routes: Routes::from_env(&name),
+            attributes,
+            capabilities: policy
+                .as_ref()
+                .map_or_else(Capabilities::unrestricted, |policy| policy.capabilities(&name)),
+            name,
+            component,
+            core_instances,
+            linker,
+            limits: limits.clone(),
+            guest: guest.clone(),
+            cache: cache.clone(),
+        });
+    }
+
+    tracing::info!("runtime initialized");
+
+    Ok(compiled)
+}
+
+/// Load a component and create a `Linker` for it without initializing the
+/// runtime, e.g. to inspect the component.
+///
+/// Unlike [`create_all`], telemetry is not started, no epoch ticker is
+/// started and the compilation cache is not used. The engine is configured with the
+/// runtime's limits and pooling settings so pre-compiled components are
+/// loaded as the runtime would load them.
+///
+/// # Errors
+///
+/// Will fail if the runtime's settings are invalid, the `wasm` file cannot be
+/// compiled/deserialized as a `Component`, or the `Linker` cannot be
+/// initialized with WASI support.
+pub fn load<T: WasiView + 'static>(wasm: &Path) -> Result<Compiled<T>> {
+    let limits = <Limits as FromEnv>::from_env()?;
+    let pooling = <Pooling as FromEnv>::from_env()?;
+    let engine = engine(&limits, &pooling)?;
+    let cache = Cache::disabled();
+    let (component, core_instances) = load_component(&engine, wasm, &limits, &cache)
+        .with_context(|| format!("loading {}", wasm.display()))?;
+
+    let linker = wasi_linker(&engine)?;
+
+    let name = component_name(wasm);
+    Ok(Compiled {
+        routes: Routes::default(),
+        attributes: Attributes::default(),
+        capabilities: Capabilities::unrestricted(),
+        name,
+        component,
+        core_instances,
+        linker,
+        limits,
+        guest: Guest::default(),
+        cache,
+    })
+}
+
+// A `Linker` providing WASI.
+fn wasi_linker<T: WasiView + 'static>(engine: &Engine) -> Result<Linker<T>> {
+    let mut linker = Linker::new(engine);
     wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
     wasmtime_wasi::p3::add_to_linker(&mut linker)?;
+    Ok(linker)
+}
 
-    tracing::info!("runtime initialized");
+/// Load a pre-compiled component, or compile a wasm32 component when the
+/// `jit` feature is enabled, reusing the compilation cache.
+///
+/// Pre-compiled components are checked against the engine settings recorded
+/// when they were compiled (if any) so incompatibilities are reported
+/// clearly.
+///
+/// When an instance limit is configured, the number of core instances the
+/// component creates is returned with it (if known) to identify
+/// instantiations failing on the limit.
+fn load_component(
+    engine: &Engine, wasm: &Path, limits: &Limits, cache: &Cache,
+) -> Result<(Component, Option<usize>)> {
+    // files too small to be pre-compiled fail detection, so are treated as wasm
+    match Engine::detect_precompiled_file(wasm).ok().flatten() {
+        Some(Precompiled::Component) => {
+            let runtime = EngineSettings::runtime(limits);
+            let compiled = EngineSettings::read(wasm)?;
+            if let Some(compiled) = &compiled {
+                compiled
+                    .check_compatible(&runtime)
+                    .context("component is incompatible with this runtime")?;
+            }
 
-    Ok(Compiled { component, linker })
+            // SAFETY: The caller should ensure only valid pre-compiled wasm files are provided.
+            let component = unsafe { Component::deserialize_file(engine, wasm) }.map_err(|e| {
+                anyhow::anyhow!(
+                    "component is incompatible with this runtime ({runtime}): {e:#}; \
+                     recompile it with `compile`"
+                )
+            })?;
+            let core_instances = compiled.and_then(|compiled| compiled.core_instances);
+            Ok((component, core_instances.filter(|_| limits.max_instances.is_some())))
+        }
+        Some(Precompiled::Module) => {
+            bail!("pre-compiled core modules are not supported; compile a component instead")
+        }
+        None if cfg!(feature = "jit") => {
+            let component = cache.load(engine, wasm)?;
+            let core_instances = match limits.max_instances {
+                Some(_) => limits::core_instances(&fs::read(wasm)?),
+                None => None,
+            };
+            Ok((component, core_instances))
+        }
+        None => {
+            bail!("not a pre-compiled component; enable the `jit` feature to load wasm32 files")
+        }
+    }
+}
+
+/// Create the `Engine` used by the runtime.
+///
+/// # Errors
+///
+/// Will fail if the engine configuration is invalid.
+pub fn engine(limits: &Limits, pooling: &Pooling) -> Result<Engine> {
+    let mut config = config(limits);
+    pooling.configure(&mut config, limits);
+    Ok(Engine::new(&config)?)
+}
+
+/// The `wasmtime` configuration shared by the runtime and the compiler.
+///
+/// Pre-compiled components must be compiled with the same configuration as
+/// the runtime that loads them. The runtime's [`EngineSettings`] are
+/// applied, and may be overridden by the compiler.
+pub fn config(limits: &Limits) -> Config {
+    let mut config = Config::new();
+    config.wasm_component_model_async(true);
+    config.epoch_interruption(true);
+    config.consume_fuel(limits.fuel.is_some());
+    EngineSettings::runtime(limits).apply(&mut config);
+    config
 }
 
 /// A compiled WebAssembly component with its associated Linker.
 pub struct Compiled<T: WasiView + 'static> {
+    name: String,
+    routes: Routes,
+    attributes: Attributes,
+    capabilities: Capabilities,
     component: Component,
+    core_instances: Option<usize>,
     linker: Linker<T>,
+    limits: Limits,
+    guest: Guest,
+    cache: Cache,
 }
 
 impl<T: WasiView> Compiled<T> {
@@ -78,29 +291,105 @@ impl<T: WasiView> Compiled<T> {
     pub fn pre_instantiate(&mut self) -> Result<InstancePre<T>> {
         self.linker.instantiate_pre(&self.component).map_err(anyhow::Error::from)
     }
+
+    /// Reload the component from the specified path and pre-instantiate it
+    /// using the existing Linker.
+    ///
+    /// # Errors
+    ///
+    /// Will fail if the component cannot be loaded or pre-instantiated.
+    pub fn reload(&mut self, wasm: &Path) -> Result<InstancePre<T>> {
+        let (component, core_instances) =
+            load_component(self.linker.engine(), wasm, &self.limits, &self.cache)?;
+        let instance_pre = self.linker.instantiate_pre(&component)?;
+        self.component = component;
+        self.core_instances = core_instances;
+        Ok(instance_pre)
+    }
+
+    /// Inspect the component against the runtime's linker, flagging imports
+    /// not provided by the named `hosts`.
+    #[must_use]
+    pub fn inspect(&self, hosts: &[&str]) -> Inspection {
+        Inspection::new(&self.component, &self.linker, hosts)
+    }
+
+    /// The component's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Routes used to select the component for incoming work.
+    #[must_use]
+    pub const fn routes(&self) -> &Routes {
+        &self.routes
+    }
+
+    /// Telemetry attributes recorded on the component's invocation spans.
+    #[must_use]
+    pub const fn attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
+    /// Resources the component may access.
+    #[must_use]
+    pub const fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// The number of core instances created by instantiating the component,
+    /// when known and an instance limit is configured.
+    #[must_use]
+    pub const fn core_instances(&self) -> Option<usize> {
+        self.core_instances
+    }
+
+    /// Execution limits to apply to each guest invocation.
+    #[must_use]
+    pub const fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// The WASI context given to each guest invocation.
+    #[must_use]
+    pub const fn guest(&self) -> &Guest {
+        &self.guest
+    }
 }
 
+// Whether telemetry has been initialized.
+static TELEMETRY: AtomicBool = AtomicBool::new(false);
+
 /// Initialize telemetry for the runtime.
 ///
 /// # Errors
 ///
 /// Will fail if the telemetry cannot be initialized.
-fn init_env(wasm: &Path) -> Result<()> {
-    let name = wasm.file_stem().and_then(|s| s.to_str()).unwrap_or("unknown");
+fn init_env(names: &[String]) -> Result<()> {
+    let name = match names {
+        [name] => name.clone(),
+        _ => env::var("COMPONENT").unwrap_or_else(|_| "omnia".into()),
+    };
 
-    if env::var("COMPONENT").is_err() {
-        // SAFETY: Environment variable modification is safe here because:
-        // 1. This runs during single-threaded initialization
-        // 2. Backend clients that depend on these vars are created after this
-        unsafe {
-            env::set_var("COMPONENT", name);
-        };
+    // telemetry is process-wide, so is initialized by the first runtime
+    // created, e.g. when a test process creates several
+    if TELEMETRY.swap(true, Ordering::AcqRel) {
+        return Ok(());
     }
 
-    // telemetry
-    let mut builder = Telemetry::new(name);
+    let mut builder = Telemetry::new(name).attribute("omnia.components", names.join(","));
     if let Ok(endpoint) = env::var("OTEL_GRPC_URL") {
         builder = builder.endpoint(endpoint);
     }
-    builder.build().context("initializing telemetry")
+    builder.build().context("initializing telemetry").inspect_err(|_| {
+        TELEMETRY.store(false, Ordering::Release);
+    })?;
+    Ok(())
+}
+
+/// The name of the component loaded from `wasm`, its file stem.
+#[must_use]
+pub fn component_name(wasm: &Path) -> String {
+    wasm.file_stem().and_then(|s| s.to_str()).unwrap_or("unknown").to_string()
 }
@@ -0,0 +1,420 @@
+//! # Guest WASI Context
+//!
+//! Controls what the guest sees of the host through WASI: its arguments,
+//! environment variables, filesystem and standard output.
+//!
+//! By default, guests receive no arguments, no environment variables and no
+//! filesystem access, and their stdout and stderr are emitted as `tracing`
+//! events tagged with the component name (and the request, via the span the
+//! invocation runs in). Access is granted using:
+//!
+//! - `GUEST_ARGS`: comma-separated arguments passed to the guest.
+//! - `GUEST_ENV_ALLOW`: comma-separated environment variables the guest may
+//!   read. A trailing `*` allows every variable with the preceding prefix.
+//! - `GUEST_PREOPEN`: comma-separated directories to make available to the
+//!   guest, as `host_path[:guest_path][:ro|rw]`. Directories are read-only
+//!   unless `rw` is specified, and are mounted at `host_path` unless a
+//!   `guest_path` is specified.
+//! - `GUEST_STDIO`: `tracing` (default) to emit guest output as events,
+//!   `inherit` to share the host's stdio, or `null` to discard it.
+
+#![allow(missing_docs)]
+
+use std::env;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+
+use anyhow::{Context, Result, bail};
+use fromenv::FromEnv;
+use tokio::io::AsyncWrite;
+use tracing::Span;
+use wasmtime_wasi::cli::{IsTerminal, StdoutStream};
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtx, WasiCtxBuilder};
+
+use crate::traits;
+
+/// The longest line buffered before guest output is emitted.
+const MAX_LINE: usize = 8 * 1024;
+
+/// Guest WASI context configuration.
+#[derive(Debug, Clone, Default, FromEnv)]
+pub struct GuestOptions {
+    /// Comma-separated arguments passed to the guest.
+    #[env(from = "GUEST_ARGS", default = "")]
+    pub args: String,
+
+    /// Comma-separated environment variables (or `*`-suffixed prefixes) the
+    /// guest may read.
+    #[env(from = "GUEST_ENV_ALLOW", default = "")]
+    pub env_allow: String,
+
+    /// Comma-separated directories to preopen, as
+    /// `host_path[:guest_path][:ro|rw]`.
+    #[env(from = "GUEST_PREOPEN", default = "")]
+    pub preopen: String,
+
+    /// Where guest stdout and stderr are sent.
+    #[env(from = "GUEST_STDIO", default = "tracing", with = parse_stdio)]
+    pub stdio: Stdio,
+}
+
+impl traits::FromEnv for GuestOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading guest options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        traits::requirement_vars(&Self::requirements())
+    }
+}
+
+fn parse_stdio(s: &str) -> fromenv::ParseResult<Stdio> {
+    Ok(s.parse()?)
+}
+
+/// Where guest stdout and stderr are sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Stdio {
+    /// Emit each line of output as a `tracing` event.
+    #[default]
+    Tracing,
+
+    /// Share the host's stdin, stdout and stderr.
+    Inherit,
+
+    /// Discard output.
+    Null,
+}
+
+impl FromStr for Stdio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tracing" => Ok(Self::Tracing),
+            "inherit" => Ok(Self::Inherit),
+            "null" => Ok(Self::Null),
+            _ => Err(format!("expected `tracing`, `inherit` or `null`, found `{s}`")),
+        }
+    }
+}
+
+/// A directory made available to the guest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preopen {
+    /// The directory on the host.
+    pub host_path: PathBuf,
+
+    /// The path the guest sees the directory at.
+    pub guest_path: String,
+
+    /// Whether the guest may modify the directory.
+    pub writable: bool,
+}
+
+impl FromStr for Preopen {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':').collect::<Vec<_>>();
+        let writable = match parts.last() {
+            Some(&"rw") => true,
+            Some(&"ro") => false,
+            _ => {
+                parts.push("ro");
+                false
+            }
+        };
+        let (host_path, guest_path) = match parts[..] {
+            [host, _] => (host, host),
+            [host, guest, _] => (host, guest),
+            _ => bail!("invalid preopen `{s}`: expected `host_path[:guest_path][:ro|rw]`"),
+        };
+        if host_path.is_empty() || guest_path.is_empty() {
+            bail!("invalid preopen `{s}`: paths cannot be empty");
+        }
+
+        Ok(Self {
+            host_path: PathBuf::from(host_path),
+            guest_path: guest_path.to_string(),
+            writable,
+        })
+    }
+}
+
+impl Display for Preopen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.writable { "rw" } else { "ro" };
+        write!(f, "{}:{}:{mode}", self.host_path.display(), self.guest_path)
+    }
+}
+
+/// The WASI context given to each guest invocation.
+#[derive(Debug, Clone, Default)]
+pub struct Guest(Arc<GuestInner>);
+
+#[derive(Debug, Default)]
+struct GuestInner {
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    preopens: Vec<Preopen>,
+    stdio: Stdio,
+}
+
+impl Guest {
+    /// Create a guest context from the specified options, resolving allowed
+    /// environment variables from the host environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a preopen is invalid or its directory does not
+    /// exist.
+    pub fn new(options: &GuestOptions) -> Result<Self> {
+        let preopens = list(&options.preopen).map(str::parse).collect::<Result<Vec<Preopen>>>()?;
+        for preopen in &preopens {
+            if !preopen.host_path.is_dir() {
+                bail!("preopened directory {} does not exist", preopen.host_path.display());
+            }
+        }
+
+        let allow = list(&options.env_allow).collect::<Vec<_>>();
+        let mut env = env::vars().filter(|(name, _)| allowed(&allow, name)).collect::<Vec<_>>();
+        env.sort();
+
+        Ok(Self(Arc::new(GuestInner {
+            args: list(&options.args).map(ToString::to_string).collect(),
+            env,
+            preopens,
+            stdio: options.stdio,
+        })))
+    }
+
+    /// Environment variables visible to the guest.
+    #[must_use]
+    pub fn env(&self) -> &[(String, String)] {
+        &self.0.env
+    }
+
+    /// Directories available to the guest.
+    #[must_use]
+    pub fn preopens(&self) -> &[Preopen] {
+        &self.0.preopens
+    }
+
+    /// Build the WASI context for an invocation of the named component.
+    ///
+    /// Guest output is emitted as events within the current span, so this
+    /// should be called from within the invocation's span.
+    #[must_use]
+    pub fn wasi_ctx(&self, component: &str) -> WasiCtx {
+        let mut builder = WasiCtxBuilder::new();
+        builder.args(&self.0.args).envs(&self.0.env);
+
+        for preopen in &self.0.preopens {
+            let (dir_perms, file_perms) = if preopen.writable {
+                (DirPerms::all(), FilePerms::all())
+            } else {
+                (DirPerms::READ, FilePerms::READ)
+            };
+            if let Err(e) = builder.preopened_dir(
+                &preopen.host_path,
+                &preopen.guest_path,
+                dir_perms,
+                file_perms,
+            ) {
+                tracing::warn!("issue preopening {preopen}: {e}");
+            }
+        }
+
+        match self.0.stdio {
+            Stdio::Tracing => {
+                let component: Arc<str> = Arc::from(component);
+                builder
+                    .stdout(GuestOutput::new(Arc::clone(&component), Stream::Stdout))
+                    .stderr(GuestOutput::new(component, Stream::Stderr));
+            }
+            Stdio::Inherit => {
+                builder.inherit_stdin().stdout(tokio::io::stdout()).stderr(tokio::io::stderr());
+            }
+            Stdio::Null => {}
+        }
+
+        builder.build()
+    }
+}
+
+// Split a comma-separated list, ignoring empty items.
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+fn allowed(allow: &[&str], name: &str) -> bool {
+    allow.iter().any(|pattern| {
+        pattern.strip_suffix('*').map_or(*pattern == name, |prefix| name.starts_with(prefix))
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Guest stdout or stderr, emitted as `tracing` events.
+struct GuestOutput {
+    component: Arc<str>,
+    stream: Stream,
+    span: Span,
+}
+
+impl GuestOutput {
+    fn new(component: Arc<str>, stream: Stream) -> Self {
+        Self {
+            component,
+            stream,
+            span: Span::current(),
+        }
+    }
+}
+
+impl IsTerminal for GuestOutput {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdoutStream for GuestOutput {
+    fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+        Box::new(LineWriter {
+            component: Arc::clone(&self.component),
+            stream: self.stream,
+            span: self.span.clone(),
+            buf: Vec::new(),
+        })
+    }
+}
+
+/// Buffers guest output, emitting an event for each complete line.
+struct LineWriter {
+    component: Arc<str>,
+    stream: Stream,
+    span: Span,
+    buf: Vec<u8>,
+}
+
+impl LineWriter {
+    fn emit(&self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        let component = &*self.component;
+        match self.stream {
+            Stream::Stdout => {
+                tracing::info!(parent: &self.span, component, stream = "stdout", "{line}");
+            }
+            Stream::Stderr => {
+                tracing::warn!(parent: &self.span, component, stream = "stderr", "{line}");
+            }
+        }
+    }
+
+    fn emit_lines(&mut self) {
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let line = self.buf.drain(..=end).collect::<Vec<_>>();
+            self.emit(&line);
+        }
+        if self.buf.len() >= MAX_LINE {
+            let line = std::mem::take(&mut self.buf);
+            self.emit(&line);
+        }
+    }
+
+    fn emit_rest(&mut self) {
+        if !self.buf.is_empty() {
+            let line = std::mem::take(&mut self.buf);
+            self.emit(&line);
+        }
+    }
+}
+
+impl AsyncWrite for LineWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>, _: &mut TaskContext<'_>, buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.buf.extend_from_slice(buf);
+        self.emit_lines();
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>, _: &mut TaskContext<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.emit_rest();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for LineWriter {
+    fn drop(&mut self) {
+        self.emit_rest();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preopens() {
+        assert_eq!(
+            "/data".parse::<Preopen>().expect("should parse"),
+            Preopen {
+                host_path: "/data".into(),
+                guest_path: "/data".into(),
+                writable: false,
+            }
+        );
+        assert_eq!(
+            "./tmp:/scratch:rw".parse::<Preopen>().expect("should parse"),
+            Preopen {
+                host_path: "./tmp".into(),
+                guest_path: "/scratch".into(),
+                writable: true,
+            }
+        );
+        assert_eq!("/data:/mnt".parse::<Preopen>().expect("should parse").guest_path, "/mnt");
+        "/a:/b:/c:rw".parse::<Preopen>().expect_err("too many paths");
+        ":rw".parse::<Preopen>().expect_err("empty path");
+    }
+
+    #[test]
+    fn env_allow_list() {
+        let allow = ["RUST_LOG", "APP_*"];
+        assert!(allowed(&allow, "RUST_LOG"));
+        assert!(allowed(&allow, "APP_NAME"));
+        assert!(!allowed(&allow, "AWS_SECRET_ACCESS_KEY"));
+        assert!(!allowed(&[], "PATH"));
+    }
+
+    #[test]
+    fn missing_preopen() {
+        let options = GuestOptions {
+            preopen: "/does/not/exist".into(),
+            ..GuestOptions::default()
+        };
+        Guest::new(&options).expect_err("should require directory");
+    }
+
+    #[test]
+    fn stdio_modes() {
+        assert_eq!("inherit".parse(), Ok(Stdio::Inherit));
+        "console".parse::<Stdio>().expect_err("should reject");
+    }
+}
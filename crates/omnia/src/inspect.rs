@@ -0,0 +1,273 @@
+//! # Component Inspection
+//!
+//! Lists a component's imported and exported interfaces and the omnia hosts
+//! needed to satisfy its imports and serve its exports (e.g. `WasiHttp` for
+//! a component exporting `wasi:http/handler`).
+
+use std::fmt::{self, Display};
+
+use wasmtime::Engine;
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, Linker};
+
+/// WASI packages linked by every runtime.
+const BUILTIN_PACKAGES: [&str; 6] =
+    ["wasi:cli", "wasi:clocks", "wasi:filesystem", "wasi:io", "wasi:random", "wasi:sockets"];
+
+/// Host types providing each interface package.
+const HOST_PACKAGES: [(&str, &str); 12] = [
+    ("omnia:cron", "WasiCron"),
+    ("omnia:identity", "WasiIdentity"),
+    ("omnia:otel", "WasiOtel"),
+    ("omnia:vault", "WasiVault"),
+    ("omnia:websocket", "WasiWebSocket"),
+    ("wasi:blobstore", "WasiBlobstore"),
+    ("wasi:config", "WasiConfig"),
+    ("wasi:http", "WasiHttp"),
+    ("wasi:jsondb", "WasiJsonDb"),
+    ("wasi:keyvalue", "WasiKeyValue"),
+    ("wasi:messaging", "WasiMessaging"),
+    ("wasi:sql", "WasiSql"),
+];
+
+/// Where an import is provided from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Provider {
+    /// Linked by every runtime.
+    Builtin,
+
+    /// Provided by the named omnia host.
+    Host(&'static str),
+
+    /// Not provided by omnia.
+    Unknown,
+}
+
+impl Provider {
+    /// Returns the provider of the named import, or the host serving the
+    /// named export.
+    #[must_use]
+    pub fn of(import: &str) -> Self {
+        // strip interface and version, e.g. `wasi:keyvalue/store@0.2.0` -> `wasi:keyvalue`
+        let package = import.split(['/', '@']).next().unwrap_or(import);
+        if BUILTIN_PACKAGES.contains(&package) {
+            return Self::Builtin;
+        }
+        HOST_PACKAGES
+            .iter()
+            .find_map(|(name, host)| (*name == package).then_some(Self::Host(host)))
+            .unwrap_or(Self::Unknown)
+    }
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Builtin => f.write_str("built-in"),
+            Self::Host(host) => f.write_str(host),
+            Self::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+/// An imported item.
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// The import name (typically a WIT interface).
+    pub name: String,
+
+    /// The provider of the import.
+    pub provider: Provider,
+
+    /// Whether the runtime is able to link the import.
+    pub linked: bool,
+}
+
+/// The interfaces imported and exported by a component.
+#[derive(Debug, Clone)]
+pub struct Inspection {
+    /// Imported items.
+    pub imports: Vec<Import>,
+
+    /// Exported item names.
+    pub exports: Vec<String>,
+
+    /// The error linking the component, if any.
+    pub link_error: Option<String>,
+}
+
+impl Inspection {
+    /// Inspect a component against a runtime built with the named `hosts`
+    /// and the `linker` they have been linked to.
+    #[must_use]
+    pub fn new<T>(component: &Component, linker: &Linker<T>, hosts: &[&str]) -> Self {
+        let engine = linker.engine();
+        let imports = items(engine, component, true)
+            .into_iter()
+            .map(|name| {
+                let provider = Provider::of(&name);
+                Import {
+                    linked: match &provider {
+                        Provider::Builtin => true,
+                        Provider::Host(host) => hosts.contains(host),
+                        Provider::Unknown => false,
+                    },
+                    name,
+                    provider,
+                }
+            })
+            .collect();
+
+        Self {
+            imports,
+            exports: items(engine, component, false),
+            link_error: linker.instantiate_pre(component).err().map(|e| format!("{e:#}")),
+        }
+    }
+
+    /// Omnia hosts needed to satisfy the component's imports and to call its
+    /// exports, such as the `wasi:http` handler.
+    #[must_use]
+    pub fn required_hosts(&self) -> Vec<&'static str> {
+        let imports = self.imports.iter().map(|import| import.provider.clone());
+        let exports = self.exports.iter().map(|export| Provider::of(export));
+        let mut hosts = imports
+            .chain(exports)
+            .filter_map(|provider| match provider {
+                Provider::Host(host) => Some(host),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        hosts.sort_unstable();
+        hosts.dedup();
+        hosts
+    }
+
+    /// Imports the runtime cannot link.
+    pub fn unlinked(&self) -> impl Iterator<Item = &Import> {
+        self.imports.iter().filter(|import| !import.linked)
+    }
+}
+
+impl Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.imports.iter().map(|import| import.name.len()).max().unwrap_or_default();
+
+        writeln!(f, "imports:")?;
+        for import in &self.imports {
+            let flag = if import.linked { "" } else { "  (not linked)" };
+            writeln!(f, "  {:width$}  {}{flag}", import.name, import.provider)?;
+        }
+
+        writeln!(f, "\nexports:")?;
+        for export in &self.exports {
+            writeln!(f, "  {export}")?;
+        }
+
+        writeln!(f, "\nrequired hosts: {}", self.required_hosts().join(", "))?;
+        match &self.link_error {
+            None => write!(f, "link check: ok"),
+            Some(e) => write!(f, "link check: failed: {e}"),
+        }
+    }
+}
+
+// Names of the component's imports or exports, with non-instance items
+// annotated with their kind.
+fn items(engine: &Engine, component: &Component, imports: bool) -> Vec<String> {
+    let ty = component.component_type();
+    let describe = |(name, item): (&str, ComponentItem)| match item {
+        ComponentItem::ComponentInstance(_) => name.to_string(),
+        ComponentItem::ComponentFunc(_) | ComponentItem::CoreFunc(_) => format!("{name} (func)"),
+        ComponentItem::Module(_) => format!("{name} (module)"),
+        ComponentItem::Component(_) => format!("{name} (component)"),
+        ComponentItem::Type(_) => format!("{name} (type)"),
+        ComponentItem::Resource(_) => format!("{name} (resource)"),
+    };
+    if imports {
+        ty.imports(engine).map(describe).collect()
+    } else {
+        ty.exports(engine).map(describe).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::Engine;
+
+    use super::*;
+
+    // (component
+    //   (import "wasi:keyvalue/store@0.2.0-draft2" (instance))
+    //   (import "acme:billing/invoices" (instance))
+    //   (import "wasi:cli/environment@0.2.0" (instance))
+    //   (export "wasi:http/handler@0.3.0" (instance 0)))
+    fn component(engine: &Engine) -> Component {
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+
+        // type section: a single empty instance type
+        bytes.extend([0x07, 0x03, 0x01, 0x42, 0x00]);
+
+        // import section
+        let imports = [
+            "wasi:keyvalue/store@0.2.0-draft2",
+            "acme:billing/invoices",
+            "wasi:cli/environment@0.2.0",
+        ];
+        let mut section = vec![u8::try_from(imports.len()).unwrap()];
+        for name in imports {
+            section.extend([0x00, u8::try_from(name.len()).unwrap()]);
+            section.extend(name.as_bytes());
+            section.extend([0x05, 0x00]); // instance of type 0
+        }
+        bytes.push(0x0a);
+        bytes.push(u8::try_from(section.len()).unwrap());
+        bytes.extend(section);
+
+        // export section
+        let name = "wasi:http/handler@0.3.0";
+        let mut section = vec![0x01, 0x00, u8::try_from(name.len()).unwrap()];
+        section.extend(name.as_bytes());
+        section.extend([0x05, 0x00, 0x00]); // instance 0, no type ascription
+        bytes.push(0x0b);
+        bytes.push(u8::try_from(section.len()).unwrap());
+        bytes.extend(section);
+
+        Component::new(engine, &bytes).expect("should compile component")
+    }
+
+    #[test]
+    fn providers() {
+        assert_eq!(Provider::of("wasi:sql/readwrite@0.2.0-draft"), Provider::Host("WasiSql"));
+        assert_eq!(Provider::of("omnia:vault/vault@0.1.0"), Provider::Host("WasiVault"));
+        assert_eq!(Provider::of("omnia:cron/handler@0.1.0"), Provider::Host("WasiCron"));
+        assert_eq!(Provider::of("wasi:io/streams@0.2.6"), Provider::Builtin);
+        assert_eq!(Provider::of("acme:billing/invoices"), Provider::Unknown);
+    }
+
+    #[test]
+    fn inspects_component() {
+        let engine = Engine::default();
+        let linker = Linker::<()>::new(&engine);
+        let inspection = Inspection::new(&component(&engine), &linker, &["WasiHttp"]);
+
+        let imports = inspection.imports.iter().map(|i| i.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            imports,
+            [
+                "wasi:keyvalue/store@0.2.0-draft2",
+                "acme:billing/invoices",
+                "wasi:cli/environment@0.2.0"
+            ]
+        );
+        assert_eq!(inspection.exports, ["wasi:http/handler@0.3.0"]);
+        // the exported handler is called by the `wasi:http` host
+        assert_eq!(inspection.required_hosts(), ["WasiHttp", "WasiKeyValue"]);
+
+        let unlinked = inspection.unlinked().map(|i| i.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(unlinked, ["wasi:keyvalue/store@0.2.0-draft2", "acme:billing/invoices"]);
+
+        // empty instance types need nothing from the linker
+        assert_eq!(inspection.link_error, None);
+    }
+}
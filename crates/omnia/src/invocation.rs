@@ -0,0 +1,174 @@
+//! # Invocation Context
+//!
+//! Each guest invocation handles an inbound request or message. Servers
+//! describe it with an [`Invocation`] stored in the invocation's store, so
+//! hosts can correlate the backend calls they make on the guest's behalf with
+//! the inbound request.
+//!
+//! The invocation's span carries its request ID and tenant, and continues the
+//! inbound request's trace when it carried a W3C `traceparent`. Servers run
+//! the guest within the span, so host calls, and the backend calls they make,
+//! are traced as its children.
+//!
+//! Each component's invocation spans also carry the component's own
+//! [`Attributes`], configured with `{COMPONENT}_OTEL_ATTRIBUTES` as
+//! comma-separated `key=value` pairs (e.g. `ORDER_SKILL_OTEL_ATTRIBUTES=
+//! team=orders,tier=gold`).
+
+use std::env;
+use std::future::Future;
+
+use anyhow::{Result, bail};
+use tracing::field::Empty;
+use tracing::instrument::Instrumented;
+use tracing::{Instrument, Span, info_span};
+
+/// The header (or message metadata key) identifying an inbound request.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The header (or message metadata key) naming the tenant an inbound request
+/// is made on behalf of.
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// The header (or message metadata key) carrying an inbound request's W3C
+/// trace context.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// The context of a single guest invocation.
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    /// Identifies the inbound request or message.
+    pub request_id: String,
+
+    /// The component handling the invocation.
+    pub component: String,
+
+    /// The tenant the inbound request is made on behalf of, if known.
+    pub tenant: Option<String>,
+
+    /// The inbound request's W3C trace context, if any.
+    pub traceparent: Option<String>,
+
+    /// The invocation's span.
+    pub span: Span,
+}
+
+impl Invocation {
+    /// Describe an invocation of `component`, reading the request ID, tenant
+    /// and trace context of the inbound request's headers (or message's
+    /// metadata) using `lookup`.
+    ///
+    /// A request ID is generated when the inbound request has none.
+    #[must_use]
+    pub fn new(component: &str, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let request_id = lookup(REQUEST_ID_HEADER)
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+        let tenant = lookup(TENANT_HEADER).filter(|tenant| !tenant.is_empty());
+        let traceparent = lookup(TRACEPARENT_HEADER);
+
+        let span =
+            info_span!("guest", service = %component, request_id = %request_id, tenant = Empty);
+        if let Some(tenant) = &tenant {
+            span.record("tenant", tenant.as_str());
+        }
+        if let Some(traceparent) = &traceparent {
+            omnia_otel::set_remote_parent(&span, traceparent);
+        }
+
+        Self {
+            request_id,
+            component: component.to_string(),
+            tenant,
+            traceparent,
+            span,
+        }
+    }
+
+    /// Describe an invocation of `component` not made on behalf of an inbound
+    /// request, e.g. a scheduled run.
+    #[must_use]
+    pub fn background(component: &str) -> Self {
+        Self::new(component, |_| None)
+    }
+
+    /// Run `future` within the invocation's span.
+    pub fn instrument<F: Future>(&self, future: F) -> Instrumented<F> {
+        future.instrument(self.span.clone())
+    }
+}
+
+/// Telemetry attributes recorded on each of a component's invocation spans.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes(Vec<(String, String)>);
+
+impl Attributes {
+    /// Load the named component's attributes from
+    /// `{COMPONENT}_OTEL_ATTRIBUTES`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an attribute is not a `key=value` pair.
+    pub fn from_env(component: &str) -> Result<Self> {
+        let var = format!("{}_OTEL_ATTRIBUTES", crate::env_prefix(component));
+        env::var(&var).map_or_else(|_| Ok(Self::default()), |value| Self::parse(&var, &value))
+    }
+
+    fn parse(var: &str, value: &str) -> Result<Self> {
+        let mut attributes = Vec::new();
+        for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let Some((key, value)) = pair.split_once('=') else {
+                bail!("`{var}`: expected `key=value`, found `{pair}`");
+            };
+            attributes.push((key.trim().to_string(), value.trim().to_string()));
+        }
+        Ok(Self(attributes))
+    }
+
+    /// Record the attributes on an invocation's span.
+    pub fn record(&self, invocation: &Invocation) {
+        omnia_otel::set_attributes(
+            &invocation.span,
+            self.0.iter().map(|(key, value)| (key.as_str(), value.as_str())),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn from_headers() {
+        let headers = HashMap::from([
+            (REQUEST_ID_HEADER, "req-1".to_string()),
+            (TENANT_HEADER, "acme".to_string()),
+        ]);
+        let invocation = Invocation::new("orders", |name| headers.get(name).cloned());
+        assert_eq!(invocation.request_id, "req-1");
+        assert_eq!(invocation.component, "orders");
+        assert_eq!(invocation.tenant.as_deref(), Some("acme"));
+        assert_eq!(invocation.traceparent, None);
+    }
+
+    #[test]
+    fn generates_request_id() {
+        let invocation = Invocation::background("orders");
+        assert_eq!(invocation.request_id.len(), 16);
+        assert_eq!(invocation.tenant, None);
+        assert_ne!(invocation.request_id, Invocation::background("orders").request_id);
+    }
+
+    #[test]
+    fn attributes() {
+        let attributes = Attributes::parse("ORDERS_OTEL_ATTRIBUTES", "team=orders, tier = gold,")
+            .expect("parse");
+        assert_eq!(
+            attributes,
+            Attributes(vec![("team".into(), "orders".into()), ("tier".into(), "gold".into())])
+        );
+        Attributes::parse("ORDERS_OTEL_ATTRIBUTES", "team").expect_err("should require value");
+    }
+}
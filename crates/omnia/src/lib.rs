@@ -1,9 +1,24 @@
 #![doc = include_str!("../README.md")]
 #![cfg(not(target_arch = "wasm32"))]
 
+mod admin;
+mod backends;
+mod cache;
 #[cfg(feature = "jit")]
 mod compile;
+mod concurrency;
+mod config;
 mod create;
+mod guest;
+mod inspect;
+mod invocation;
+mod limits;
+mod policy;
+mod pool;
+mod reload;
+mod routing;
+mod settings;
+mod shutdown;
 mod traits;
 
 use std::path::PathBuf;
@@ -15,9 +30,29 @@ pub use omnia_runtime_macro::runtime;
 pub use {anyhow, futures, tokio, wasmtime, wasmtime_wasi};
 
 // re-export internal modules
+pub use self::admin::{AdminOptions, Health, Readiness, RuntimeInfo, serve_admin};
+pub use self::backends::Backends;
+pub use self::cache::{Cache, CacheOptions};
 #[cfg(feature = "jit")]
 pub use self::compile::*;
-pub use self::create::*;
+pub use self::concurrency::{Concurrency, ConcurrencyOptions, Permit};
+pub use self::config::{ConfigErrors, RuntimeConfig, runtime_vars};
+pub use self::create::{Compiled, component_name, create, create_all, create_all_with, load};
+pub use self::guest::{Guest, GuestOptions, Preopen, Stdio};
+pub use self::inspect::{Import, Inspection, Provider};
+pub use self::invocation::{
+    Attributes, Invocation, REQUEST_ID_HEADER, TENANT_HEADER, TRACEPARENT_HEADER,
+};
+pub use self::limits::{
+    DeadlineExceeded, EPOCH_TICK, LimitExceeded, LimitedResource, Limiter, Limits, core_instances,
+    is_deadline_exceeded, is_fuel_exhausted, limit_exceeded,
+};
+pub use self::policy::{AccessDenied, Capabilities, Capability, Grants, Policy};
+pub use self::pool::Pooling;
+pub use self::reload::{ActiveComponent, ReloadOptions, watch, watch_with};
+pub use self::routing::{Routes, env_prefix};
+pub use self::settings::{EngineSettings, OptLevel, WasmFeatures, settings_path};
+pub use self::shutdown::{Shutdown, ShutdownOptions, ShutdownTrigger, flush_telemetry};
 pub use self::traits::*;
 
 /// Command line interface for omnia.
@@ -31,11 +66,25 @@ pub struct Cli {
 /// Subcommands for the omnia CLI.
 #[derive(Subcommand, PartialEq, Eq)]
 pub enum Command {
-    /// Run the specified wasm guest.
+    /// Run the specified wasm guest(s).
     Run {
-        /// The path to the wasm file to run. The file can either be a
+        /// The path(s) to the wasm file(s) to run. Each file can either be a
         /// serialized (pre-compiled) wasmtime `Component` or standard
-        /// WASI component
+        /// WASI component. Components are named after their file stem.
+        #[arg(required = true)]
+        wasm: Vec<PathBuf>,
+
+        /// An optional runtime configuration file. Settings in the file are
+        /// used for any environment variables that are not already set.
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+    },
+    /// List a component's imports and exports, and the hosts needed to
+    /// satisfy its imports.
+    Inspect {
+        /// The path to the wasm file to inspect. The file can either be a
+        /// serialized (pre-compiled) wasmtime `Component` or standard
+        /// WASI component.
         wasm: PathBuf,
     },
     /// Compile the specified wasm32-wasip2 component.
@@ -48,5 +97,39 @@ pub enum Command {
         /// will be written to the same location as the input file.
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Compile into the runtime's compilation cache instead of writing
+        /// a pre-compiled component, using the runtime's settings.
+        #[arg(long, conflicts_with_all = ["output", "target", "opt_level"])]
+        cache: bool,
+
+        /// Compilation options.
+        #[command(flatten)]
+        options: CompileOptions,
     },
 }
+
+impl Command {
+    /// Execute commands that do not need the runtime's hosts, such as
+    /// `compile`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails or requires the runtime.
+    pub fn execute(self) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "jit")]
+            Self::Compile {
+                wasm, cache: true, ..
+            } => warm_cache(&wasm).map(|_| ()),
+            #[cfg(feature = "jit")]
+            Self::Compile {
+                wasm,
+                output,
+                options,
+                ..
+            } => compile_with(&wasm, output, &options),
+            _ => anyhow::bail!("command must be run by the runtime"),
+        }
+    }
+}
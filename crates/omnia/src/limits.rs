@@ -0,0 +1,657 @@
+//! # Execution Limits
+//!
+//! Per-invocation limits applied to every guest [`Store`] created by the
+//! runtime.
+//!
+//! Guests are compiled with epoch interruption enabled. A background ticker
+//! increments the engine's epoch every [`EPOCH_TICK`] and executing guests
+//! check the epoch at function entries and loop headers. Each time a guest
+//! reaches its deadline it cooperatively yields to the async executor, so a
+//! guest stuck in a hot loop cannot pin a tokio worker. Guests that exceed
+//! their CPU budget, counted in ticks spent running rather than waiting to
+//! resume, are trapped with [`DeadlineExceeded`].
+//!
+//! Each store also carries a [`Limiter`] capping the linear memory, table
+//! elements and instances a guest may create. Guests that exceed a cap are
+//! trapped with [`LimitExceeded`]. `wasmtime` does not identify the instance
+//! limit when it fails an instantiation, so the limiter compares the limit
+//! with the number of core instances the component creates (see
+//! [`core_instances`]).
+//!
+//! Fuel metering can optionally be enabled to provide a deterministic budget
+//! based on the number of guest instructions executed rather than wall-clock
+//! time. Guests that exhaust their fuel are trapped with
+//! [`Trap::OutOfFuel`], identified using [`is_fuel_exhausted`].
+
+#![allow(missing_docs)]
+
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use fromenv::FromEnv;
+use wasmparser::{
+    ComponentAlias, ComponentExternalKind, ComponentInstance, ComponentOuterAliasKind,
+    ComponentTypeRef, Encoding, Instance, Parser, Payload,
+};
+use wasmtime::{DEFAULT_INSTANCE_LIMIT, Engine, ResourceLimiter, Store, Trap, UpdateDeadline};
+
+/// Interval at which the engine epoch is incremented.
+pub const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Limits applied to each guest invocation.
+#[derive(Debug, Clone, Default, FromEnv)]
+pub struct Limits {
+    /// The maximum CPU time (in milliseconds) a single guest invocation may
+    /// consume before it is trapped. When unset, guests yield periodically
+    /// but are never trapped.
+    #[env(from = "GUEST_CPU_BUDGET_MS")]
+    pub cpu_budget_ms: Option<u64>,
+
+    /// The maximum size (in bytes) of any linear memory created by a guest
+    /// invocation.
+    #[env(from = "GUEST_MAX_MEMORY_BYTES")]
+    pub max_memory_bytes: Option<usize>,
+
+    /// The maximum number of elements in any table created by a guest
+    /// invocation.
+    #[env(from = "GUEST_MAX_TABLE_ELEMENTS")]
+    pub max_table_elements: Option<usize>,
+
+    /// The maximum number of (core) instances a guest invocation may create.
+    /// A single component typically instantiates several core modules.
+    #[env(from = "GUEST_MAX_INSTANCES")]
+    pub max_instances: Option<usize>,
+
+    /// The amount of fuel available to each guest invocation. Setting this
+    /// enables fuel metering, where guests consume fuel for each instruction
+    /// executed and are trapped once it has been exhausted.
+    #[env(from = "GUEST_FUEL")]
+    pub fuel: Option<u64>,
+}
+
+impl crate::FromEnv for Limits {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading guest limits")
+    }
+
+    fn env_vars() -> Vec<String> {
+        crate::requirement_vars(&Self::requirements())
+    }
+}
+
+impl Limits {
+    /// Apply limits to a newly created guest store.
+    ///
+    /// The store's epoch deadline callback counts the ticks the guest has
+    /// spent executing, yielding on each tick and trapping with
+    /// [`DeadlineExceeded`] once the CPU budget has been consumed. When fuel
+    /// metering is enabled, the store is given its fuel budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fuel metering was not enabled for the store's
+    /// engine.
+    pub fn configure<T: 'static>(&self, store: &mut Store<T>) -> Result<()> {
+        if let Some(fuel) = self.fuel {
+            store.set_fuel(fuel)?;
+        }
+
+        let budget = self.cpu_budget_ms.map(|ms| ms.div_ceil(tick_millis()).max(1));
+        let mut ticks = 0_u64;
+        let resumed = Arc::new(Mutex::new(None::<Instant>));
+
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| {
+            // the deadline is set before yielding, so has usually passed by
+            // the time the guest resumes: only ticks spent running count
+            let at = resumed.lock().unwrap_or_else(PoisonError::into_inner).take();
+            if at.is_some_and(|at| at.elapsed() < EPOCH_TICK / 2) {
+                return Ok(UpdateDeadline::Continue(1));
+            }
+
+            ticks += 1;
+            if budget.is_some_and(|budget| ticks >= budget) {
+                return Err(DeadlineExceeded.into());
+            }
+            let resumed = Arc::clone(&resumed);
+            Ok(UpdateDeadline::YieldCustom(
+                1,
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    *resumed.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+                }),
+            ))
+        });
+
+        Ok(())
+    }
+
+    /// Returns the fuel consumed by a guest invocation, when fuel metering is
+    /// enabled.
+    #[must_use]
+    pub fn fuel_consumed<T>(&self, store: &Store<T>) -> Option<u64> {
+        let fuel = self.fuel?;
+        let remaining = store.get_fuel().ok()?;
+        Some(fuel.saturating_sub(remaining))
+    }
+
+    /// Create a resource limiter for a single guest invocation.
+    ///
+    /// The limiter should be stored in the store's data and registered using
+    /// [`Store::limiter`].
+    #[must_use]
+    pub const fn limiter(&self) -> Limiter {
+        Limiter {
+            memory_bytes: self.max_memory_bytes,
+            table_elements: self.max_table_elements,
+            instances: self.max_instances,
+            core_instances: None,
+            instances_exceeded: None,
+        }
+    }
+}
+
+/// Resource limiter applied to a single guest invocation's store.
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    memory_bytes: Option<usize>,
+    table_elements: Option<usize>,
+    instances: Option<usize>,
+    core_instances: Option<usize>,
+    instances_exceeded: Option<LimitExceeded>,
+}
+
+impl Limiter {
+    /// Set the number of core instances created by instantiating the
+    /// store's component, as counted by [`core_instances`].
+    #[must_use]
+    pub const fn with_core_instances(mut self, core_instances: Option<usize>) -> Self {
+        self.core_instances = core_instances;
+        self
+    }
+
+    /// Record a failure to instantiate a component in the limiter's store.
+    ///
+    /// `wasmtime` only reads the instance limit when the limiter is
+    /// registered, and fails instantiation without identifying the limit in
+    /// the error it returns. The limiter records a failure as exceeding its
+    /// instance limit when the component creates more core instances than
+    /// the limit allows, returning the error with [`LimitExceeded`] attached.
+    pub fn instantiation_failed(&mut self, err: anyhow::Error) -> anyhow::Error {
+        if let Some(limit) = self.instances
+            && let Some(requested) = self.core_instances
+            && requested > limit
+        {
+            self.instances_exceeded = Some(LimitExceeded {
+                resource: LimitedResource::Instances,
+                requested,
+                limit,
+            });
+        }
+        match self.instances_exceeded {
+            Some(exceeded) => err.context(exceeded),
+            None => err,
+        }
+    }
+
+    /// Returns the instance limit exceeded by the guest, if any.
+    #[must_use]
+    pub const fn instances_exceeded(&self) -> Option<LimitExceeded> {
+        self.instances_exceeded
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Limits::default().limiter()
+    }
+}
+
+impl ResourceLimiter for Limiter {
+    fn memory_growing(
+        &mut self, _current: usize, desired: usize, maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        if let Some(limit) = self.memory_bytes
+            && desired > limit
+        {
+            return Err(LimitExceeded {
+                resource: LimitedResource::Memory,
+                requested: desired,
+                limit,
+            }
+            .into());
+        }
+        Ok(maximum.is_none_or(|max| desired <= max))
+    }
+
+    fn table_growing(
+        &mut self, _current: usize, desired: usize, maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        if let Some(limit) = self.table_elements
+            && desired > limit
+        {
+            return Err(LimitExceeded {
+                resource: LimitedResource::TableElements,
+                requested: desired,
+                limit,
+            }
+            .into());
+        }
+        Ok(maximum.is_none_or(|max| desired <= max))
+    }
+
+    fn instances(&self) -> usize {
+        self.instances.unwrap_or(DEFAULT_INSTANCE_LIMIT)
+    }
+}
+
+/// Error used to trap a guest invocation that has exceeded its CPU budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "guest deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// Returns `true` if the error was caused by a guest exceeding its CPU
+/// budget.
+#[must_use]
+pub fn is_deadline_exceeded(err: &anyhow::Error) -> bool {
+    err.chain().any(<dyn std::error::Error>::is::<DeadlineExceeded>)
+}
+
+/// Returns `true` if the error was caused by a guest exhausting its fuel.
+#[must_use]
+pub fn is_fuel_exhausted(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel))
+}
+
+/// A resource capped by [`Limiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitedResource {
+    /// Bytes of linear memory.
+    Memory,
+
+    /// Elements in a table.
+    TableElements,
+
+    /// Core instances.
+    Instances,
+}
+
+impl Display for LimitedResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory => write!(f, "memory"),
+            Self::TableElements => write!(f, "table elements"),
+            Self::Instances => write!(f, "instances"),
+        }
+    }
+}
+
+/// Error used to trap a guest invocation that has exceeded a resource limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+    /// The resource whose limit was exceeded.
+    pub resource: LimitedResource,
+
+    /// The amount of the resource requested by the guest.
+    pub requested: usize,
+
+    /// The configured limit.
+    pub limit: usize,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guest {} limit exceeded: requested {}, limit {}",
+            self.resource, self.requested, self.limit
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Returns the resource limit exceeded by a guest, if that was the cause of
+/// the error.
+///
+/// Instance limits are only identified for components instantiated using
+/// [`State::instantiate`](crate::State::instantiate), which records them with
+/// [`Limiter::instantiation_failed`].
+#[must_use]
+pub fn limit_exceeded(err: &anyhow::Error) -> Option<LimitExceeded> {
+    // context added to an error is only found by downcasting the error itself
+    err.downcast_ref::<LimitExceeded>()
+        .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<LimitExceeded>()))
+        .copied()
+}
+
+/// Start a background thread that increments the engine's epoch every
+/// [`EPOCH_TICK`].
+///
+/// The thread exits once the engine has been dropped.
+pub fn start_epoch_ticker(engine: &Engine) -> Result<()> {
+    let engine = engine.weak();
+
+    thread::Builder::new()
+        .name("epoch-ticker".into())
+        .spawn(move || {
+            while let Some(engine) = engine.upgrade() {
+                engine.increment_epoch();
+                drop(engine);
+                thread::sleep(EPOCH_TICK);
+            }
+        })
+        .context("starting epoch ticker")?;
+
+    Ok(())
+}
+
+/// Count the core instances created by instantiating a component.
+///
+/// The count includes core instances created by nested components, but not
+/// the adapter modules `wasmtime` may add between them. Returns `None` when
+/// the count depends on components imported by the component, or the
+/// component cannot be parsed.
+#[must_use]
+pub fn core_instances(component: &[u8]) -> Option<usize> {
+    // the component (or core module) being parsed, and those enclosing it
+    let mut scopes: Vec<Option<Scope>> = Vec::new();
+
+    for payload in Parser::new(0).parse_all(component) {
+        match payload.ok()? {
+            Payload::Version { encoding, .. } => {
+                scopes.push((encoding == Encoding::Component).then(Scope::default));
+            }
+            Payload::End(_) => match (scopes.pop()?, scopes.last_mut()) {
+                (Some(scope), None) => return Some(scope.instances),
+                (Some(scope), Some(parent)) => {
+                    parent.as_mut()?.components.push(Some(scope.instances));
+                }
+                (None, _) => {}
+            },
+            Payload::InstanceSection(reader) => {
+                let scope = scopes.last_mut()?.as_mut()?;
+                for instance in reader {
+                    if matches!(instance.ok()?, Instance::Instantiate { .. }) {
+                        scope.instances += 1;
+                    }
+                }
+            }
+            Payload::ComponentInstanceSection(reader) => {
+                let scope = scopes.last_mut()?.as_mut()?;
+                for instance in reader {
+                    if let ComponentInstance::Instantiate { component_index, .. } = instance.ok()? {
+                        scope.instances += scope.component(component_index)?;
+                    }
+                }
+            }
+            Payload::ComponentImportSection(reader) => {
+                let scope = scopes.last_mut()?.as_mut()?;
+                for import in reader {
+                    if matches!(import.ok()?.ty, ComponentTypeRef::Component(_)) {
+                        scope.components.push(None);
+                    }
+                }
+            }
+            Payload::ComponentAliasSection(reader) => {
+                for alias in reader {
+                    let instances = match alias.ok()? {
+                        ComponentAlias::InstanceExport {
+                            kind: ComponentExternalKind::Component,
+                            ..
+                        } => None,
+                        ComponentAlias::Outer {
+                            kind: ComponentOuterAliasKind::Component,
+                            count,
+                            index,
+                        } => {
+                            let outer =
+                                scopes.len().checked_sub(usize::try_from(count).ok()? + 1)?;
+                            scopes[outer]
+                                .as_ref()?
+                                .components
+                                .get(usize::try_from(index).ok()?)?
+                                .to_owned()
+                        }
+                        _ => continue,
+                    };
+                    scopes.last_mut()?.as_mut()?.components.push(instances);
+                }
+            }
+            Payload::ComponentExportSection(reader) => {
+                let scope = scopes.last_mut()?.as_mut()?;
+                for export in reader {
+                    let export = export.ok()?;
+                    if export.kind == ComponentExternalKind::Component {
+                        let instances =
+                            scope.components.get(usize::try_from(export.index).ok()?)?;
+                        scope.components.push(*instances);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// Core instances created by a component being parsed, along with those
+// created by each component in its index space (when known).
+#[derive(Default)]
+struct Scope {
+    instances: usize,
+    components: Vec<Option<usize>>,
+}
+
+impl Scope {
+    fn component(&self, index: u32) -> Option<usize> {
+        *self.components.get(usize::try_from(index).ok()?)?
+    }
+}
+
+fn tick_millis() -> u64 {
+    u64::try_from(EPOCH_TICK.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::component::{Component, Linker};
+    use wasmtime::{Instance, Memory, MemoryType, Module};
+
+    use super::*;
+
+    // (module (func (export "spin") (loop (br 0))))
+    const SPIN: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+        0x03, 0x02, 0x01, 0x00, // function section
+        0x07, 0x08, 0x01, 0x04, b's', b'p', b'i', b'n', 0x00, 0x00, // export section
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b, // code section
+    ];
+
+    // (module)
+    const EMPTY: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+    // (component
+    //   (core module $m)
+    //   (core instance (instantiate $m))
+    //   (core instance (instantiate $m))
+    //   (core instance (instantiate $m)))
+    const INSTANCES: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00, // header
+        0x01, 0x08, 0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // core module section
+        0x02, 0x0a, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, // core instance section
+    ];
+
+    // (component
+    //   (component $c (core module $m) (core instance (instantiate $m)))
+    //   (instance (instantiate $c))
+    //   (instance (instantiate $c)))
+    const NESTED: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00, // header
+        0x04, 0x18, // component section
+        0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00, // nested header
+        0x01, 0x08, 0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // core module section
+        0x02, 0x04, 0x01, 0x00, 0x00, 0x00, // core instance section
+        0x05, 0x07, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // instance section
+    ];
+
+    // (component
+    //   (import "a" (component $c))
+    //   (instance (instantiate $c)))
+    const IMPORTED: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00, // header
+        0x0a, 0x06, 0x01, 0x00, 0x01, b'a', 0x04, 0x00, // import section
+        0x05, 0x04, 0x01, 0x00, 0x00, 0x00, // instance section
+    ];
+
+    const PAGE_SIZE: usize = 64 * 1024;
+
+    #[tokio::test]
+    async fn fuel_metering() {
+        let limits = Limits {
+            fuel: Some(10_000),
+            ..Limits::default()
+        };
+        let engine = Engine::new(&crate::create::config(&limits)).expect("should create engine");
+        start_epoch_ticker(&engine).expect("should start ticker");
+
+        // consumes fuel until exhausted
+        let module = Module::new(&engine, SPIN).expect("should compile module");
+        let mut store = Store::new(&engine, ());
+        limits.configure(&mut store).expect("should configure store");
+
+        let instance =
+            Instance::new_async(&mut store, &module, &[]).await.expect("should instantiate");
+        let spin = instance.get_typed_func::<(), ()>(&mut store, "spin").expect("should export");
+        let err = spin.call_async(&mut store, ()).await.expect_err("should trap").into();
+
+        assert!(is_fuel_exhausted(&err));
+        assert!(!is_deadline_exceeded(&err));
+        assert_eq!(limits.fuel_consumed(&store), Some(10_000));
+    }
+
+    #[test]
+    fn memory_limit() {
+        let engine = Engine::default();
+        let limits = Limits {
+            max_memory_bytes: Some(2 * PAGE_SIZE),
+            ..Limits::default()
+        };
+        let mut store = Store::new(&engine, limits.limiter());
+        store.limiter(|limiter| limiter);
+
+        let memory = Memory::new(&mut store, MemoryType::new(1, None)).expect("should create");
+        memory.grow(&mut store, 1).expect("should grow within limit");
+
+        let err = memory.grow(&mut store, 1).expect_err("should exceed limit");
+        let exceeded = limit_exceeded(&err.into()).expect("should be limit exceeded");
+        assert_eq!(exceeded.resource, LimitedResource::Memory);
+        assert_eq!(exceeded.requested, 3 * PAGE_SIZE);
+        assert_eq!(exceeded.limit, 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn table_limit() {
+        let mut limiter = Limits {
+            max_table_elements: Some(10),
+            ..Limits::default()
+        }
+        .limiter();
+
+        assert!(limiter.table_growing(0, 10, None).expect("should allow"));
+        assert!(!limiter.table_growing(0, 10, Some(5)).expect("should deny"));
+
+        let err = limiter.table_growing(10, 11, None).expect_err("should exceed limit");
+        let exceeded = limit_exceeded(&err.into()).expect("should be limit exceeded");
+        assert_eq!(exceeded.resource, LimitedResource::TableElements);
+    }
+
+    #[test]
+    fn instance_limit() {
+        let engine = Engine::default();
+        let component = Component::new(&engine, INSTANCES).expect("should compile component");
+        let limits = Limits {
+            max_instances: Some(2),
+            ..Limits::default()
+        };
+        let limiter = limits.limiter().with_core_instances(core_instances(INSTANCES));
+        let mut store = Store::new(&engine, limiter);
+        store.limiter(|limiter| limiter);
+
+        let linker = Linker::new(&engine);
+        let err = linker.instantiate(&mut store, &component).expect_err("should exceed limit");
+
+        // wasmtime does not identify the limit itself
+        let err = anyhow::Error::from(err);
+        assert!(limit_exceeded(&err).is_none());
+
+        let err = store.data_mut().instantiation_failed(err);
+        let exceeded = limit_exceeded(&err).expect("should be limit exceeded");
+        assert_eq!(exceeded.resource, LimitedResource::Instances);
+        assert_eq!(exceeded.requested, 3);
+        assert_eq!(exceeded.limit, 2);
+        assert_eq!(store.data().instances_exceeded(), Some(exceeded));
+    }
+
+    #[test]
+    fn instantiation_failure() {
+        // failures of components within the limit are not attributed to it
+        let mut limiter = Limits {
+            max_instances: Some(3),
+            ..Limits::default()
+        }
+        .limiter()
+        .with_core_instances(Some(3));
+        let err = limiter.instantiation_failed(anyhow::anyhow!("missing import"));
+        assert!(limit_exceeded(&err).is_none());
+        assert!(limiter.instances_exceeded().is_none());
+
+        // nor are failures when no limit is configured
+        let mut limiter = Limiter::default().with_core_instances(Some(3));
+        let err = limiter.instantiation_failed(anyhow::anyhow!("missing import"));
+        assert!(limit_exceeded(&err).is_none());
+
+        // nor when the component's instances are unknown
+        let mut limiter = Limits {
+            max_instances: Some(1),
+            ..Limits::default()
+        }
+        .limiter();
+        let err = limiter.instantiation_failed(anyhow::anyhow!("missing import"));
+        assert!(limit_exceeded(&err).is_none());
+    }
+
+    #[test]
+    fn counts_core_instances() {
+        assert_eq!(core_instances(INSTANCES), Some(3));
+        assert_eq!(core_instances(NESTED), Some(2));
+        assert_eq!(core_instances(IMPORTED), None);
+        assert_eq!(core_instances(EMPTY), None);
+        assert_eq!(core_instances(&INSTANCES[..20]), None);
+    }
+
+    #[test]
+    fn other_errors() {
+        let err = anyhow::anyhow!("some other error");
+        assert!(!is_deadline_exceeded(&err));
+
+        assert!(limit_exceeded(&err).is_none());
+
+        let err = anyhow::Error::from(DeadlineExceeded).context("handling request");
+        assert!(is_deadline_exceeded(&err));
+        assert!(limit_exceeded(&err).is_none());
+    }
+}
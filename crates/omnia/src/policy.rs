@@ -0,0 +1,345 @@
+//! # Capability Policy
+//!
+//! Restricts the resources each hosted component may access.
+//!
+//! Linking a host gives a guest access to the whole interface: any bucket,
+//! SQL connection, vault locker, and so on. A capability policy narrows that
+//! access to the named resources each component needs. Hosts check the
+//! component's [`Capabilities`] when a resource is opened and return the
+//! interface's access denied error when the policy does not allow it.
+//! Interfaces without one (`omnia:identity`, `wasi:sql` and `wasi:blobstore`)
+//! return their general error, with a message starting with
+//! [`AccessDenied::PREFIX`].
+//!
+//! The policy is loaded from the file named by `CAPABILITY_POLICY`. Files
+//! with a `.json` extension are parsed as JSON, anything else as TOML:
+//!
+//! ```toml
+//! [components.order-skill]
+//! buckets = ["orders-cache"]
+//! sql = ["orders"]
+//! send = ["orders.*"]
+//! subscribe = ["orders.*"]
+//! lockers = ["payments"]
+//! identities = ["orders-service"]
+//! containers = ["invoices"]
+//! collections = ["orders"]
+//! http_hosts = ["api.example.com", "*.internal.example.com"]
+//! ```
+//!
+//! When no policy is configured, components are unrestricted. Once a policy
+//! is configured, components are denied anything the policy does not grant,
+//! including components missing from the policy. Entries ending in `*` match
+//! names with the preceding prefix; entries starting with `*` (`http_hosts`
+//! only) match names with the following suffix.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::sync::Arc;
+use std::{env, fs};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// A kind of resource a guest may access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// A key-value bucket.
+    Bucket,
+
+    /// A named SQL connection.
+    SqlConnection,
+
+    /// A messaging topic the guest sends to.
+    Send,
+
+    /// A messaging topic the guest receives from.
+    Subscribe,
+
+    /// A vault locker.
+    Locker,
+
+    /// A named identity.
+    Identity,
+
+    /// A blobstore container.
+    Container,
+
+    /// A JSON document collection.
+    Collection,
+
+    /// The host of an outbound HTTP request.
+    HttpHost,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bucket => "bucket",
+            Self::SqlConnection => "sql connection",
+            Self::Send => "send topic",
+            Self::Subscribe => "subscribe topic",
+            Self::Locker => "vault locker",
+            Self::Identity => "identity",
+            Self::Container => "blobstore container",
+            Self::Collection => "jsondb collection",
+            Self::HttpHost => "http host",
+        })
+    }
+}
+
+/// Resources granted to a single component.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Grants {
+    /// Key-value buckets the component may open.
+    pub buckets: Vec<String>,
+
+    /// SQL connections the component may open.
+    pub sql: Vec<String>,
+
+    /// Topics the component may send (or send requests) to.
+    pub send: Vec<String>,
+
+    /// Topics the component may receive messages from.
+    pub subscribe: Vec<String>,
+
+    /// Vault lockers the component may open.
+    pub lockers: Vec<String>,
+
+    /// Identities the component may get tokens for.
+    pub identities: Vec<String>,
+
+    /// Blobstore containers the component may use.
+    pub containers: Vec<String>,
+
+    /// JSON document collections the component may use.
+    pub collections: Vec<String>,
+
+    /// Hosts the component may make outbound HTTP requests to.
+    pub http_hosts: Vec<String>,
+}
+
+impl Grants {
+    fn allows(&self, capability: Capability, name: &str) -> bool {
+        let patterns = match capability {
+            Capability::Bucket => &self.buckets,
+            Capability::SqlConnection => &self.sql,
+            Capability::Send => &self.send,
+            Capability::Subscribe => &self.subscribe,
+            Capability::Locker => &self.lockers,
+            Capability::Identity => &self.identities,
+            Capability::Container => &self.containers,
+            Capability::Collection => &self.collections,
+            Capability::HttpHost => {
+                return self.http_hosts.iter().any(|pattern| {
+                    pattern.strip_prefix('*').map_or_else(
+                        || pattern.eq_ignore_ascii_case(name),
+                        |suffix| name.to_ascii_lowercase().ends_with(&suffix.to_ascii_lowercase()),
+                    )
+                });
+            }
+        };
+        patterns.iter().any(|pattern| {
+            pattern.strip_suffix('*').map_or(pattern == name, |prefix| name.starts_with(prefix))
+        })
+    }
+}
+
+/// The capability policy for all hosted components.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Grants keyed by component name.
+    #[serde(default)]
+    pub components: HashMap<String, Grants>,
+}
+
+impl Policy {
+    /// Load a policy from a TOML or JSON file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("reading capability policy {}", path.display()))?;
+        let policy = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            serde_json::from_str(&contents)?
+        } else {
+            toml::from_str(&contents)?
+        };
+        Ok(policy)
+    }
+
+    /// Load the policy named by `CAPABILITY_POLICY`, if set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the policy file cannot be read or parsed.
+    pub fn from_env() -> Result<Option<Self>> {
+        env::var("CAPABILITY_POLICY")
+            .ok()
+            .map(|path| Self::load(Path::new(&path)))
+            .transpose()
+            .context("issue loading capability policy")
+    }
+
+    /// Returns the capabilities granted to the named component.
+    #[must_use]
+    pub fn capabilities(&self, component: &str) -> Capabilities {
+        let grants = self.components.get(component).cloned().unwrap_or_default();
+        Capabilities(Some(Arc::new(grants)))
+    }
+}
+
+/// The capabilities of a single component, checked by hosts before a guest
+/// accesses a resource.
+///
+/// The default is unrestricted.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities(Option<Arc<Grants>>);
+
+impl Capabilities {
+    /// Capabilities that allow access to everything.
+    #[must_use]
+    pub const fn unrestricted() -> Self {
+        Self(None)
+    }
+
+    /// Capabilities limited to the specified grants.
+    #[must_use]
+    pub fn granted(grants: Grants) -> Self {
+        Self(Some(Arc::new(grants)))
+    }
+
+    /// Returns `true` if no policy applies.
+    #[must_use]
+    pub const fn is_unrestricted(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Check the component may access the named resource.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AccessDenied`] if the resource has not been granted.
+    pub fn check(&self, capability: Capability, name: &str) -> Result<(), AccessDenied> {
+        match &self.0 {
+            Some(grants) if !grants.allows(capability, name) => {
+                tracing::warn!(
+                    monotonic_counter.access_denied = 1,
+                    capability = %capability,
+                    "{}: {capability} `{name}`",
+                    AccessDenied::PREFIX
+                );
+                Err(AccessDenied {
+                    capability,
+                    name: name.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returned when the capability policy does not grant access to a resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessDenied {
+    /// The kind of resource.
+    pub capability: Capability,
+
+    /// The resource name.
+    pub name: String,
+}
+
+impl AccessDenied {
+    /// The start of every denial's message, marking errors from interfaces
+    /// without an access denied error as denied by the policy rather than
+    /// failed by the backend.
+    pub const PREFIX: &str = "access denied by capability policy";
+}
+
+impl Display for AccessDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} `{}`", Self::PREFIX, self.capability, self.name)
+    }
+}
+
+impl std::error::Error for AccessDenied {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"
+        [components.orders]
+        buckets = ["cache"]
+        send = ["orders.*"]
+        collections = ["orders"]
+        http_hosts = ["api.example.com", "*.internal.example.com"]
+    "#;
+
+    #[test]
+    fn grants() {
+        let policy: Policy = toml::from_str(POLICY).expect("should parse");
+        let capabilities = policy.capabilities("orders");
+
+        capabilities.check(Capability::Bucket, "cache").expect("should allow");
+        capabilities.check(Capability::Bucket, "sessions").expect_err("should deny");
+        capabilities.check(Capability::Send, "orders.created").expect("should allow");
+        capabilities.check(Capability::Send, "users.created").expect_err("should deny");
+        capabilities.check(Capability::SqlConnection, "db").expect_err("should deny");
+        capabilities.check(Capability::Collection, "orders").expect("should allow");
+        capabilities.check(Capability::Collection, "users").expect_err("should deny");
+
+        capabilities.check(Capability::HttpHost, "API.example.com").expect("should allow");
+        capabilities.check(Capability::HttpHost, "svc.internal.example.com").expect("should allow");
+        capabilities.check(Capability::HttpHost, "example.com").expect_err("should deny");
+    }
+
+    #[test]
+    fn unlisted_component() {
+        let policy: Policy = toml::from_str(POLICY).expect("should parse");
+        let denied = policy.capabilities("users").check(Capability::Bucket, "cache");
+        assert_eq!(
+            denied,
+            Err(AccessDenied {
+                capability: Capability::Bucket,
+                name: "cache".into(),
+            })
+        );
+        assert_eq!(
+            denied.unwrap_err().to_string(),
+            "access denied by capability policy: bucket `cache`"
+        );
+    }
+
+    #[test]
+    fn unrestricted() {
+        let capabilities = Capabilities::default();
+        assert!(capabilities.is_unrestricted());
+        capabilities.check(Capability::Locker, "anything").expect("should allow");
+    }
+
+    #[test]
+    fn json_policy() {
+        let path = std::env::temp_dir().join(format!("omnia-policy-{}.json", std::process::id()));
+        fs::write(&path, r#"{ "components": { "orders": { "lockers": ["payments"] } } }"#)
+            .expect("should write");
+
+        let policy = Policy::load(&path).expect("should load");
+        fs::remove_file(&path).expect("should remove");
+
+        let capabilities = policy.capabilities("orders");
+        capabilities.check(Capability::Locker, "payments").expect("should allow");
+        capabilities.check(Capability::Locker, "other").expect_err("should deny");
+    }
+
+    #[test]
+    fn unknown_fields() {
+        toml::from_str::<Policy>("[components.orders]\nbucket = [\"cache\"]")
+            .expect_err("should reject unknown fields");
+    }
+}
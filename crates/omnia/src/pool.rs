@@ -0,0 +1,109 @@
+//! # Pooling Allocator
+//!
+//! Opt-in configuration for `wasmtime`'s pooling instance allocator.
+//!
+//! By default, each guest invocation instantiates the component into freshly
+//! allocated (mmapped) memories, tables and stacks. The pooling allocator
+//! reserves slots for these resources up front and reuses them across
+//! invocations, avoiding per-request allocation and making instantiation
+//! substantially cheaper for high-throughput guests.
+//!
+//! Pooling is enabled by setting `GUEST_POOL_INSTANCES`. The remaining pool
+//! sizes are derived from the number of component instances and may be
+//! tuned individually.
+
+#![allow(missing_docs)]
+
+use anyhow::{Context, Result};
+use fromenv::FromEnv;
+use wasmtime::{Config, InstanceAllocationStrategy, PoolingAllocationConfig};
+
+use crate::limits::Limits;
+
+/// Default number of core instances reserved per component instance.
+const CORE_INSTANCES_PER_COMPONENT: u32 = 20;
+
+/// Default number of linear memories reserved per component instance.
+const MEMORIES_PER_COMPONENT: u32 = 4;
+
+/// Default number of tables reserved per component instance.
+const TABLES_PER_COMPONENT: u32 = 20;
+
+/// Pooling allocator configuration.
+#[derive(Debug, Clone, Default, FromEnv)]
+pub struct Pooling {
+    /// The maximum number of component instances (i.e. concurrent guest
+    /// invocations) the pool can hold. Setting this enables pooling.
+    #[env(from = "GUEST_POOL_INSTANCES")]
+    pub instances: Option<u32>,
+
+    /// The number of core instances reserved per component instance.
+    #[env(from = "GUEST_POOL_CORE_INSTANCES_PER_COMPONENT")]
+    pub core_instances_per_component: Option<u32>,
+
+    /// The number of linear memories reserved per component instance.
+    #[env(from = "GUEST_POOL_MEMORIES_PER_COMPONENT")]
+    pub memories_per_component: Option<u32>,
+
+    /// The number of tables reserved per component instance.
+    #[env(from = "GUEST_POOL_TABLES_PER_COMPONENT")]
+    pub tables_per_component: Option<u32>,
+
+    /// The maximum number of unused slots kept warm for reuse.
+    #[env(from = "GUEST_POOL_WARM_SLOTS")]
+    pub warm_slots: Option<u32>,
+}
+
+impl crate::FromEnv for Pooling {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading pooling allocator configuration")
+    }
+
+    fn env_vars() -> Vec<String> {
+        crate::requirement_vars(&Self::requirements())
+    }
+}
+
+impl Pooling {
+    /// Returns `true` if the pooling allocator has been enabled.
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.instances.is_some()
+    }
+
+    /// Configure the engine to use the pooling allocator, when enabled.
+    ///
+    /// Memory and table slots are sized using the guest [`Limits`], when set,
+    /// to reduce the address space reserved by the pool.
+    pub fn configure(&self, config: &mut Config, limits: &Limits) {
+        let Some(instances) = self.instances else {
+            return;
+        };
+        let core_instances =
+            self.core_instances_per_component.unwrap_or(CORE_INSTANCES_PER_COMPONENT);
+        let memories = self.memories_per_component.unwrap_or(MEMORIES_PER_COMPONENT);
+        let tables = self.tables_per_component.unwrap_or(TABLES_PER_COMPONENT);
+
+        let mut pooling = PoolingAllocationConfig::new();
+        pooling
+            .total_component_instances(instances)
+            .max_core_instances_per_component(core_instances)
+            .total_core_instances(instances.saturating_mul(core_instances))
+            .max_memories_per_component(memories)
+            .total_memories(instances.saturating_mul(memories))
+            .max_tables_per_component(tables)
+            .total_tables(instances.saturating_mul(tables));
+
+        if let Some(warm_slots) = self.warm_slots {
+            pooling.max_unused_warm_slots(warm_slots);
+        }
+        if let Some(max_memory_bytes) = limits.max_memory_bytes {
+            pooling.max_memory_size(max_memory_bytes);
+        }
+        if let Some(max_table_elements) = limits.max_table_elements {
+            pooling.table_elements(max_table_elements);
+        }
+
+        config.allocation_strategy(InstanceAllocationStrategy::Pooling(pooling));
+    }
+}
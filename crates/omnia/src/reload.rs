@@ -0,0 +1,274 @@
+//! # Hot Reload
+//!
+//! Swaps the guest component without restarting the runtime.
+//!
+//! The runtime holds the pre-instantiated component in an [`ActiveComponent`].
+//! Each invocation takes the component that is active when it starts, so a
+//! reload only affects new invocations while in-flight invocations finish on
+//! the previous version.
+//!
+//! Reloads are triggered by `SIGHUP` or, when `COMPONENT_WATCH_INTERVAL_MS`
+//! is set, by changes to the component file's modification time.
+
+#![allow(missing_docs)]
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use fromenv::FromEnv;
+use wasmtime::component::InstancePre;
+
+use crate::shutdown::Shutdown;
+use crate::traits;
+
+/// The pre-instantiated component used for new guest invocations.
+pub struct ActiveComponent<T: 'static>(Arc<ArcSwap<Active<T>>>);
+
+// The active component and the number of core instances it creates.
+struct Active<T: 'static> {
+    instance_pre: InstancePre<T>,
+    core_instances: Option<usize>,
+}
+
+impl<T: 'static> ActiveComponent<T> {
+    /// Create a new active component, along with the number of core
+    /// instances it creates (see [`Compiled::core_instances`]).
+    ///
+    /// [`Compiled::core_instances`]: crate::Compiled::core_instances
+    #[must_use]
+    pub fn new(instance_pre: InstancePre<T>, core_instances: Option<usize>) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(Active {
+            instance_pre,
+            core_instances,
+        })))
+    }
+
+    /// Returns the currently active component.
+    #[must_use]
+    pub fn load(&self) -> InstancePre<T> {
+        InstancePre::clone(&self.0.load().instance_pre)
+    }
+
+    /// Returns the number of core instances the currently active component
+    /// creates, if known.
+    #[must_use]
+    pub fn core_instances(&self) -> Option<usize> {
+        self.0.load().core_instances
+    }
+
+    /// Atomically replace the active component. Invocations already using
+    /// the previous component are unaffected.
+    pub fn swap(&self, instance_pre: InstancePre<T>, core_instances: Option<usize>) {
+        self.0.store(Arc::new(Active {
+            instance_pre,
+            core_instances,
+        }));
+    }
+}
+
+impl<T: 'static> Clone for ActiveComponent<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+/// Hot reload configuration.
+#[derive(Debug, Clone, Copy, Default, FromEnv)]
+pub struct ReloadOptions {
+    /// The interval (in milliseconds) at which to check the component file
+    /// for changes. When unset, the file is not watched and components are
+    /// only reloaded on `SIGHUP`.
+    #[env(from = "COMPONENT_WATCH_INTERVAL_MS")]
+    pub watch_interval_ms: Option<u64>,
+}
+
+impl traits::FromEnv for ReloadOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading reload options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        traits::requirement_vars(&Self::requirements())
+    }
+}
+
+/// Watch for reload triggers, calling `reload` each time the component
+/// should be reloaded.
+///
+/// Reload options are loaded from the environment. Watching stops once
+/// shutdown is signalled.
+///
+/// # Errors
+///
+/// Returns an error if the reload options cannot be loaded or the signal
+/// handler cannot be installed.
+pub fn watch<F>(wasm: &Path, shutdown: &Shutdown, reload: F) -> Result<()>
+where
+    F: FnMut() -> Result<()> + Send + 'static,
+{
+    let options = <ReloadOptions as traits::FromEnv>::from_env()?;
+    watch_with(options, wasm, shutdown, reload)
+}
+
+/// Watch for reload triggers using the specified options.
+///
+/// # Errors
+///
+/// Returns an error if the signal handler cannot be installed.
+pub fn watch_with<F>(
+    options: ReloadOptions, wasm: &Path, shutdown: &Shutdown, reload: F,
+) -> Result<()>
+where
+    F: FnMut() -> Result<()> + Send + 'static,
+{
+    let wasm = wasm.to_path_buf();
+    let shutdown = shutdown.clone();
+    let mut triggers = Triggers::new(options, wasm.clone())?;
+    let reload = Arc::new(Mutex::new(reload));
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                () = triggers.next() => {}
+                () = shutdown.signalled() => return,
+            }
+
+            // compiling the component is CPU-bound
+            let reload = Arc::clone(&reload);
+            let result = tokio::task::spawn_blocking(move || {
+                let mut reload = reload.lock().unwrap_or_else(PoisonError::into_inner);
+                reload()
+            })
+            .await
+            .context("reload task panicked")
+            .flatten();
+
+            match result {
+                Ok(()) => {
+                    tracing::info!(
+                        monotonic_counter.component_reloads = 1,
+                        "reloaded component: {}",
+                        wasm.display()
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        monotonic_counter.component_reload_errors = 1,
+                        "issue reloading component {}: {e:#}",
+                        wasm.display()
+                    );
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Sources of reload triggers.
+struct Triggers {
+    wasm: PathBuf,
+    interval: Option<tokio::time::Interval>,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+}
+
+impl Triggers {
+    fn new(options: ReloadOptions, wasm: PathBuf) -> Result<Self> {
+        let interval = options.watch_interval_ms.map(|ms| {
+            let mut interval = tokio::time::interval(Duration::from_millis(ms.max(1)));
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval
+        });
+        let modified = modified(&wasm);
+
+        Ok(Self {
+            wasm,
+            interval,
+            modified,
+            #[cfg(unix)]
+            hangup: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .context("installing SIGHUP handler")?,
+        })
+    }
+
+    // Resolves when the component should be reloaded.
+    async fn next(&mut self) {
+        #[cfg(unix)]
+        let hangup = self.hangup.recv();
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<Option<()>>();
+
+        let (wasm, last_modified) = (&self.wasm, &mut self.modified);
+        let changed = async {
+            let Some(interval) = self.interval.as_mut() else {
+                return std::future::pending().await;
+            };
+            loop {
+                interval.tick().await;
+                let modified = modified(wasm);
+                if modified.is_some() && modified != *last_modified {
+                    *last_modified = modified;
+                    return;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = hangup => tracing::info!("received SIGHUP"),
+            () = changed => tracing::debug!("component file changed"),
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn reloads_on_change() {
+        let wasm = std::env::temp_dir().join(format!("omnia-reload-{}.wasm", std::process::id()));
+        fs::write(&wasm, b"v1").expect("should write");
+
+        let (shutdown, trigger) = Shutdown::new(Duration::ZERO);
+        let reloads = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&reloads);
+
+        let options = ReloadOptions {
+            watch_interval_ms: Some(10),
+        };
+        watch_with(options, &wasm, &shutdown, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .expect("should watch");
+
+        // unchanged file does not trigger a reload
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(reloads.load(Ordering::SeqCst), 0);
+
+        // bump the modification time to simulate a new build
+        let file = fs::File::options().write(true).open(&wasm).expect("should open");
+        file.set_modified(SystemTime::now() + Duration::from_secs(1)).expect("should set");
+
+        let start = tokio::time::Instant::now();
+        while reloads.load(Ordering::SeqCst) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "should reload");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        trigger.trigger();
+        fs::remove_file(&wasm).expect("should remove");
+    }
+}
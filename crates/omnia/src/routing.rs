@@ -0,0 +1,174 @@
+//! # Component Routing
+//!
+//! A single runtime process can host several components. Servers use each
+//! component's [`Routes`] to decide which component handles incoming work:
+//! HTTP requests are routed by `Host` header and path prefix, and messages by
+//! topic.
+//!
+//! Routes are configured per component using environment variables prefixed
+//! with the component's name in upper snake case. For example, a component
+//! named `order-skill` is configured with:
+//!
+//! - `ORDER_SKILL_HTTP_PREFIX`: the URL path prefix served by the component.
+//! - `ORDER_SKILL_HTTP_HOSTS`: a comma-separated list of `Host` header values
+//!   served by the component.
+//! - `ORDER_SKILL_TOPICS`: a comma-separated list of topics the component
+//!   handles. A trailing `*` matches any topic with the preceding prefix.
+//!
+//! Components without routes for a protocol accept everything for that
+//! protocol.
+
+use std::env;
+
+/// Routes used to select the component that handles incoming work.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Routes {
+    /// The URL path prefix served by the component.
+    pub path_prefix: Option<String>,
+
+    /// `Host` header values served by the component.
+    pub hosts: Vec<String>,
+
+    /// Topics handled by the component.
+    pub topics: Vec<String>,
+}
+
+impl Routes {
+    /// Load routes for the named component from the environment.
+    #[must_use]
+    pub fn from_env(component: &str) -> Self {
+        let prefix = env_prefix(component);
+        let list = |key: &str| {
+            env::var(format!("{prefix}_{key}"))
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(ToString::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        Self {
+            path_prefix: env::var(format!("{prefix}_HTTP_PREFIX")).ok(),
+            hosts: list("HTTP_HOSTS"),
+            topics: list("TOPICS"),
+        }
+    }
+
+    /// Scores how specifically the routes match an HTTP request, returning
+    /// `None` if they do not match.
+    ///
+    /// The `host` is the request's host without its port (e.g. from
+    /// `Uri::host`). Addresses such as `[::1]` match with or without brackets.
+    ///
+    /// Matching hosts take precedence over matching path prefixes, and longer
+    /// path prefixes over shorter ones.
+    #[must_use]
+    pub fn match_request(&self, host: Option<&str>, path: &str) -> Option<(bool, usize)> {
+        let host_matched = if self.hosts.is_empty() {
+            false
+        } else {
+            let host = unbracket(host?);
+            if !self.hosts.iter().any(|h| unbracket(h).eq_ignore_ascii_case(host)) {
+                return None;
+            }
+            true
+        };
+
+        let prefix_len = match self.path_prefix.as_deref().map(|p| p.trim_end_matches('/')) {
+            None | Some("") => 0,
+            Some(prefix) => {
+                let rest = path.strip_prefix(prefix)?;
+                if !rest.is_empty() && !rest.starts_with('/') {
+                    return None;
+                }
+                prefix.len()
+            }
+        };
+
+        Some((host_matched, prefix_len))
+    }
+
+    /// Returns `true` if the component handles messages on the topic.
+    #[must_use]
+    pub fn matches_topic(&self, topic: &str) -> bool {
+        self.topics.is_empty()
+            || self.topics.iter().any(|pattern| {
+                pattern
+                    .strip_suffix('*')
+                    .map_or(pattern == topic, |prefix| topic.starts_with(prefix))
+            })
+    }
+}
+
+// Strip the brackets from an IPv6 address.
+fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host)
+}
+
+/// Convert a name to an environment variable prefix in upper snake case.
+#[must_use]
+pub fn env_prefix(component: &str) -> String {
+    component
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_routes() {
+        let catch_all = Routes::default();
+        assert_eq!(catch_all.match_request(None, "/anything"), Some((false, 0)));
+
+        let prefixed = Routes {
+            path_prefix: Some("/orders/".into()),
+            ..Routes::default()
+        };
+        assert_eq!(prefixed.match_request(None, "/orders"), Some((false, 7)));
+        assert_eq!(prefixed.match_request(None, "/orders/1"), Some((false, 7)));
+        assert_eq!(prefixed.match_request(None, "/ordersx"), None);
+        assert_eq!(prefixed.match_request(None, "/users"), None);
+
+        let hosted = Routes {
+            hosts: vec!["api.example.com".into()],
+            ..Routes::default()
+        };
+        assert_eq!(hosted.match_request(Some("API.example.com"), "/"), Some((true, 0)));
+        assert_eq!(hosted.match_request(Some("other.example.com"), "/"), None);
+        assert_eq!(hosted.match_request(None, "/"), None);
+
+        let literal = Routes {
+            hosts: vec!["::1".into(), "[fd00::1]".into()],
+            ..Routes::default()
+        };
+        assert_eq!(literal.match_request(Some("[::1]"), "/"), Some((true, 0)));
+        assert_eq!(literal.match_request(Some("[fd00::1]"), "/"), Some((true, 0)));
+        assert_eq!(literal.match_request(Some("[::2]"), "/"), None);
+    }
+
+    #[test]
+    fn topic_routes() {
+        assert!(Routes::default().matches_topic("any"));
+
+        let routes = Routes {
+            topics: vec!["orders.*".into(), "users".into()],
+            ..Routes::default()
+        };
+        assert!(routes.matches_topic("orders.created"));
+        assert!(routes.matches_topic("users"));
+        assert!(!routes.matches_topic("users.created"));
+    }
+
+    #[test]
+    fn env_prefixes() {
+        assert_eq!(env_prefix("order-skill"), "ORDER_SKILL");
+        assert_eq!(env_prefix("skill.v2"), "SKILL_V2");
+    }
+}
@@ -0,0 +1,286 @@
+//! # Engine Settings
+//!
+//! A pre-compiled component can only be loaded by an engine configured
+//! compatibly with the one that compiled it. `compile` records the settings
+//! it used next to the compiled component (as `<output>.toml`) so the runtime
+//! can explain why a component cannot be loaded rather than failing with a
+//! generic deserialization error.
+
+use std::ffi::OsString;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use target_lexicon::Triple;
+use wasmtime::Config;
+
+use crate::limits::Limits;
+
+/// Cranelift optimization level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum OptLevel {
+    /// No optimizations.
+    None,
+
+    /// Optimize for speed.
+    #[default]
+    Speed,
+
+    /// Optimize for speed and code size.
+    SpeedAndSize,
+}
+
+impl From<OptLevel> for wasmtime::OptLevel {
+    fn from(level: OptLevel) -> Self {
+        match level {
+            OptLevel::None => Self::None,
+            OptLevel::Speed => Self::Speed,
+            OptLevel::SpeedAndSize => Self::SpeedAndSize,
+        }
+    }
+}
+
+/// Optional WebAssembly proposals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::Args)]
+pub struct WasmFeatures {
+    /// Enable the WebAssembly SIMD proposal.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub simd: bool,
+
+    /// Enable the WebAssembly relaxed SIMD proposal.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub relaxed_simd: bool,
+
+    /// Enable the WebAssembly tail call proposal.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub tail_call: bool,
+}
+
+impl Default for WasmFeatures {
+    fn default() -> Self {
+        Self {
+            simd: true,
+            relaxed_simd: true,
+            tail_call: true,
+        }
+    }
+}
+
+/// The engine settings a component was compiled with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineSettings {
+    /// The target triple.
+    pub target: String,
+
+    /// Cranelift optimization level.
+    pub opt_level: OptLevel,
+
+    /// Whether fuel metering is enabled.
+    pub fuel: bool,
+
+    /// Enabled WebAssembly proposals.
+    pub features: WasmFeatures,
+
+    /// The number of core instances the component creates, used to identify
+    /// instantiations failing on `GUEST_MAX_INSTANCES`. Not an engine
+    /// setting, so not checked for compatibility.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_instances: Option<usize>,
+}
+
+impl EngineSettings {
+    /// The settings of an engine created by this runtime.
+    ///
+    /// The runtime applies these settings to its engine, so they describe
+    /// the engine a pre-compiled component is loaded by.
+    #[must_use]
+    pub fn runtime(limits: &Limits) -> Self {
+        Self {
+            target: target_lexicon::HOST.to_string(),
+            opt_level: OptLevel::default(),
+            fuel: limits.fuel.is_some(),
+            features: WasmFeatures::default(),
+            core_instances: None,
+        }
+    }
+
+    /// Apply the settings to an engine configuration.
+    ///
+    /// The target is not applied: setting it explicitly disables inferring
+    /// the host's CPU features, so it is only set when cross-compiling.
+    pub fn apply(&self, config: &mut Config) {
+        config.cranelift_opt_level(self.opt_level.into());
+        config.wasm_simd(self.features.simd);
+        config.wasm_relaxed_simd(self.features.relaxed_simd);
+        config.wasm_tail_call(self.features.tail_call);
+    }
+
+    /// Read the settings recorded for a pre-compiled component, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the settings file exists but cannot be read or
+    /// parsed.
+    pub fn read(compiled: &Path) -> Result<Option<Self>> {
+        let path = settings_path(compiled);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("reading engine settings {}", path.display()))?;
+        let settings = toml::from_str(&contents)
+            .with_context(|| format!("parsing engine settings {}", path.display()))?;
+        Ok(Some(settings))
+    }
+
+    /// Record the settings next to a pre-compiled component.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the settings file cannot be written.
+    pub fn write(&self, compiled: &Path) -> Result<()> {
+        let path = settings_path(compiled);
+        fs::write(&path, toml::to_string(self)?)
+            .with_context(|| format!("writing engine settings {}", path.display()))
+    }
+
+    /// Check a component compiled with these settings can be loaded by an
+    /// engine with the `runtime` settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first incompatible setting.
+    pub fn check_compatible(&self, runtime: &Self) -> Result<()> {
+        let compiled = Triple::from_str(&self.target).map_err(|e| anyhow::anyhow!("{e}"))?;
+        let host = Triple::from_str(&runtime.target).map_err(|e| anyhow::anyhow!("{e}"))?;
+        if compiled.architecture != host.architecture
+            || compiled.operating_system != host.operating_system
+        {
+            bail!(
+                "compiled for {} but the runtime targets {}; recompile with `compile --target {}`",
+                self.target,
+                runtime.target,
+                runtime.target
+            );
+        }
+
+        if self.fuel != runtime.fuel {
+            bail!(
+                "compiled with fuel metering {} but the runtime has it {}; set `GUEST_FUEL` the \
+                 same way when compiling and running",
+                enabled(self.fuel),
+                enabled(runtime.fuel)
+            );
+        }
+
+        for (feature, compiled, host) in [
+            ("simd", self.features.simd, runtime.features.simd),
+            ("relaxed-simd", self.features.relaxed_simd, runtime.features.relaxed_simd),
+            ("tail-call", self.features.tail_call, runtime.features.tail_call),
+        ] {
+            if compiled && !host {
+                bail!("compiled with `{feature}`, which the runtime does not enable");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for EngineSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "target={}, opt-level={:?}, fuel={}, simd={}, relaxed-simd={}, tail-call={}",
+            self.target,
+            self.opt_level,
+            self.fuel,
+            self.features.simd,
+            self.features.relaxed_simd,
+            self.features.tail_call
+        )
+    }
+}
+
+/// The path settings are recorded at for a pre-compiled component.
+#[must_use]
+pub fn settings_path(compiled: &Path) -> PathBuf {
+    let mut path = OsString::from(compiled.as_os_str());
+    path.push(".toml");
+    PathBuf::from(path)
+}
+
+const fn enabled(on: bool) -> &'static str {
+    if on { "enabled" } else { "disabled" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime() -> EngineSettings {
+        EngineSettings::runtime(&Limits::default())
+    }
+
+    #[test]
+    fn compatible() {
+        let compiled = EngineSettings {
+            opt_level: OptLevel::SpeedAndSize,
+            features: WasmFeatures {
+                relaxed_simd: false,
+                ..WasmFeatures::default()
+            },
+            ..runtime()
+        };
+        compiled.check_compatible(&runtime()).expect("should be compatible");
+    }
+
+    #[test]
+    fn incompatible() {
+        let arch = if cfg!(target_arch = "aarch64") { "x86_64" } else { "aarch64" };
+        let cross = EngineSettings {
+            target: format!("{arch}-unknown-linux-gnu"),
+            ..runtime()
+        };
+        let err = cross.check_compatible(&runtime()).expect_err("should be incompatible");
+        assert!(err.to_string().contains("recompile with `compile --target"));
+
+        let fuel = EngineSettings {
+            fuel: true,
+            ..runtime()
+        };
+        let err = fuel.check_compatible(&runtime()).expect_err("should be incompatible");
+        assert!(err.to_string().contains("fuel metering enabled"));
+
+        let host = EngineSettings {
+            features: WasmFeatures {
+                simd: false,
+                ..WasmFeatures::default()
+            },
+            ..runtime()
+        };
+        runtime().check_compatible(&host).expect_err("should be incompatible");
+    }
+
+    #[test]
+    fn round_trip() {
+        let compiled =
+            std::env::temp_dir().join(format!("omnia-settings-{}.bin", std::process::id()));
+        assert_eq!(EngineSettings::read(&compiled).expect("should read"), None);
+
+        let settings = EngineSettings {
+            opt_level: OptLevel::None,
+            core_instances: Some(3),
+            ..runtime()
+        };
+        settings.write(&compiled).expect("should write");
+        let read = EngineSettings::read(&compiled).expect("should read");
+        fs::remove_file(settings_path(&compiled)).expect("should remove");
+
+        assert_eq!(read, Some(settings));
+    }
+}
@@ -0,0 +1,233 @@
+//! # Graceful Shutdown
+//!
+//! Coordinates shutdown of the runtime's servers.
+//!
+//! A [`Shutdown`] is passed to each [`Server`](crate::Server) when it is
+//! started. Once signalled (on `SIGTERM` or `SIGINT`), servers stop accepting
+//! new work and drain in-flight guest invocations for up to the configured
+//! grace period before returning.
+//!
+//! The [`Shutdown`] also carries the runtime-wide [`Concurrency`] limits, so
+//! each server can add its own limit to those shared by every server.
+
+#![allow(missing_docs)]
+
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use fromenv::FromEnv;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::task::TaskTracker;
+
+use crate::{Concurrency, traits};
+
+/// Shutdown configuration.
+#[derive(Debug, Clone, FromEnv)]
+pub struct ShutdownOptions {
+    /// The maximum time (in seconds) to wait for in-flight guest invocations
+    /// to complete once shutdown has been signalled.
+    #[env(from = "SHUTDOWN_GRACE_PERIOD_SECS", default = "30")]
+    pub grace_period_secs: u64,
+}
+
+impl traits::FromEnv for ShutdownOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading shutdown options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        traits::requirement_vars(&Self::requirements())
+    }
+}
+
+/// Shutdown signal shared by the runtime's servers.
+///
+/// Guest invocations spawned using [`Shutdown::spawn`] are tracked so they
+/// can be drained before the runtime exits.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    signal: watch::Receiver<bool>,
+    tracker: TaskTracker,
+    grace_period: Duration,
+    listening: Arc<AtomicBool>,
+    concurrency: Concurrency,
+}
+
+/// Triggers a [`Shutdown`].
+#[derive(Debug)]
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl ShutdownTrigger {
+    /// Signal servers to shut down.
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    /// Create a new shutdown signal with the specified grace period.
+    #[must_use]
+    pub fn new(grace_period: Duration) -> (Self, ShutdownTrigger) {
+        let (tx, rx) = watch::channel(false);
+        let shutdown = Self {
+            signal: rx,
+            tracker: TaskTracker::new(),
+            grace_period,
+            listening: Arc::new(AtomicBool::new(false)),
+            concurrency: Concurrency::default(),
+        };
+        (shutdown, ShutdownTrigger(tx))
+    }
+
+    /// Share the runtime-wide `concurrency` limits with every server.
+    #[must_use]
+    pub fn with_concurrency(self, concurrency: Concurrency) -> Self {
+        Self { concurrency, ..self }
+    }
+
+    /// The runtime-wide limits on in-flight guest invocations.
+    #[must_use]
+    pub const fn concurrency(&self) -> &Concurrency {
+        &self.concurrency
+    }
+
+    /// Create a shutdown signal that is triggered when the process receives
+    /// `SIGTERM` or `SIGINT`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shutdown options cannot be loaded from the
+    /// environment or the signal handlers cannot be installed.
+    pub fn listen() -> Result<Self> {
+        let options = <ShutdownOptions as traits::FromEnv>::from_env()?;
+        let (shutdown, trigger) = Self::new(Duration::from_secs(options.grace_period_secs));
+
+        #[cfg(unix)]
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .context("installing SIGTERM handler")?;
+
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let terminate = terminate.recv();
+            #[cfg(not(unix))]
+            let terminate = std::future::pending::<Option<()>>();
+
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => tracing::info!("received SIGINT"),
+                _ = terminate => tracing::info!("received SIGTERM"),
+            }
+            trigger.trigger();
+        });
+
+        Ok(shutdown)
+    }
+
+    /// Resolves once shutdown has been signalled.
+    pub async fn signalled(&self) {
+        let mut signal = self.signal.clone();
+
+        // an error means the trigger was dropped without signalling
+        if signal.wait_for(|signalled| *signalled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Returns `true` if shutdown has been signalled.
+    #[must_use]
+    pub fn is_signalled(&self) -> bool {
+        *self.signal.borrow()
+    }
+
+    /// The maximum time to wait for in-flight work to drain.
+    #[must_use]
+    pub const fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    /// A copy of the signal for a single server, separately tracking
+    /// whether that server is listening.
+    #[must_use]
+    pub fn for_server(&self) -> Self {
+        Self {
+            listening: Arc::new(AtomicBool::new(false)),
+            ..self.clone()
+        }
+    }
+
+    /// Signal that the server has started accepting work.
+    pub fn listening(&self) {
+        self.listening.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` once the server has started accepting work.
+    #[must_use]
+    pub fn is_listening(&self) -> bool {
+        self.listening.load(Ordering::Acquire)
+    }
+
+    /// Spawn a task that will be drained on shutdown.
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tracker.spawn(task)
+    }
+
+    /// Wait for tracked tasks to complete, up to the grace period.
+    ///
+    /// Returns `false` if the grace period elapsed before all tasks
+    /// completed.
+    pub async fn drain(&self) -> bool {
+        self.tracker.close();
+        if tokio::time::timeout(self.grace_period, self.tracker.wait()).await.is_ok() {
+            return true;
+        }
+        tracing::warn!("grace period elapsed with {} task(s) still in flight", self.tracker.len());
+        false
+    }
+}
+
+/// Flush buffered telemetry before the runtime exits.
+pub fn flush_telemetry() {
+    if let Err(e) = omnia_otel::shutdown() {
+        tracing::warn!("issue flushing telemetry: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drains_in_flight_tasks() {
+        let (shutdown, trigger) = Shutdown::new(Duration::from_secs(5));
+        assert!(!shutdown.is_signalled());
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let task = shutdown.spawn(async move { rx.await.is_ok() });
+
+        trigger.trigger();
+        shutdown.signalled().await;
+        assert!(shutdown.is_signalled());
+
+        // in-flight task completes during the grace period
+        tx.send(()).expect("should send");
+        assert!(shutdown.drain().await);
+        assert!(task.await.expect("should complete"));
+    }
+
+    #[tokio::test]
+    async fn grace_period_elapses() {
+        let (shutdown, trigger) = Shutdown::new(Duration::from_millis(50));
+        shutdown.spawn(std::future::pending::<()>());
+
+        trigger.trigger();
+        assert!(!shutdown.drain().await);
+    }
+}
@@ -10,7 +10,10 @@ use std::future::Future;
 
 use anyhow::Result;
 use futures::future::BoxFuture;
-use wasmtime::component::{InstancePre, Linker};
+use wasmtime::Store;
+use wasmtime::component::{Instance, InstancePre, Linker};
+
+use crate::{Capabilities, Invocation, Limiter, Limits, Routes, Shutdown};
 
 /// Result type for asynchronous operations.
 pub type FutureResult<T> = BoxFuture<'static, Result<T>>;
@@ -20,12 +23,61 @@ pub trait State: Clone + Send + Sync + 'static {
     /// The store context type.
     type StoreCtx: Send;
 
-    /// Returns the store context.
+    /// Returns the store context for the invocation.
     #[must_use]
-    fn store(&self) -> Self::StoreCtx;
+    fn store(&self, invocation: Invocation) -> Self::StoreCtx;
+
+    /// Returns the component's name.
+    fn name(&self) -> &str;
+
+    /// Returns the routes used to select the component for incoming work.
+    fn routes(&self) -> &Routes;
+
+    /// Returns the resources the component may access.
+    fn capabilities(&self) -> &Capabilities;
 
     /// Returns the pre-instantiated component.
-    fn instance_pre(&self) -> &InstancePre<Self::StoreCtx>;
+    ///
+    /// The component may be replaced when the runtime reloads it, so a single
+    /// invocation should use the returned component throughout.
+    #[must_use]
+    fn instance_pre(&self) -> InstancePre<Self::StoreCtx>;
+
+    /// Returns the limits applied to each guest invocation.
+    fn limits(&self) -> &Limits;
+
+    /// Returns a new store for a single guest invocation.
+    ///
+    /// Implementations should apply the runtime's [`Limits`] to the store
+    /// and make the [`Invocation`] available to hosts before returning it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the limits cannot be applied to the store.
+    fn new_store(&self, invocation: Invocation) -> Result<Store<Self::StoreCtx>>;
+
+    /// Returns the resource limiter registered with a store returned by
+    /// [`State::new_store`].
+    fn limiter(ctx: &mut Self::StoreCtx) -> &mut Limiter;
+
+    /// Instantiate the component in a store returned by
+    /// [`State::new_store`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the component cannot be instantiated, including
+    /// [`LimitExceeded`](crate::LimitExceeded) when the guest needs more
+    /// instances than its limit allows.
+    fn instantiate(
+        &self, instance_pre: &InstancePre<Self::StoreCtx>, store: &mut Store<Self::StoreCtx>,
+    ) -> impl Future<Output = Result<Instance>> + Send {
+        async move {
+            match instance_pre.instantiate_async(&mut *store).await {
+                Ok(instance) => Ok(instance),
+                Err(err) => Err(Self::limiter(store.data_mut()).instantiation_failed(err.into())),
+            }
+        }
+    }
 }
 
 /// Implemented by all WASI hosts in order to allow the runtime to link their
@@ -39,17 +91,36 @@ pub trait Host<T>: Debug + Sync + Send {
     fn add_to_linker(linker: &mut Linker<T>) -> Result<()>;
 }
 
+/// Implemented by WASI hosts whose resources are opened by name.
+///
+/// This allows the runtime to route each name (e.g. a SQL connection or
+/// key-value bucket) to a different backend using
+/// [`Backends`](crate::Backends).
+pub trait NamedHost {
+    /// The host's backend context, e.g. `dyn WasiSqlCtx`.
+    type Ctx: ?Sized + Send + Sync;
+}
+
 /// Implemented by WASI hosts that are servers in order to allow the runtime to
 /// start them.
 pub trait Server<S: State>: Debug + Sync + Send {
     /// Start the service.
     ///
     /// This is typically implemented by services that instantiate (or run)
-    /// wasm components.
+    /// wasm components. Servers dispatch incoming work to one of the hosted
+    /// `components` using each component's [`Routes`]. Once `shutdown` has
+    /// been signalled, servers should stop accepting new work, drain
+    /// in-flight guest invocations, and return.
     #[allow(unused_variables)]
-    fn run(&self, state: &S) -> impl Future<Output = Result<()>> {
+    fn run(&self, components: &[S], shutdown: &Shutdown) -> impl Future<Output = Result<()>> {
         async { Ok(()) }
     }
+
+    /// The environment variables read by the server, as for
+    /// [`FromEnv::env_vars`].
+    fn env_vars(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 
 /// Implemented by backend resources to allow the backend to be connected to a
@@ -59,13 +130,39 @@ pub trait Backend: Sized + Sync + Send {
     type ConnectOptions: FromEnv;
 
     /// Connect to the resource.
-    #[must_use]
+    #[must_use = "the backend is not connected until the future is awaited"]
     fn connect() -> impl Future<Output = Result<Self>> {
         async { Self::connect_with(Self::ConnectOptions::from_env()?).await }
     }
 
     /// Connect to the resource with the specified options.
     fn connect_with(options: Self::ConnectOptions) -> impl Future<Output = Result<Self>>;
+
+    /// The backend used by the named component.
+    ///
+    /// Backends are shared between components by default. Backends holding
+    /// per-component state, such as configuration scoped to the component,
+    /// return a copy for the component.
+    #[must_use]
+    fn for_component(&self, component: &str) -> Self
+    where
+        Self: Clone,
+    {
+        let _ = component;
+        self.clone()
+    }
+
+    /// Check the connected resource is healthy, e.g. by pinging it.
+    ///
+    /// Used by the runtime's readiness probe. Backends are assumed healthy
+    /// unless they override this check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing why the resource is unhealthy.
+    fn health(&self) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
 }
 
 /// Trait for creating connection options from environment variables.
@@ -76,4 +173,20 @@ pub trait FromEnv: Sized {
     ///
     /// Returns an error if required environment variables are missing or invalid.
     fn from_env() -> Result<Self>;
+
+    /// The environment variables read by [`FromEnv::from_env`].
+    ///
+    /// Runtime configuration files may only supply known variables, so
+    /// options read from the environment should list them here.
+    #[must_use]
+    fn env_vars() -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The variables listed by the `requirements()` of a type deriving
+/// `fromenv::FromEnv`, e.g. to implement [`FromEnv::env_vars`].
+#[must_use]
+pub fn requirement_vars(requirements: &str) -> Vec<String> {
+    requirements.lines().filter_map(|line| Some(line.split_once('=')?.0.to_string())).collect()
 }
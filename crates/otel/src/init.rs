@@ -18,6 +18,7 @@ use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, Registry};
 
 static RESOURCE: OnceLock<Resource> = OnceLock::new();
+static PROVIDERS: OnceLock<(SdkMeterProvider, SdkTracerProvider)> = OnceLock::new();
 
 /// Telemetry initializer.
 pub struct Telemetry {
@@ -30,6 +31,9 @@ pub struct Telemetry {
 
     /// The OpenTelemetry metrics collection endpoint.
     endpoint: Option<String>,
+
+    /// Additional resource attributes.
+    attributes: Vec<KeyValue>,
 }
 
 impl Telemetry {
@@ -40,6 +44,7 @@ impl Telemetry {
             app_name: name.into(),
             env_name: None,
             endpoint: None,
+            attributes: Vec::new(),
         }
     }
 
@@ -64,6 +69,13 @@ impl Telemetry {
         self
     }
 
+    /// Add a resource attribute.
+    #[must_use]
+    pub fn attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.push(KeyValue::new(key.into(), value.into()));
+        self
+    }
+
     /// Initializes telemetry using the provided configuration.
     ///
     /// # Errors
@@ -91,7 +103,7 @@ impl Telemetry {
         let fmt_layer = tracing_subscriber::fmt::layer();
         let tracer = tracer_provider.tracer(self.app_name);
         let tracing_layer = tracing_opentelemetry::layer().with_tracer(tracer);
-        let metrics_layer = MetricsLayer::new(meter_provider);
+        let metrics_layer = MetricsLayer::new(meter_provider.clone());
 
         // set global default subscriber
         Registry::default()
@@ -101,10 +113,32 @@ impl Telemetry {
             .with(metrics_layer)
             .try_init()?;
 
+        // retain providers so they can be flushed on shutdown
+        PROVIDERS
+            .set((meter_provider, tracer_provider))
+            .map_err(|_providers| anyhow!("telemetry providers already set"))?;
+
         Ok(())
     }
 }
 
+/// Flush and shut down the OpenTelemetry providers, exporting any buffered
+/// spans and metrics.
+///
+/// This is a no-op if telemetry has not been initialized.
+///
+/// # Errors
+///
+/// Returns an error if either provider fails to flush or shut down.
+pub fn shutdown() -> Result<()> {
+    let Some((meter_provider, tracer_provider)) = PROVIDERS.get() else {
+        return Ok(());
+    };
+    tracer_provider.shutdown()?;
+    meter_provider.shutdown()?;
+    Ok(())
+}
+
 fn init_traces(endpoint: Option<&str>) -> Result<SdkTracerProvider> {
     let mut builder = SpanExporter::builder().with_tonic();
     if let Some(endpoint) = endpoint {
@@ -155,6 +189,7 @@ impl From<&Telemetry> for Resource {
                 KeyValue::new("telemetry.sdk.name", "opentelemetry"),
                 KeyValue::new("instrumentation.provider", "opentelemetry"),
             ])
+            .with_attributes(otel.attributes.clone())
             .build()
     }
 }
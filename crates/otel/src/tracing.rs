@@ -2,6 +2,7 @@
 //!
 //! Tracing functionality.
 
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -10,9 +11,12 @@ use std::time::Instant;
 use anyhow::Result;
 use http::{Request, Response};
 use opentelemetry::metrics::Histogram;
+use opentelemetry::propagation::TextMapPropagator;
 use opentelemetry::{KeyValue, global};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
 use tower::{Layer, Service};
-use tracing::Instrument;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 /// Layer that records request latency and emits OpenTelemetry metrics.
 #[derive(Clone)]
@@ -110,3 +114,26 @@ where
         Box::pin(self_fut.instrument(span))
     }
 }
+
+/// Record OpenTelemetry attributes on `span`, such as those describing the
+/// component it was created for.
+///
+/// Attributes are ignored when OpenTelemetry tracing is not initialized.
+pub fn set_attributes<'a>(span: &Span, attributes: impl IntoIterator<Item = (&'a str, &'a str)>) {
+    for (key, value) in attributes {
+        span.set_attribute(key.to_string(), value.to_string());
+    }
+}
+
+/// Continue the trace of an inbound request or message by parenting `span`
+/// on the W3C trace context (`traceparent`) it carried.
+///
+/// Invalid trace contexts are ignored, as is the parent when OpenTelemetry
+/// tracing is not initialized.
+pub fn set_remote_parent(span: &Span, traceparent: &str) {
+    let carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    let cx = TraceContextPropagator::new().extract(&carrier);
+    if let Err(e) = span.set_parent(cx) {
+        tracing::trace!("not setting remote parent: {e}");
+    }
+}
@@ -4,21 +4,34 @@
 
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
-use syn::{Ident, Path};
+use syn::{Ident, LitStr, Path};
 
-use crate::runtime::Config;
+use crate::runtime::{Config, HostBackend};
 
 // Generate the runtime from the configuration.
 pub fn expand(config: &Config) -> syn::Result<TokenStream> {
     let Expanded {
-        context_fields,
+        backend_fields,
+        backend_types,
         store_ctx_fields,
         store_ctx_values,
         host_trait_impls,
         server_trait_impls,
         wasi_view_impls,
+        named_fields,
+        named_values,
+        named_idents,
         main_fn,
     } = Expanded::try_from(config)?;
+    let host_names = host_names(&host_trait_impls);
+    let run_fn = run_fn(&host_names);
+    let env_vars_fn = env_vars_fn(&backend_types, &server_trait_impls);
+    let options = options(&backend_fields, &backend_types);
+    let inspect_fn = inspect_fn(&host_trait_impls, &host_names);
+    let new_fn =
+        new_fn(&backend_fields, &backend_types, &host_trait_impls, &named_values, &named_idents);
+    let start_fn = start_fn(&server_trait_impls);
+    let state_impl = state_impl(&store_ctx_values);
 
     Ok(quote! {
         mod runtime {
@@ -28,80 +41,53 @@ pub fn expand(config: &Config) -> syn::Result<TokenStream> {
             use omnia::anyhow::Context as _;
             use omnia::futures::future::{try_join_all, BoxFuture};
             use omnia::tokio;
+            use omnia::wasmtime::Store;
             use omnia::wasmtime::component::{HasData,InstancePre};
-            use omnia::wasmtime_wasi::{ResourceTable, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
-            use omnia::{Backend, Compiled, Server, State};
+            use omnia::wasmtime_wasi::{ResourceTable, WasiCtx, WasiCtxView, WasiView};
+            use omnia::{
+                ActiveComponent, Attributes, Backend, Capabilities, Compiled, ConfigErrors, FromEnv, Guest, Health, Invocation,
+                Limiter, Limits, Routes, Server, Shutdown, State,
+            };
 
             use super::*;
 
-            /// Run the specified wasm guest using the configured runtime.
-            pub async fn run(wasm: PathBuf) -> Result<()> {
-                let mut compiled = omnia::create(&wasm)
-                    .with_context(|| format!("compiling {}", wasm.display()))?;
-                let run_state = Context::new(&mut compiled)
-                    .await
-                    .context("preparing runtime state")?;
-                run_state.start().await.context("starting runtime services")
-            }
+            #run_fn
 
-            /// Initiator state holding pre-instantiated components and backend connections.
-            #[derive(Clone)]
-            struct Context {
-                instance_pre: InstancePre<StoreCtx>,
-                #(pub #context_fields,)*
-            }
+            #inspect_fn
 
-            impl Context {
-                /// Creates a new runtime state by linking WASI interfaces and connecting to backends.
-                async fn new(compiled: &mut Compiled<StoreCtx>) -> Result<Self> {
-                    // link enabled WASI components
-                    #(compiled.link(#host_trait_impls)?;)*
+            #env_vars_fn
 
-                    Ok(Self {
-                        instance_pre: compiled.pre_instantiate()?,
-                        #(#context_fields::connect().await?,)*
-                    })
-                }
+            #options
 
-                /// Start servers.
-                ///
-                /// N.B. for simplicity, all hosts are "servers" with a default implementation that does nothing.
-                async fn start(&self) -> Result<()> {
-                    let futures: Vec<BoxFuture<'_, Result<()>>> =
-                        vec![#(Box::pin(#server_trait_impls.run(self)),)*];
-                    try_join_all(futures).await?;
-                    Ok(())
-                }
+            /// Initiator state holding a pre-instantiated component and backend connections.
+            #[derive(Clone)]
+            pub struct Context {
+                name: String,
+                routes: Routes,
+                attributes: Attributes,
+                capabilities: Capabilities,
+                instance_pre: ActiveComponent<StoreCtx>,
+                limits: Limits,
+                guest: Guest,
+                #(pub #backend_fields: #backend_types,)*
+                #(#named_fields,)*
             }
 
-            impl State for Context {
-                type StoreCtx = StoreCtx;
-
-                fn instance_pre(&self) -> &InstancePre<Self::StoreCtx> {
-                    &self.instance_pre
-                }
+            impl Context {
+                #new_fn
 
-                fn store(&self) -> Self::StoreCtx {
-                    let wasi_ctx = WasiCtxBuilder::new()
-                        // .inherit_args()
-                        .inherit_env()
-                        .inherit_stdin()
-                        .stdout(tokio::io::stdout())
-                        .stderr(tokio::io::stderr())
-                        .build();
-
-                    StoreCtx {
-                        table: ResourceTable::new(),
-                        wasi: wasi_ctx,
-                        #(#store_ctx_values,)*
-                    }
-                }
+                #start_fn
             }
 
+            #state_impl
+
             /// Per-guest instance data shared between the runtime and the guest.
             pub struct StoreCtx {
                 pub table: ResourceTable,
                 pub wasi: WasiCtx,
+                pub limiter: Limiter,
+                pub capabilities: Capabilities,
+                pub invocation: Invocation,
                 #(pub #store_ctx_fields,)*
             }
 
@@ -124,13 +110,278 @@ pub fn expand(config: &Config) -> syn::Result<TokenStream> {
     })
 }
 
+// Generate the function creating runtime state for each component.
+fn new_fn(
+    backend_fields: &[Ident], backend_types: &[Path], host_trait_impls: &[Path],
+    named_values: &[TokenStream], named_idents: &[Ident],
+) -> TokenStream {
+    quote! {
+        /// Creates runtime state for each component by linking WASI interfaces and
+        /// connecting to backends. Backend connections are shared between components,
+        /// each using the backend returned by `Backend::for_component`.
+        async fn new(
+            compiled: &mut [Compiled<StoreCtx>], options: Options, health: &Health,
+        ) -> Result<Vec<Self>> {
+            #(let #backend_fields = <#backend_types>::connect_with(options.#backend_fields).await?;)*
+            #(health.add_backend(stringify!(#backend_types), &#backend_fields);)*
+            health.connected();
+            #(#named_values)*
+
+            compiled
+                .iter_mut()
+                .map(|compiled| {
+                    // link enabled WASI components
+                    #(compiled.link(#host_trait_impls)?;)*
+
+                    Ok(Self {
+                        name: compiled.name().to_string(),
+                        routes: compiled.routes().clone(),
+                        attributes: compiled.attributes().clone(),
+                        capabilities: compiled.capabilities().clone(),
+                        instance_pre: ActiveComponent::new(
+                            compiled.pre_instantiate()?,
+                            compiled.core_instances(),
+                        ),
+                        limits: compiled.limits().clone(),
+                        guest: compiled.guest().clone(),
+                        #(#backend_fields: #backend_fields.for_component(compiled.name()),)*
+                        #(#named_idents: #named_idents(compiled.name()),)*
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+// Generate the connection options loaded for every backend.
+fn options(backend_fields: &[Ident], backend_types: &[Path]) -> TokenStream {
+    quote! {
+        /// Connection options for each backend.
+        struct Options {
+            #(#backend_fields: <#backend_types as Backend>::ConnectOptions,)*
+        }
+
+        impl Options {
+            /// Load every backend's connection options, recording problems in
+            /// `errors` so they are reported with the runtime's own settings.
+            // `errors` is unused by runtimes without backends
+            #[allow(unused_variables)]
+            fn load(errors: &mut ConfigErrors) -> Option<Self> {
+                #(let #backend_fields = errors.check(
+                    stringify!(#backend_types),
+                    <<#backend_types as Backend>::ConnectOptions as FromEnv>::from_env(),
+                );)*
+                Some(Self {
+                    #(#backend_fields: #backend_fields?,)*
+                })
+            }
+        }
+    }
+}
+
+// Generate the function listing the environment variables the runtime reads.
+fn env_vars_fn(backend_types: &[Path], server_trait_impls: &[TokenStream]) -> TokenStream {
+    quote! {
+        /// The environment variables read by the runtime, its hosts and its backends.
+        pub fn env_vars() -> Vec<String> {
+            let mut vars = omnia::runtime_vars();
+            #(vars.extend(<<#backend_types as Backend>::ConnectOptions as FromEnv>::env_vars());)*
+            #(vars.extend(Server::<Context>::env_vars(&#server_trait_impls));)*
+            vars
+        }
+    }
+}
+
+// Generate the function starting the runtime's servers.
+fn start_fn(server_trait_impls: &[TokenStream]) -> TokenStream {
+    let server_indices = (0..server_trait_impls.len()).map(syn::Index::from);
+
+    quote! {
+        /// Start servers, returning once they have all shut down.
+        ///
+        /// N.B. for simplicity, all hosts are "servers" with a default implementation that does nothing.
+        async fn start(components: &[Self], shutdown: &Shutdown, health: &Health) -> Result<()> {
+            // register every server up front so readiness waits for all of them
+            let shutdowns: Vec<Shutdown> =
+                vec![#(health.register(stringify!(#server_trait_impls), shutdown),)*];
+            let futures: Vec<BoxFuture<'_, Result<()>>> = vec![#(Box::pin(async {
+                let shutdown = &shutdowns[#server_indices];
+                let result = #server_trait_impls.run(components, shutdown).await;
+                // servers that do not listen return immediately
+                shutdown.listening();
+                result
+            }),)*];
+            try_join_all(futures).await?;
+            Ok(())
+        }
+    }
+}
+
+// Generate the `State` implementation for the runtime's `Context`.
+fn state_impl(store_ctx_values: &[TokenStream]) -> TokenStream {
+    quote! {
+        impl State for Context {
+            type StoreCtx = StoreCtx;
+
+            fn name(&self) -> &str {
+                &self.name
+            }
+
+            fn routes(&self) -> &Routes {
+                &self.routes
+            }
+
+            fn capabilities(&self) -> &Capabilities {
+                &self.capabilities
+            }
+
+            fn instance_pre(&self) -> InstancePre<Self::StoreCtx> {
+                self.instance_pre.load()
+            }
+
+            fn limits(&self) -> &Limits {
+                &self.limits
+            }
+
+            fn new_store(&self, invocation: Invocation) -> Result<Store<Self::StoreCtx>> {
+                let mut store = Store::new(self.instance_pre().engine(), self.store(invocation));
+                store.limiter(|ctx| &mut ctx.limiter);
+                self.limits.configure(&mut store)?;
+                Ok(store)
+            }
+
+            fn limiter(ctx: &mut Self::StoreCtx) -> &mut Limiter {
+                &mut ctx.limiter
+            }
+
+            fn store(&self, invocation: Invocation) -> Self::StoreCtx {
+                self.attributes.record(&invocation);
+                StoreCtx {
+                    table: ResourceTable::new(),
+                    // guest output is logged within the invocation's span
+                    wasi: invocation.span.in_scope(|| self.guest.wasi_ctx(&self.name)),
+                    limiter: self.limits.limiter().with_core_instances(self.instance_pre.core_instances()),
+                    capabilities: self.capabilities.clone(),
+                    invocation,
+                    #(#store_ctx_values,)*
+                }
+            }
+        }
+    }
+}
+
+// Generate the function inspecting a component against the runtime.
+fn inspect_fn(host_trait_impls: &[Path], host_names: &[String]) -> TokenStream {
+    quote! {
+        /// Print a component's imports and exports, flagging imports this runtime cannot link.
+        pub fn inspect(wasm: PathBuf) -> Result<()> {
+            // the runtime is not initialized: only the component and its linker are needed
+            let mut compiled = omnia::load::<StoreCtx>(&wasm)?;
+            #(compiled.link(#host_trait_impls)?;)*
+
+            let inspection = compiled.inspect(&[#(#host_names),*]);
+            println!("component: {}\n\n{inspection}", compiled.name());
+            Ok(())
+        }
+    }
+}
+
+// Names of the runtime's hosts, e.g. `WasiHttp`.
+fn host_names(host_trait_impls: &[Path]) -> Vec<String> {
+    host_trait_impls
+        .iter()
+        .filter_map(|host| host.segments.last())
+        .map(|segment| segment.ident.to_string())
+        .collect()
+}
+
+// Generate the runtime's entry point.
+fn run_fn(host_names: &[String]) -> TokenStream {
+    quote! {
+        /// Prepare the specified wasm guests with their hosts and backends, without
+        /// starting any servers (e.g. to drive the guests from tests).
+        pub async fn components(wasm: &[PathBuf]) -> Result<Vec<Context>> {
+            let (mut compiled, options, _) = create(wasm)?;
+            Context::new(&mut compiled, options, &Health::default())
+                .await
+                .context("preparing runtime state")
+        }
+
+        /// Run the specified wasm guests using the configured runtime.
+        pub async fn run(wasm: Vec<PathBuf>) -> Result<()> {
+            let health = Health::default();
+            let (mut compiled, options, concurrency) = create(&wasm)?;
+            let info = omnia::RuntimeInfo::new(
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                compiled.iter().map(|c| c.name().to_string()).collect(),
+                &[#(#host_names),*],
+            );
+
+            let serve = async {
+                let components = Context::new(&mut compiled, options, &health)
+                    .await
+                    .context("preparing runtime state")?;
+                let shutdown =
+                    Shutdown::listen()?.with_concurrency(omnia::Concurrency::new(&concurrency));
+
+                // reload each component when it changes
+                for ((mut compiled, path), component) in compiled.into_iter().zip(wasm).zip(&components) {
+                    let active = component.instance_pre.clone();
+                    let watched = path.clone();
+                    omnia::watch(&watched, &shutdown, move || {
+                        active.swap(compiled.reload(&path)?, compiled.core_instances());
+                        Ok(())
+                    })?;
+                }
+
+                Context::start(&components, &shutdown, &health).await.context("starting runtime services")
+            };
+
+            // the admin endpoint is started first so probes are answered (and
+            // report not ready) while backends connect; run until shutdown,
+            // flushing buffered telemetry before exiting
+            let result = tokio::select! {
+                biased;
+                Err(e) = omnia::serve_admin(&health, info) => Err(e.context("serving admin endpoint")),
+                result = serve => result,
+            };
+            omnia::flush_telemetry();
+            result
+        }
+
+        // Compile the components and load every backend's connection options and
+        // the servers' concurrency limits, reporting problems with the runtime's
+        // settings, the options and the limits together.
+        fn create(
+            wasm: &[PathBuf],
+        ) -> Result<(Vec<Compiled<StoreCtx>>, Options, omnia::ConcurrencyOptions)> {
+            let mut errors = ConfigErrors::default();
+            let options = Options::load(&mut errors);
+            let concurrency = errors.check(
+                "concurrency",
+                <omnia::ConcurrencyOptions as FromEnv>::from_env(),
+            );
+            let compiled = omnia::create_all_with(wasm, errors).context("initializing runtime")?;
+            // options failing to load are reported by `create_all_with`
+            let options = options.context("loading backend connection options")?;
+            let concurrency = concurrency.context("loading concurrency options")?;
+            Ok((compiled, options, concurrency))
+        }
+    }
+}
+
 struct Expanded {
-    context_fields: Vec<TokenStream>,
+    backend_fields: Vec<Ident>,
+    backend_types: Vec<Path>,
     store_ctx_fields: Vec<TokenStream>,
     store_ctx_values: Vec<TokenStream>,
     host_trait_impls: Vec<Path>,
     server_trait_impls: Vec<TokenStream>,
     wasi_view_impls: Vec<TokenStream>,
+    named_fields: Vec<TokenStream>,
+    named_values: Vec<TokenStream>,
+    named_idents: Vec<Ident>,
     main_fn: TokenStream,
 }
 
@@ -139,7 +390,8 @@ impl TryFrom<&Config> for Expanded {
 
     fn try_from(input: &Config) -> Result<Self, Self::Error> {
         // `Context` struct
-        let mut context_fields = Vec::new();
+        let mut backend_fields = Vec::new();
+        let mut backend_types = Vec::new();
         let mut seen_backends = Vec::new();
 
         for backend in &input.backends {
@@ -150,8 +402,8 @@ impl TryFrom<&Config> for Expanded {
             }
             seen_backends.push(backend_str);
 
-            let field = field_ident(backend);
-            context_fields.push(quote! {#field: #backend});
+            backend_fields.push(field_ident(backend));
+            backend_types.push(backend.clone());
         }
 
         let mut store_ctx_fields = Vec::new();
@@ -159,16 +411,36 @@ impl TryFrom<&Config> for Expanded {
         let mut host_trait_impls = Vec::new();
         let mut server_trait_impls = Vec::new();
         let mut wasi_view_impls = Vec::new();
+        let mut named_fields = Vec::new();
+        let mut named_values = Vec::new();
+        let mut named_idents = Vec::new();
 
         for host in &input.hosts {
             let host_type = &host.type_;
             let host_ident = wasi_ident(host_type);
-            let backend_type = &host.backend;
-            let backend_ident = field_ident(backend_type);
 
             host_trait_impls.push(host_type.clone());
-            store_ctx_fields.push(quote! {#host_ident: #backend_type});
-            store_ctx_values.push(quote! {#host_ident: self.#backend_ident.clone()});
+            match &host.backend {
+                HostBackend::Single(backend_type) => {
+                    let backend_ident = field_ident(backend_type);
+                    store_ctx_fields.push(quote! {#host_ident: #backend_type});
+                    store_ctx_values.push(quote! {#host_ident: self.#backend_ident.clone()});
+                }
+                HostBackend::Named { named, default } => {
+                    // backends are routed by name, shared by every store of a component
+                    let ctx = quote! {omnia::Backends<<#host_type as omnia::NamedHost>::Ctx>};
+                    store_ctx_fields.push(quote! {#host_ident: #ctx});
+                    store_ctx_values.push(quote! {#host_ident: self.#host_ident.clone()});
+                    named_fields.push(quote! {#host_ident: #ctx});
+                    named_values.push(named_backends(
+                        &host_ident,
+                        host_type,
+                        named,
+                        default.as_ref(),
+                    ));
+                    named_idents.push(host_ident.clone());
+                }
+            }
 
             // servers
             server_trait_impls.push(quote! {#host_type});
@@ -184,14 +456,22 @@ impl TryFrom<&Config> for Expanded {
         // main function (optional)
         let main_fn = if input.gen_main {
             quote! {
-                use omnia::tokio;
-
-                #[tokio::main]
-                async fn main() -> anyhow::Result<()> {
+                fn main() -> anyhow::Result<()> {
                     use omnia::Parser;
                     match omnia::Cli::parse().command {
-                        omnia::Command::Run { wasm } => runtime::run(wasm).await,
-                        _ => unreachable!(),
+                        omnia::Command::Run { wasm, config } => {
+                            if let Some(config) = config {
+                                let config = omnia::RuntimeConfig::load(&config)?;
+                                let names = wasm.iter().map(|path| omnia::component_name(path));
+                                config.check(&runtime::env_vars(), &names.collect::<Vec<_>>())?;
+                                // SAFETY: the async runtime, and so any other thread, has not
+                                // been started yet.
+                                unsafe { config.apply() };
+                            }
+                            omnia::tokio::runtime::Runtime::new()?.block_on(runtime::run(wasm))
+                        }
+                        omnia::Command::Inspect { wasm } => runtime::inspect(wasm),
+                        command => command.execute(),
                     }
                 }
             }
@@ -200,17 +480,50 @@ impl TryFrom<&Config> for Expanded {
         };
 
         Ok(Self {
-            context_fields,
+            backend_fields,
+            backend_types,
             store_ctx_fields,
             store_ctx_values,
             host_trait_impls,
             server_trait_impls,
             wasi_view_impls,
+            named_fields,
+            named_values,
+            named_idents,
             main_fn,
         })
     }
 }
 
+// Generate a closure returning the backends routed by name for a host, each
+// using the backend returned by `Backend::for_component` for the component.
+fn named_backends(
+    host_ident: &Ident, host_type: &Path, named: &[(LitStr, Path)], default: Option<&Path>,
+) -> TokenStream {
+    let arc = quote! {std::sync::Arc<<#host_type as omnia::NamedHost>::Ctx>};
+    let resources = named.iter().map(|(name, _)| name);
+    let backends = named.iter().map(|(_, backend)| field_ident(backend));
+    let default = default.map_or_else(
+        || quote! {None},
+        |backend| {
+            let backend = field_ident(backend);
+            quote! {Some(std::sync::Arc::new(#backend.for_component(component)) as #arc)}
+        },
+    );
+
+    quote! {
+        let #host_ident = |component: &str| -> omnia::Backends<<#host_type as omnia::NamedHost>::Ctx> {
+            omnia::Backends::new(
+                [#((
+                    #resources.to_string(),
+                    std::sync::Arc::new(#backends.for_component(component)) as #arc
+                )),*],
+                #default,
+            )
+        };
+    }
+}
+
 /// Generates a field name for a backend type.
 fn field_ident(path: &Path) -> Ident {
     let Some(ident) = path.segments.last() else {
@@ -1,14 +1,19 @@
 use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
-use syn::{LitBool, Path, Result, Token};
+use syn::{LitBool, LitStr, Path, Result, Token};
 
 /// Configuration for the runtime macro.
 ///
-/// Parses input in the form of 'host:backend' pairs. For example:
+/// Parses input in the form of 'host:backend' pairs, where a host may
+/// instead route resource names to different backends. For example:
 /// ```ignore
 /// {
 ///     WasiHttp: HttpDefault,
 ///     WasiOtel: DefaultOtel,
+///     WasiSql: {
+///         "orders": SqlPostgres,
+///         _: SqlDefault,
+///     },
 ///     ...
 /// }
 /// ```
@@ -36,11 +41,11 @@ impl Parse for Config {
 
         // deduplicate backends
         let mut backends = vec![];
-        for host in &hosts.0 {
-            if backends.iter().any(|b: &Path| b.get_ident() == host.backend.get_ident()) {
+        for backend in hosts.0.iter().flat_map(|host| host.backend.paths()) {
+            if backends.iter().any(|b: &Path| b.get_ident() == backend.get_ident()) {
                 continue;
             }
-            backends.push(host.backend.clone());
+            backends.push(backend.clone());
         }
 
         Ok(Self {
@@ -93,14 +98,74 @@ impl Parse for Hosts {
 /// Information about a WASI host and its configuration.
 pub struct Host {
     pub type_: Path,
-    pub backend: Path,
+    pub backend: HostBackend,
 }
 
 impl Parse for Host {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let type_ = input.parse::<Path>()?;
         input.parse::<Token![:]>()?;
-        let backend = input.parse::<Path>()?;
+        let backend = input.parse::<HostBackend>()?;
         Ok(Self { type_, backend })
     }
 }
+
+/// The backend(s) used by a host.
+pub enum HostBackend {
+    /// A single backend for every resource.
+    Single(Path),
+
+    /// Backends selected by resource name, with an optional default for
+    /// other names.
+    Named { named: Vec<(LitStr, Path)>, default: Option<Path> },
+}
+
+impl HostBackend {
+    /// All backends used by the host.
+    pub fn paths(&self) -> Vec<&Path> {
+        match self {
+            Self::Single(backend) => vec![backend],
+            Self::Named { named, default } => {
+                named.iter().map(|(_, backend)| backend).chain(default).collect()
+            }
+        }
+    }
+}
+
+impl Parse for HostBackend {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if !input.peek(syn::token::Brace) {
+            return Ok(Self::Single(input.parse()?));
+        }
+
+        let list;
+        let brace = syn::braced!(list in input);
+        let mut named: Vec<(LitStr, Path)> = Vec::new();
+        let mut default = None;
+
+        while !list.is_empty() {
+            if list.peek(Token![_]) {
+                let underscore = list.parse::<Token![_]>()?;
+                list.parse::<Token![:]>()?;
+                if default.replace(list.parse::<Path>()?).is_some() {
+                    return Err(syn::Error::new(underscore.span, "duplicate default backend"));
+                }
+            } else {
+                let name = list.parse::<LitStr>()?;
+                list.parse::<Token![:]>()?;
+                if named.iter().any(|(n, _)| n.value() == name.value()) {
+                    return Err(syn::Error::new(name.span(), "duplicate backend name"));
+                }
+                named.push((name, list.parse()?));
+            }
+            if !list.is_empty() {
+                list.parse::<Token![,]>()?;
+            }
+        }
+
+        if named.is_empty() && default.is_none() {
+            return Err(syn::Error::new(brace.span.join(), "expected at least one backend"));
+        }
+        Ok(Self::Named { named, default })
+    }
+}
@@ -0,0 +1,187 @@
+//! # Omnia Test Guest
+//!
+//! A guest exercising each host, driven by the integration tests in
+//! `omnia-test` and the host crates.
+//!
+//! HTTP routes:
+//!
+//! - `/echo` responds with the request's method, URI, `x-` headers and body,
+//!   one per line.
+//! - `/sleep/{ms}` waits `ms` milliseconds before responding.
+//! - `/spin` loops without calling the host, never responding.
+//! - `/keyvalue/{bucket}`, `/sql/{name}`, `/vault/{locker}`,
+//!   `/blobstore/{container}`, `/identity/{name}` and `/messaging/{topic}`
+//!   use the named resource, writing the request body where the host allows.
+//!
+//! Host errors are returned as a `500` with the error as the body.
+//!
+//! Messages are stored in the `messages` bucket keyed by topic, and websocket
+//! events in the `events` bucket under `last`. Messages on the `slow` topic
+//! are instead stored under `started`, then after 100ms under `finished`.
+
+#![cfg(target_arch = "wasm32")]
+
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::Path;
+use axum::http::{HeaderMap, Method, StatusCode, Uri};
+use axum::routing::{any, get, post};
+use bytes::Bytes;
+use omnia_wasi_blobstore::blobstore;
+use omnia_wasi_blobstore::types::OutgoingValue;
+use omnia_wasi_identity::credentials::get_identity;
+use omnia_wasi_keyvalue::store;
+use omnia_wasi_messaging::producer;
+use omnia_wasi_messaging::types::{Client, Error as MessagingError, Message};
+use omnia_wasi_sql::readwrite;
+use omnia_wasi_sql::types::{Connection, Statement};
+use omnia_wasi_vault::vault;
+use omnia_wasi_websocket::types::{Error as WebSocketError, Event};
+use wasip3::clocks::monotonic_clock;
+use wasip3::exports::http::handler::Guest;
+use wasip3::http::types::{ErrorCode, Request, Response};
+
+type HostResult<T = String> = Result<T, (StatusCode, String)>;
+
+struct Http;
+wasip3::http::service::export!(Http);
+
+impl Guest for Http {
+    async fn handle(request: Request) -> Result<Response, ErrorCode> {
+        let router = Router::new()
+            .route("/echo", any(echo))
+            .route("/sleep/{ms}", get(sleep))
+            .route("/spin", get(spin))
+            .route("/keyvalue/{bucket}", post(keyvalue))
+            .route("/sql/{name}", post(sql))
+            .route("/vault/{locker}", post(vault))
+            .route("/blobstore/{container}", post(blobstore))
+            .route("/identity/{name}", get(identity))
+            .route("/messaging/{topic}", post(messaging));
+        omnia_wasi_http::serve(router, request).await
+    }
+}
+
+fn host_error(e: impl std::fmt::Debug) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"))
+}
+
+async fn echo(method: Method, uri: Uri, headers: HeaderMap, body: Bytes) -> String {
+    let mut lines = vec![method.to_string(), uri.to_string()];
+    let mut headers = headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-"))
+        .map(|(name, value)| format!("{name}: {}", String::from_utf8_lossy(value.as_bytes())))
+        .collect::<Vec<_>>();
+    headers.sort();
+    lines.extend(headers);
+    lines.push(String::from_utf8_lossy(&body).to_string());
+    lines.join("\n")
+}
+
+async fn sleep(Path(ms): Path<u64>) -> String {
+    wait(Duration::from_millis(ms)).await;
+    format!("slept {ms}ms")
+}
+
+async fn spin() -> String {
+    let mut n = 0_u64;
+    loop {
+        n = std::hint::black_box(n.wrapping_add(1));
+    }
+}
+
+async fn wait(duration: Duration) {
+    monotonic_clock::wait_for(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)).await;
+}
+
+async fn keyvalue(Path(bucket): Path<String>, body: Bytes) -> HostResult {
+    let bucket = store::open(bucket).await.map_err(host_error)?;
+    bucket.set("body".to_string(), body.to_vec()).await.map_err(host_error)?;
+    Ok("stored".to_string())
+}
+
+async fn sql(Path(name): Path<String>) -> HostResult {
+    let connection = Connection::open(name).await.map_err(|e| host_error(e.trace()))?;
+    let statement = Statement::prepare("SELECT 1".to_string(), Vec::new())
+        .await
+        .map_err(|e| host_error(e.trace()))?;
+    let rows =
+        readwrite::query(&connection, &statement).await.map_err(|e| host_error(e.trace()))?;
+    Ok(format!("{} rows", rows.len()))
+}
+
+async fn vault(Path(locker): Path<String>, body: Bytes) -> HostResult {
+    let locker = vault::open(locker).await.map_err(host_error)?;
+    locker.set("body".to_string(), body.to_vec()).await.map_err(host_error)?;
+    Ok("stored".to_string())
+}
+
+async fn blobstore(Path(container): Path<String>, body: Bytes) -> HostResult {
+    let container = blobstore::create_container(container).await.map_err(host_error)?;
+    let outgoing = OutgoingValue::new_outgoing_value();
+    let stream = outgoing.outgoing_value_write_body().await.map_err(host_error)?;
+    stream.blocking_write_and_flush(&body).map_err(host_error)?;
+    drop(stream);
+    container.write_data("body".to_string(), &outgoing).await.map_err(host_error)?;
+    OutgoingValue::finish(outgoing).map_err(host_error)?;
+    Ok("stored".to_string())
+}
+
+async fn identity(Path(name): Path<String>) -> HostResult {
+    wit_bindgen::block_on(get_identity(name)).map_err(host_error)?;
+    Ok("found".to_string())
+}
+
+async fn messaging(Path(topic): Path<String>, body: Bytes) -> HostResult {
+    let client = Client::connect("default".to_string()).await.map_err(host_error)?;
+    let message = Message::new(&body);
+    wit_bindgen::block_on(producer::send(&client, topic, message)).map_err(host_error)?;
+    Ok("sent".to_string())
+}
+
+struct Messaging;
+omnia_wasi_messaging::export!(Messaging with_types_in omnia_wasi_messaging);
+
+impl omnia_wasi_messaging::incoming_handler::Guest for Messaging {
+    async fn handle(message: Message) -> Result<(), MessagingError> {
+        let topic = message.topic().unwrap_or_default();
+        let bucket = store::open("messages".to_string())
+            .await
+            .map_err(|e| MessagingError::Other(format!("{e:?}")))?;
+        if topic != "slow" {
+            return bucket
+                .set(topic, message.data())
+                .await
+                .map_err(|e| MessagingError::Other(format!("{e:?}")));
+        }
+
+        // record when handling starts and finishes, so overlapping
+        // invocations can be detected
+        bucket
+            .set("started".to_string(), message.data())
+            .await
+            .map_err(|e| MessagingError::Other(format!("{e:?}")))?;
+        wait(Duration::from_millis(100)).await;
+        bucket
+            .set("finished".to_string(), message.data())
+            .await
+            .map_err(|e| MessagingError::Other(format!("{e:?}")))
+    }
+}
+
+struct WebSocket;
+omnia_wasi_websocket::export!(WebSocket);
+
+impl omnia_wasi_websocket::handler::Guest for WebSocket {
+    async fn handle(event: Event) -> Result<(), WebSocketError> {
+        let bucket = store::open("events".to_string())
+            .await
+            .map_err(|e| WebSocketError::Other(format!("{e:?}")))?;
+        bucket
+            .set("last".to_string(), event.data())
+            .await
+            .map_err(|e| WebSocketError::Other(format!("{e:?}")))
+    }
+}
@@ -0,0 +1,66 @@
+//! Instantiating the guest for each HTTP request.
+//!
+//! Run once with on-demand allocation, then with the pooling allocator
+//! enabled, and criterion reports the change between them:
+//!
+//! ```bash
+//! cargo bench -p omnia-test --bench instantiation
+//! GUEST_POOL_INSTANCES=100 cargo bench -p omnia-test --bench instantiation
+//! ```
+
+#![cfg(not(target_arch = "wasm32"))]
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::path::PathBuf;
+
+use criterion::Criterion;
+use omnia_test::Harness;
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+use tokio::runtime::Runtime;
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+fn http_request(c: &mut Criterion, guest: PathBuf) {
+    let executor = Runtime::new().expect("should create tokio runtime");
+    let components =
+        executor.block_on(runtime::components(&[guest])).expect("should load components");
+    let harness = Harness::new(components);
+
+    c.bench_function("http request", |b| {
+        b.to_async(&executor).iter(|| async {
+            let request = http::Request::get("http://localhost/echo").body("").unwrap();
+            let response = harness.http(request).await.expect("should handle request");
+            assert_eq!(response.status(), 200);
+        });
+    });
+}
+
+fn main() {
+    // started again with the runtime's environment, before any threads
+    let guest = common::rerun(&[], "instantiation");
+
+    let mut criterion = Criterion::default().configure_from_args();
+    http_request(&mut criterion, guest);
+    criterion.final_summary();
+}
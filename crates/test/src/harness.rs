@@ -0,0 +1,148 @@
+//! # Harness
+//!
+//! Drives guests' `wasi:http`, `wasi:messaging` and `omnia:websocket`
+//! handlers in-process.
+
+use std::any::Any;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use http::header::HOST;
+use http::{HeaderValue, Request, Response};
+use http_body_util::{BodyExt, Full};
+use omnia::State;
+use omnia_wasi_http::{ErrorCode, WasiHttpView};
+use omnia_wasi_messaging::{Message, MessageProxy, Metadata, Reply, WasiMessagingView};
+use omnia_wasi_websocket::{Event, EventProxy, WebSocketView};
+
+/// Drives guests prepared by a runtime's generated `components` function.
+#[derive(Clone)]
+pub struct Harness<S: State> {
+    components: Vec<S>,
+}
+
+impl<S: State> Harness<S> {
+    /// Create a harness for the prepared components.
+    #[must_use]
+    pub const fn new(components: Vec<S>) -> Self {
+        Self { components }
+    }
+
+    /// The prepared components.
+    #[must_use]
+    pub fn components(&self) -> &[S] {
+        &self.components
+    }
+
+    /// Call the `wasi:http` handler of the component routed the request,
+    /// returning the response with its body collected.
+    ///
+    /// A `Host` header is added from the request's URI when missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the response body cannot be read.
+    pub async fn http(&self, request: Request<impl Into<Bytes>>) -> Result<Response<Bytes>>
+    where
+        S::StoreCtx: WasiHttpView,
+    {
+        let (mut parts, body) = request.into_parts();
+        if !parts.headers.contains_key(HOST) {
+            let host = parts.uri.authority().map_or("localhost", |authority| authority.as_str());
+            parts.headers.insert(HOST, HeaderValue::from_str(host).context("invalid host")?);
+        }
+        let body =
+            Full::new(body.into()).map_err(|never: Infallible| -> ErrorCode { match never {} });
+
+        let response =
+            omnia_wasi_http::handle(&self.components, Request::from_parts(parts, body)).await;
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await.context("reading response body")?.to_bytes();
+        Ok(Response::from_parts(parts, body))
+    }
+
+    /// Deliver a message published on `topic` to the `incoming-handler` of
+    /// each component handling the topic.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no component handles the topic or a component
+    /// fails to handle the message.
+    pub async fn message(&self, topic: &str, payload: impl Into<Vec<u8>>) -> Result<()>
+    where
+        S::StoreCtx: WasiMessagingView,
+    {
+        let message = TestMessage {
+            topic: topic.to_string(),
+            payload: payload.into(),
+        };
+        omnia_wasi_messaging::deliver(&self.components, MessageProxy(Arc::new(message))).await
+    }
+
+    /// Deliver a websocket event carrying `data` to the component's event
+    /// handler.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the component fails to handle the event.
+    pub async fn websocket(&self, data: impl Into<Vec<u8>>) -> Result<()>
+    where
+        S::StoreCtx: WebSocketView,
+    {
+        let event = TestEvent { data: data.into() };
+        omnia_wasi_websocket::deliver(&self.components, EventProxy(Arc::new(event))).await
+    }
+}
+
+#[derive(Debug)]
+struct TestMessage {
+    topic: String,
+    payload: Vec<u8>,
+}
+
+impl Message for TestMessage {
+    fn topic(&self) -> String {
+        self.topic.clone()
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        self.payload.clone()
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        None
+    }
+
+    fn description(&self) -> Option<String> {
+        None
+    }
+
+    fn length(&self) -> usize {
+        self.payload.len()
+    }
+
+    fn reply(&self) -> Option<Reply> {
+        None
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct TestEvent {
+    data: Vec<u8>,
+}
+
+impl Event for TestEvent {
+    fn socket_addr(&self) -> Option<&str> {
+        None
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
@@ -0,0 +1,17 @@
+#![doc = include_str!("../README.md")]
+
+//! # Omnia Test
+//!
+//! An in-process harness for integration-testing guests. Guests are driven
+//! through the same handlers as the runtime's servers, without binding
+//! sockets or publishing on a broker, and the side effects they have on
+//! key-value, SQL and blob backends are recorded for assertions.
+
+#![forbid(unsafe_code)]
+#![cfg(not(target_arch = "wasm32"))]
+
+mod harness;
+mod recording;
+
+pub use self::harness::Harness;
+pub use self::recording::{BlobstoreRecorder, Effect, KeyValueRecorder, Recording, SqlRecorder};
@@ -0,0 +1,487 @@
+//! # Recording
+//!
+//! Backends recording the side effects guests have on key-value, SQL and
+//! blob stores. Each recorder wraps the corresponding in-memory default
+//! backend, so guests read back what they wrote, and records successful
+//! mutations in its [`Recording`].
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::Result;
+use futures::FutureExt;
+use omnia::Backend;
+use omnia_wasi_blobstore::{
+    BlobstoreDefault, Container, ContainerMetadata, FutureResult, ObjectMetadata, WasiBlobstoreCtx,
+};
+use omnia_wasi_keyvalue::{Bucket, KeyValueDefault, WasiKeyValueCtx};
+use omnia_wasi_sql::{Connection, DataType, Row, SqlDefault, WasiSqlCtx};
+
+/// A side effect a guest had on a backend.
+#[derive(Debug, Clone)]
+pub enum Effect {
+    /// A key-value entry was set.
+    KeyValueSet {
+        /// The bucket identifier.
+        bucket: String,
+        /// The entry's key.
+        key: String,
+        /// The entry's new value.
+        value: Vec<u8>,
+    },
+
+    /// A key-value entry was deleted.
+    KeyValueDelete {
+        /// The bucket identifier.
+        bucket: String,
+        /// The entry's key.
+        key: String,
+    },
+
+    /// A SQL statement not returning rows was executed.
+    SqlExec {
+        /// The connection name.
+        connection: String,
+        /// The statement.
+        query: String,
+        /// The statement's parameters.
+        params: Vec<DataType>,
+    },
+
+    /// A blob was written.
+    BlobWrite {
+        /// The container name.
+        container: String,
+        /// The blob's name.
+        name: String,
+        /// The blob's data.
+        data: Vec<u8>,
+    },
+
+    /// A blob was deleted.
+    BlobDelete {
+        /// The container name.
+        container: String,
+        /// The blob's name.
+        name: String,
+    },
+}
+
+/// The side effects recorded by a recorder, in the order they happened.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    effects: Arc<Mutex<Vec<Effect>>>,
+}
+
+impl Recording {
+    /// The recorded effects.
+    #[must_use]
+    pub fn effects(&self) -> Vec<Effect> {
+        self.effects.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Forget the recorded effects, e.g. between test cases.
+    pub fn clear(&self) {
+        self.effects.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// The value of the key-value entry after the recorded effects, or `None`
+    /// if it was never set or has been deleted.
+    #[must_use]
+    pub fn key_value(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        self.effects().into_iter().fold(None, |current, effect| match effect {
+            Effect::KeyValueSet {
+                bucket: b,
+                key: k,
+                value,
+            } if b == bucket && k == key => Some(value),
+            Effect::KeyValueDelete { bucket: b, key: k } if b == bucket && k == key => None,
+            _ => current,
+        })
+    }
+
+    /// The SQL statements executed, in order.
+    #[must_use]
+    pub fn sql_statements(&self) -> Vec<String> {
+        self.effects()
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::SqlExec { query, .. } => Some(query),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The data of the blob after the recorded effects, or `None` if it was
+    /// never written or has been deleted.
+    #[must_use]
+    pub fn blob(&self, container: &str, name: &str) -> Option<Vec<u8>> {
+        self.effects().into_iter().fold(None, |current, effect| match effect {
+            Effect::BlobWrite {
+                container: c,
+                name: n,
+                data,
+            } if c == container && n == name => Some(data),
+            Effect::BlobDelete {
+                container: c,
+                name: n,
+            } if c == container && n == name => None,
+            _ => current,
+        })
+    }
+
+    fn record(&self, effect: Effect) {
+        self.effects.lock().unwrap_or_else(PoisonError::into_inner).push(effect);
+    }
+}
+
+/// Key-value backend recording the entries guests set and delete.
+#[derive(Debug, Clone)]
+pub struct KeyValueRecorder {
+    inner: KeyValueDefault,
+    recording: Recording,
+}
+
+impl KeyValueRecorder {
+    /// The effects recorded by the backend.
+    #[must_use]
+    pub const fn recording(&self) -> &Recording {
+        &self.recording
+    }
+}
+
+impl Backend for KeyValueRecorder {
+    type ConnectOptions = <KeyValueDefault as Backend>::ConnectOptions;
+
+    async fn connect_with(options: Self::ConnectOptions) -> Result<Self> {
+        Ok(Self {
+            inner: KeyValueDefault::connect_with(options).await?,
+            recording: Recording::default(),
+        })
+    }
+}
+
+impl WasiKeyValueCtx for KeyValueRecorder {
+    fn open_bucket(&self, identifier: String) -> FutureResult<Arc<dyn Bucket>> {
+        let bucket = self.inner.open_bucket(identifier.clone());
+        let recording = self.recording.clone();
+        async move {
+            let bucket = RecordedBucket {
+                inner: bucket.await?,
+                identifier,
+                recording,
+            };
+            Ok(Arc::new(bucket) as Arc<dyn Bucket>)
+        }
+        .boxed()
+    }
+}
+
+#[derive(Debug)]
+struct RecordedBucket {
+    inner: Arc<dyn Bucket>,
+    identifier: String,
+    recording: Recording,
+}
+
+impl Bucket for RecordedBucket {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get(&self, key: String) -> FutureResult<Option<Vec<u8>>> {
+        self.inner.get(key)
+    }
+
+    fn set(&self, key: String, value: Vec<u8>) -> FutureResult<()> {
+        let set = self.inner.set(key.clone(), value.clone());
+        let recording = self.recording.clone();
+        let bucket = self.identifier.clone();
+        async move {
+            set.await?;
+            recording.record(Effect::KeyValueSet { bucket, key, value });
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn delete(&self, key: String) -> FutureResult<()> {
+        let delete = self.inner.delete(key.clone());
+        let recording = self.recording.clone();
+        let bucket = self.identifier.clone();
+        async move {
+            delete.await?;
+            recording.record(Effect::KeyValueDelete { bucket, key });
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn exists(&self, key: String) -> FutureResult<bool> {
+        self.inner.exists(key)
+    }
+
+    fn keys(&self) -> FutureResult<Vec<String>> {
+        self.inner.keys()
+    }
+}
+
+/// SQL backend recording the statements guests execute.
+#[derive(Debug, Clone)]
+pub struct SqlRecorder {
+    inner: SqlDefault,
+    recording: Recording,
+}
+
+impl SqlRecorder {
+    /// The effects recorded by the backend.
+    #[must_use]
+    pub const fn recording(&self) -> &Recording {
+        &self.recording
+    }
+}
+
+impl Backend for SqlRecorder {
+    type ConnectOptions = <SqlDefault as Backend>::ConnectOptions;
+
+    async fn connect_with(options: Self::ConnectOptions) -> Result<Self> {
+        Ok(Self {
+            inner: SqlDefault::connect_with(options).await?,
+            recording: Recording::default(),
+        })
+    }
+
+    async fn health(&self) -> Result<()> {
+        self.inner.health().await
+    }
+}
+
+impl WasiSqlCtx for SqlRecorder {
+    fn open(&self, name: String) -> FutureResult<Arc<dyn Connection>> {
+        let connection = self.inner.open(name.clone());
+        let recording = self.recording.clone();
+        async move {
+            let connection = RecordedConnection {
+                inner: connection.await?,
+                name,
+                recording,
+            };
+            Ok(Arc::new(connection) as Arc<dyn Connection>)
+        }
+        .boxed()
+    }
+}
+
+#[derive(Debug)]
+struct RecordedConnection {
+    inner: Arc<dyn Connection>,
+    name: String,
+    recording: Recording,
+}
+
+impl Connection for RecordedConnection {
+    fn query(&self, query: String, params: Vec<DataType>) -> FutureResult<Vec<Row>> {
+        self.inner.query(query, params)
+    }
+
+    fn exec(&self, query: String, params: Vec<DataType>) -> FutureResult<u32> {
+        let exec = self.inner.exec(query.clone(), params.clone());
+        let recording = self.recording.clone();
+        let connection = self.name.clone();
+        async move {
+            let affected = exec.await?;
+            recording.record(Effect::SqlExec {
+                connection,
+                query,
+                params,
+            });
+            Ok(affected)
+        }
+        .boxed()
+    }
+}
+
+/// Blobstore backend recording the blobs guests write and delete.
+#[derive(Debug, Clone)]
+pub struct BlobstoreRecorder {
+    inner: BlobstoreDefault,
+    recording: Recording,
+}
+
+impl BlobstoreRecorder {
+    /// The effects recorded by the backend.
+    #[must_use]
+    pub const fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    fn recorded(
+        &self, container: FutureResult<Arc<dyn Container>>, name: String,
+    ) -> FutureResult<Arc<dyn Container>> {
+        let recording = self.recording.clone();
+        async move {
+            let container = RecordedContainer {
+                inner: container.await?,
+                name,
+                recording,
+            };
+            Ok(Arc::new(container) as Arc<dyn Container>)
+        }
+        .boxed()
+    }
+}
+
+impl Backend for BlobstoreRecorder {
+    type ConnectOptions = <BlobstoreDefault as Backend>::ConnectOptions;
+
+    async fn connect_with(options: Self::ConnectOptions) -> Result<Self> {
+        Ok(Self {
+            inner: BlobstoreDefault::connect_with(options).await?,
+            recording: Recording::default(),
+        })
+    }
+}
+
+impl WasiBlobstoreCtx for BlobstoreRecorder {
+    fn create_container(&self, name: String) -> FutureResult<Arc<dyn Container>> {
+        self.recorded(self.inner.create_container(name.clone()), name)
+    }
+
+    fn get_container(&self, name: String) -> FutureResult<Arc<dyn Container>> {
+        self.recorded(self.inner.get_container(name.clone()), name)
+    }
+
+    fn delete_container(&self, name: String) -> FutureResult<()> {
+        self.inner.delete_container(name)
+    }
+
+    fn container_exists(&self, name: String) -> FutureResult<bool> {
+        self.inner.container_exists(name)
+    }
+}
+
+#[derive(Debug)]
+struct RecordedContainer {
+    inner: Arc<dyn Container>,
+    name: String,
+    recording: Recording,
+}
+
+impl Container for RecordedContainer {
+    fn name(&self) -> Result<String> {
+        self.inner.name()
+    }
+
+    fn info(&self) -> Result<ContainerMetadata> {
+        self.inner.info()
+    }
+
+    fn get_data(&self, name: String, start: u64, end: u64) -> FutureResult<Option<Vec<u8>>> {
+        self.inner.get_data(name, start, end)
+    }
+
+    fn write_data(&self, name: String, data: Vec<u8>) -> FutureResult<()> {
+        let write = self.inner.write_data(name.clone(), data.clone());
+        let recording = self.recording.clone();
+        let container = self.name.clone();
+        async move {
+            write.await?;
+            recording.record(Effect::BlobWrite {
+                container,
+                name,
+                data,
+            });
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn list_objects(&self) -> FutureResult<Vec<String>> {
+        self.inner.list_objects()
+    }
+
+    fn delete_object(&self, name: String) -> FutureResult<()> {
+        let delete = self.inner.delete_object(name.clone());
+        let recording = self.recording.clone();
+        let container = self.name.clone();
+        async move {
+            delete.await?;
+            recording.record(Effect::BlobDelete { container, name });
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn has_object(&self, name: String) -> FutureResult<bool> {
+        self.inner.has_object(name)
+    }
+
+    fn object_info(&self, name: String) -> FutureResult<ObjectMetadata> {
+        self.inner.object_info(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use omnia::FromEnv;
+
+    use super::*;
+
+    async fn connect<B: Backend>() -> B {
+        let options = B::ConnectOptions::from_env().expect("options");
+        B::connect_with(options).await.expect("connect")
+    }
+
+    #[tokio::test]
+    async fn records_key_values() {
+        let backend = connect::<KeyValueRecorder>().await;
+        let bucket = backend.open_bucket("cache".to_string()).await.expect("open");
+
+        bucket.set("a".to_string(), b"1".to_vec()).await.expect("set");
+        bucket.set("b".to_string(), b"2".to_vec()).await.expect("set");
+        bucket.delete("b".to_string()).await.expect("delete");
+
+        let recording = backend.recording();
+        assert_eq!(recording.effects().len(), 3);
+        assert_eq!(recording.key_value("cache", "a"), Some(b"1".to_vec()));
+        assert_eq!(recording.key_value("cache", "b"), None);
+
+        recording.clear();
+        assert!(recording.effects().is_empty());
+    }
+
+    #[tokio::test]
+    async fn records_sql() {
+        let backend = connect::<SqlRecorder>().await;
+        let connection = backend.open("db".to_string()).await.expect("open");
+
+        connection
+            .exec("CREATE TABLE recorded (id INTEGER)".to_string(), vec![])
+            .await
+            .expect("create");
+        connection
+            .exec(
+                "INSERT INTO recorded (id) VALUES (?)".to_string(),
+                vec![DataType::Int32(Some(1))],
+            )
+            .await
+            .expect("insert");
+        connection.query("SELECT id FROM recorded".to_string(), vec![]).await.expect("select");
+
+        assert_eq!(
+            backend.recording().sql_statements(),
+            ["CREATE TABLE recorded (id INTEGER)", "INSERT INTO recorded (id) VALUES (?)"]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_blobs() {
+        let backend = connect::<BlobstoreRecorder>().await;
+        let container = backend.create_container("images".to_string()).await.expect("create");
+
+        container.write_data("logo".to_string(), b"png".to_vec()).await.expect("write");
+        assert_eq!(backend.recording().blob("images", "logo"), Some(b"png".to_vec()));
+
+        container.delete_object("logo".to_string()).await.expect("delete");
+        assert_eq!(backend.recording().blob("images", "logo"), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Turning away or holding back work while too many guest invocations are
+//! in flight.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::num::NonZeroUsize;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use omnia::{Concurrency, ConcurrencyOptions, Server, Shutdown};
+use omnia_test::{Effect, Harness, KeyValueRecorder};
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::WasiKeyValue;
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+use reqwest::StatusCode;
+use reqwest::header::RETRY_AFTER;
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueRecorder,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// Serve the guest, handling one HTTP request at a time and asking callers
+// turned away to retry after 7 seconds.
+fn url(path: &str) -> String {
+    static ADDR: OnceLock<String> = OnceLock::new();
+    let addr = ADDR.get_or_init(|| {
+        let addr = common::free_addr();
+        let vars =
+            [("HTTP_ADDR", addr.as_str()), ("HTTP_MAX_IN_FLIGHT", "1"), ("RETRY_AFTER_SECS", "7")];
+        common::serve(&addr, &vars, "backpressure");
+        addr
+    });
+    format!("http://{addr}{path}")
+}
+
+#[tokio::test]
+async fn sheds_requests_over_limit() -> anyhow::Result<()> {
+    let busy = tokio::spawn(reqwest::get(url("/sleep/1000")));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let response = reqwest::get(url("/echo")).await?;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers().get(RETRY_AFTER).map(|v| v.to_str()).transpose()?, Some("7"));
+
+    // the in-flight request is unaffected, and frees its slot once done
+    assert_eq!(busy.await??.status(), StatusCode::OK);
+    assert_eq!(reqwest::get(url("/echo")).await?.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn holds_back_messages_over_limit() -> anyhow::Result<()> {
+    let Some(guest) = common::isolate(&[], "backpressure") else {
+        return Ok(());
+    };
+    let components = runtime::components(&[guest]).await?;
+    let recording = components[0].key_value_recorder.recording().clone();
+    let harness = Harness::new(components.clone());
+
+    // run the messaging server, handling one message at a time
+    let options = ConcurrencyOptions {
+        max_in_flight: NonZeroUsize::new(1),
+        http_max_in_flight: None,
+        messaging_max_in_flight: None,
+        websocket_max_in_flight: None,
+        cron_max_in_flight: None,
+        retry_after_secs: 1,
+    };
+    let (shutdown, trigger) = Shutdown::new(Duration::from_secs(5));
+    let shutdown = shutdown.with_concurrency(Concurrency::new(&options));
+    let server = {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { WasiMessaging.run(&components, &shutdown).await })
+    };
+    while !shutdown.is_listening() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // publish messages the guest takes 100ms to handle
+    for n in 0..3 {
+        let request = http::Request::post("http://localhost/messaging/slow").body(n.to_string())?;
+        assert_eq!(harness.http(request).await?.status(), 200);
+    }
+    for _ in 0..50 {
+        if recording.effects().len() == 6 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    trigger.trigger();
+    server.await??;
+
+    // each message is handled once the previous has finished
+    let handled = recording
+        .effects()
+        .into_iter()
+        .filter_map(|effect| match effect {
+            Effect::KeyValueSet { key, value, .. } => {
+                Some(format!("{key} {}", String::from_utf8_lossy(&value)))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        handled,
+        ["started 0", "finished 0", "started 1", "finished 1", "started 2", "finished 2"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_invalid_server_limit() -> anyhow::Result<()> {
+    let Some(guest) = common::isolate(&[("MESSAGING_MAX_IN_FLIGHT", "0")], "backpressure") else {
+        return Ok(());
+    };
+
+    // reported with the runtime's settings, before any server starts
+    let Err(err) = runtime::components(&[guest]).await else {
+        panic!("should reject a limit of 0");
+    };
+    assert!(format!("{err:#}").contains("concurrency"), "{err:#}");
+
+    Ok(())
+}
+
+// The runtime started by `common::serve`.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "started by `common::serve`"]
+async fn serve() -> anyhow::Result<()> {
+    match common::served() {
+        Some(guest) => runtime::run(vec![guest]).await,
+        None => Ok(()),
+    }
+}
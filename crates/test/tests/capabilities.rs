@@ -0,0 +1,150 @@
+//! Hosts denying resources the capability policy does not grant.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::path::Path;
+use std::sync::OnceLock;
+use std::{fs, process};
+
+use omnia_test::Harness;
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+const POLICY: &str = r#"
+[components.orders]
+buckets = ["orders", "messages"]
+sql = ["orders"]
+send = ["orders"]
+subscribe = ["orders"]
+lockers = ["orders"]
+identities = ["orders"]
+containers = ["orders"]
+"#;
+
+// The `orders` component, only granted resources named `orders`, when run in
+// the test's own process (see `common::isolate`).
+async fn harness() -> anyhow::Result<Option<Harness<runtime::Context>>> {
+    static POLICY_FILE: OnceLock<String> = OnceLock::new();
+    let policy = POLICY_FILE.get_or_init(|| {
+        // replaced atomically, as tests' processes may be reading it
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+        let written = dir.join(format!("capabilities.toml.{}", process::id()));
+        fs::write(&written, POLICY).expect("should write policy");
+        let path = dir.join("capabilities.toml");
+        fs::rename(written, &path).expect("should replace policy");
+        path.display().to_string()
+    });
+
+    let Some(guest) = common::isolate(&[("CAPABILITY_POLICY", policy)], "orders") else {
+        return Ok(None);
+    };
+    Ok(Some(Harness::new(runtime::components(&[guest]).await?)))
+}
+
+// Call `method` on the guest route for `host`, using the resource `name`.
+async fn call(
+    harness: &Harness<runtime::Context>, method: &str, host: &str, name: &str,
+) -> anyhow::Result<(u16, String)> {
+    let request = http::Request::builder()
+        .method(method)
+        .uri(format!("http://localhost/{host}/{name}"))
+        .body("data")?;
+    let response = harness.http(request).await?;
+    Ok((response.status().as_u16(), String::from_utf8_lossy(response.body()).to_string()))
+}
+
+async fn assert_denied(host: &str, method: &str) -> anyhow::Result<()> {
+    assert_denied_with(host, method, "denied").await
+}
+
+// Interfaces without an access denied error mark denials by their message.
+async fn assert_denied_with(host: &str, method: &str, message: &str) -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+
+    let (status, body) = call(&harness, method, host, "orders").await?;
+    assert_eq!(status, 200, "{host} orders: {body}");
+
+    let (status, body) = call(&harness, method, host, "payments").await?;
+    assert_eq!(status, 500, "{host} payments: {body}");
+    assert!(body.to_lowercase().contains(message), "{host} payments: {body}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn keyvalue_bucket_denied() -> anyhow::Result<()> {
+    assert_denied("keyvalue", "POST").await
+}
+
+#[tokio::test]
+async fn sql_connection_denied() -> anyhow::Result<()> {
+    assert_denied_with(
+        "sql",
+        "POST",
+        "access denied by capability policy: sql connection `payments`",
+    )
+    .await
+}
+
+#[tokio::test]
+async fn vault_locker_denied() -> anyhow::Result<()> {
+    assert_denied("vault", "POST").await
+}
+
+#[tokio::test]
+async fn blobstore_container_denied() -> anyhow::Result<()> {
+    assert_denied_with(
+        "blobstore",
+        "POST",
+        "access denied by capability policy: blobstore container `payments`",
+    )
+    .await
+}
+
+#[tokio::test]
+async fn identity_denied() -> anyhow::Result<()> {
+    assert_denied_with("identity", "GET", "access denied by capability policy: identity `payments`")
+        .await
+}
+
+#[tokio::test]
+async fn messaging_send_denied() -> anyhow::Result<()> {
+    assert_denied("messaging", "POST").await
+}
+
+#[tokio::test]
+async fn messaging_subscribe_denied() -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+
+    harness.message("orders", "order 1").await?;
+    let err = harness.message("payments", "payment 1").await.unwrap_err();
+    assert!(err.to_string().contains("no component handles topic"), "{err}");
+
+    Ok(())
+}
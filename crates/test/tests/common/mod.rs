@@ -0,0 +1,171 @@
+//! Helpers for tests driving the test guest built from `crates/test-guest`.
+//!
+//! The runtime is configured from its environment, which cannot be safely
+//! modified while tests are running. The runtime is instead run in a process
+//! of its own, started with the environment it needs: [`isolate`] re-runs a
+//! test in its own process, [`serve`] serves the guest from one and
+//! [`rerun`] re-runs a benchmark.
+
+#![allow(dead_code)]
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use std::{env, fs, io, thread};
+
+/// Variables every test runtime needs, so hosts connect without external
+/// services.
+const VARS: &[(&str, &str)] = &[
+    ("IDENTITY_CLIENT_ID", "test"),
+    ("IDENTITY_CLIENT_SECRET", "test"),
+    ("IDENTITY_TOKEN_URL", "http://127.0.0.1:1/token"),
+    ("WEBSOCKET_ADDR", "127.0.0.1:0"),
+];
+
+/// Set in processes started with the runtime's environment, to the guest
+/// they run.
+const GUEST: &str = "OMNIA_TEST_GUEST";
+
+/// Run the calling test in a process of its own, started with the runtime's
+/// environment: `vars` over the defaults.
+///
+/// Returns the test guest as a component named `name` when called in that
+/// process, or `None` once the test has passed in it.
+///
+/// # Panics
+///
+/// Panics if the test fails in its own process.
+pub fn isolate(vars: &[(&str, &str)], name: &str) -> Option<PathBuf> {
+    if let Some(guest) = env::var_os(GUEST) {
+        return Some(guest.into());
+    }
+
+    let test = thread::current().name().expect("tests should run on named threads").to_string();
+    let output = command(vars, name)
+        .args([test.as_str(), "--exact", "--nocapture"])
+        .output()
+        .expect("should run test");
+
+    // forward the output, so it is captured as the test's own
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success(), "{test} failed in its own process");
+    None
+}
+
+/// Serve the test guest as a component named `name` from a runtime in a
+/// process of its own, started with the runtime's environment: `vars` over
+/// the defaults. Returns once `addr` accepts connections.
+///
+/// The runtime is run by the test binary's `serve` test (see [`served`]) and
+/// stops when the test binary exits.
+///
+/// # Panics
+///
+/// Panics if the runtime stops, or does not listen within 30 seconds.
+pub fn serve(addr: &str, vars: &[(&str, &str)], name: &str) {
+    static SERVERS: Mutex<Vec<Child>> = Mutex::new(Vec::new());
+
+    let mut server = command(vars, name)
+        .args(["serve", "--exact", "--ignored", "--nocapture"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("should start runtime");
+
+    let started = Instant::now();
+    while TcpStream::connect(addr).is_err() {
+        if let Some(status) = server.try_wait().expect("should check runtime") {
+            panic!("runtime stopped: {status}");
+        }
+        assert!(started.elapsed() < Duration::from_secs(30), "runtime not listening on {addr}");
+        thread::sleep(Duration::from_millis(50));
+    }
+    SERVERS.lock().unwrap_or_else(PoisonError::into_inner).push(server);
+}
+
+/// The guest the test binary's `serve` test should run, when started by
+/// [`serve`].
+///
+/// The process exits once the test binary that started it exits.
+pub fn served() -> Option<PathBuf> {
+    let guest = env::var_os(GUEST)?;
+
+    // the test binary holds stdin open until it exits
+    thread::spawn(|| {
+        let _ = io::stdin().read_to_end(&mut Vec::new());
+        process::exit(0);
+    });
+    Some(guest.into())
+}
+
+/// Re-run the benchmark with the runtime's environment: `vars` over the
+/// defaults, exiting with its status.
+///
+/// Returns the test guest as a component named `name` when running with the
+/// environment. Should be called first in `main`.
+pub fn rerun(vars: &[(&str, &str)], name: &str) -> PathBuf {
+    if let Some(guest) = env::var_os(GUEST) {
+        return guest.into();
+    }
+
+    let status =
+        command(vars, name).args(env::args_os().skip(1)).status().expect("should run benchmark");
+    process::exit(status.code().unwrap_or(1));
+}
+
+/// A free local address for a server to listen on.
+pub fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("should bind");
+    listener.local_addr().expect("should have address").to_string()
+}
+
+// The test binary, started with the runtime's environment to run the guest
+// as a component named `name`.
+fn command(vars: &[(&str, &str)], name: &str) -> Command {
+    let mut command = Command::new(env::current_exe().expect("should find test binary"));
+    command.envs(VARS.iter().chain(vars).copied()).env(GUEST, component(name));
+    command
+}
+
+// The test guest as a component named `name`. Components are named after
+// their file, so each name is given its own copy of the guest, letting tests
+// configure policies and routes per component.
+fn component(name: &str) -> PathBuf {
+    static COPIED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+    let guest = guest();
+    let dir =
+        Path::new(env!("CARGO_TARGET_TMPDIR")).join("components").join(env!("CARGO_CRATE_NAME"));
+    let wasm = dir.join(format!("{name}.wasm"));
+
+    // copy the guest once per test binary, as it may have been rebuilt
+    let mut copied = COPIED.lock().unwrap_or_else(PoisonError::into_inner);
+    if copied.get_or_insert_default().insert(name.to_string()) {
+        fs::create_dir_all(&dir).expect("should create components directory");
+        fs::copy(guest, &wasm).expect("should copy guest");
+    }
+    drop(copied);
+    wasm
+}
+
+// Build the guest once per test binary, into its own target directory so the
+// build does not wait on the lock held by the running `cargo test`.
+fn guest() -> &'static Path {
+    static BUILT: OnceLock<PathBuf> = OnceLock::new();
+    BUILT.get_or_init(|| {
+        let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("guest");
+        let status = Command::new(env!("CARGO"))
+            .args(["build", "-p", "omnia-test-guest", "--target", "wasm32-wasip2", "--release"])
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .status()
+            .expect("should run cargo");
+        assert!(status.success(), "building the test guest failed");
+        target_dir.join("wasm32-wasip2/release/omnia_test_guest.wasm")
+    })
+}
@@ -0,0 +1,81 @@
+//! Driving the test guest's handlers through the harness.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use omnia_test::{Harness, KeyValueRecorder};
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::WasiKeyValue;
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueRecorder,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// The guest, when run in the test's own process (see `common::isolate`).
+async fn harness() -> anyhow::Result<Option<Harness<runtime::Context>>> {
+    let Some(guest) = common::isolate(&[], "harness") else {
+        return Ok(None);
+    };
+    Ok(Some(Harness::new(runtime::components(&[guest]).await?)))
+}
+
+#[tokio::test]
+async fn http() -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+
+    let request = http::Request::post("http://localhost/echo").header("x-test", "1").body("hi")?;
+    let response = harness.http(request).await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().as_ref(), b"POST\nhttp://localhost/echo\nx-test: 1\nhi");
+
+    // unrouted paths are answered by the guest's router
+    let request = http::Request::get("http://localhost/missing").body("")?;
+    assert_eq!(harness.http(request).await?.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn message() -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+    let recording = harness.components()[0].key_value_recorder.recording().clone();
+
+    harness.message("orders", "order 1").await?;
+    assert_eq!(recording.key_value("messages", "orders"), Some(b"order 1".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn websocket() -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+    let recording = harness.components()[0].key_value_recorder.recording().clone();
+
+    harness.websocket("hello").await?;
+    assert_eq!(recording.key_value("events", "last"), Some(b"hello".to_vec()));
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Cancelling guests at the request deadline.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+use reqwest::StatusCode;
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// Serve the guest with a one second deadline, handling one request at a
+// time.
+fn url(path: &str) -> String {
+    static ADDR: OnceLock<String> = OnceLock::new();
+    let addr = ADDR.get_or_init(|| {
+        let addr = common::free_addr();
+        let vars = [
+            ("HTTP_ADDR", addr.as_str()),
+            ("HTTP_REQUEST_TIMEOUT_SECS", "1"),
+            ("HTTP_MAX_IN_FLIGHT", "1"),
+        ];
+        common::serve(&addr, &vars, "deadline");
+        addr
+    });
+    format!("http://{addr}{path}")
+}
+
+#[tokio::test]
+async fn cancels_guest_at_deadline() -> anyhow::Result<()> {
+    let started = Instant::now();
+    let response = reqwest::get(url("/sleep/5000")).await?;
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+
+    // the cancelled guest no longer holds the only in-flight slot, though it
+    // would still be sleeping had it not been cancelled
+    let started = Instant::now();
+    loop {
+        let response = reqwest::get(url("/sleep/0")).await?;
+        if response.status() == StatusCode::OK {
+            break;
+        }
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(started.elapsed() < Duration::from_secs(2), "guest was not cancelled");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    Ok(())
+}
+
+// The runtime started by `common::serve`.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "started by `common::serve`"]
+async fn serve() -> anyhow::Result<()> {
+    match common::served() {
+        Some(guest) => runtime::run(vec![guest]).await,
+        None => Ok(()),
+    }
+}
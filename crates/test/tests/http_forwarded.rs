@@ -0,0 +1,87 @@
+//! Honouring the `Forwarded` header only from trusted proxies.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::sync::OnceLock;
+
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// Serve the guest without trusting any proxy.
+fn untrusted() -> &'static str {
+    static ADDR: OnceLock<String> = OnceLock::new();
+    ADDR.get_or_init(|| {
+        let addr = common::free_addr();
+        common::serve(&addr, &[("HTTP_ADDR", &addr)], "untrusted");
+        addr
+    })
+}
+
+// Serve the guest behind a proxy on the loopback address.
+fn trusted() -> &'static str {
+    static ADDR: OnceLock<String> = OnceLock::new();
+    ADDR.get_or_init(|| {
+        let addr = common::free_addr();
+        let vars = [("HTTP_ADDR", addr.as_str()), ("HTTP_TRUSTED_PROXIES", "127.0.0.1")];
+        common::serve(&addr, &vars, "trusted");
+        addr
+    })
+}
+
+// Echo a request carrying a `Forwarded` header claiming another host and
+// scheme, returning the URI the guest received.
+async fn forwarded_uri(addr: &str) -> anyhow::Result<String> {
+    let response = reqwest::Client::new()
+        .get(format!("http://{addr}/echo"))
+        .header("forwarded", "host=internal.example.com;proto=https")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+    let body = response.text().await?;
+    Ok(body.lines().nth(1).unwrap_or_default().to_string())
+}
+
+#[tokio::test]
+async fn ignores_spoofed_forwarded_header() -> anyhow::Result<()> {
+    let addr = untrusted();
+    assert_eq!(forwarded_uri(addr).await?, format!("http://{addr}/echo"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn honours_forwarded_header_from_trusted_proxy() -> anyhow::Result<()> {
+    assert_eq!(forwarded_uri(trusted()).await?, "https://internal.example.com/echo");
+    Ok(())
+}
+
+// The runtime started by `common::serve`.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "started by `common::serve`"]
+async fn serve() -> anyhow::Result<()> {
+    match common::served() {
+        Some(guest) => runtime::run(vec![guest]).await,
+        None => Ok(()),
+    }
+}
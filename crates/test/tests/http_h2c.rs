@@ -0,0 +1,73 @@
+//! Serving prior-knowledge HTTP/2 without TLS.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::sync::OnceLock;
+
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+use reqwest::{Client, Version};
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+fn url() -> String {
+    static ADDR: OnceLock<String> = OnceLock::new();
+    let addr = ADDR.get_or_init(|| {
+        let addr = common::free_addr();
+        common::serve(&addr, &[("HTTP_ADDR", &addr)], "h2c");
+        addr
+    });
+    format!("http://{addr}/echo")
+}
+
+async fn echo(client: &Client, version: Version) -> anyhow::Result<String> {
+    let response =
+        client.post(url()).header("x-test", "1").header("x-trace", "abc").body("hi").send().await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.version(), version);
+    Ok(response.text().await?)
+}
+
+#[tokio::test]
+async fn serves_prior_knowledge_h2c() -> anyhow::Result<()> {
+    let http1 = Client::builder().http1_only().build()?;
+    let h2c = Client::builder().http2_prior_knowledge().build()?;
+
+    // the guest sees the same request over either protocol
+    let over_http1 = echo(&http1, Version::HTTP_11).await?;
+    let over_h2c = echo(&h2c, Version::HTTP_2).await?;
+    assert_eq!(over_http1, format!("POST\n{}\nx-test: 1\nx-trace: abc\nhi", url()));
+    assert_eq!(over_h2c, over_http1);
+
+    Ok(())
+}
+
+// The runtime started by `common::serve`.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "started by `common::serve`"]
+async fn serve() -> anyhow::Result<()> {
+    match common::served() {
+        Some(guest) => runtime::run(vec![guest]).await,
+        None => Ok(()),
+    }
+}
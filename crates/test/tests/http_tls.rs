@@ -0,0 +1,188 @@
+//! Terminating TLS on the inbound HTTP server.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair, SerialNumber,
+};
+use reqwest::tls::TlsInfo;
+use reqwest::{Certificate, Client, Identity, Version};
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// A CA issuing the server's and client's certificates.
+struct Pki {
+    ca: CertifiedIssuer<'static, KeyPair>,
+    server_key: KeyPair,
+    client: Identity,
+    dir: PathBuf,
+}
+
+impl Pki {
+    fn new() -> Self {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, "omnia test ca");
+        let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::default();
+        params.distinguished_name.push(DnType::CommonName, "orders");
+        let client_cert = params.signed_by(&client_key, &ca).unwrap();
+        let client = format!("{}{}", client_cert.pem(), client_key.serialize_pem());
+
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("http_tls");
+        fs::create_dir_all(&dir).unwrap();
+        let pki = Self {
+            ca,
+            server_key: KeyPair::generate().unwrap(),
+            client: Identity::from_pem(client.as_bytes()).unwrap(),
+            dir,
+        };
+        fs::write(pki.dir.join("ca.pem"), pki.ca.pem()).unwrap();
+        fs::write(pki.dir.join("key.pem"), pki.server_key.serialize_pem()).unwrap();
+        pki.issue_server_cert(1);
+        pki
+    }
+
+    // Issue the server a certificate with `serial`, replacing its current
+    // certificate file in one step.
+    fn issue_server_cert(&self, serial: u64) -> Vec<u8> {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.serial_number = Some(SerialNumber::from(serial));
+        let cert = params.signed_by(&self.server_key, &self.ca).unwrap();
+
+        let staged = self.dir.join("cert.pem.new");
+        fs::write(&staged, cert.pem()).unwrap();
+        fs::rename(staged, self.dir.join("cert.pem")).unwrap();
+        cert.der().to_vec()
+    }
+}
+
+// Serve the guest over TLS, requiring client certificates issued by the CA.
+fn server() -> &'static (String, Pki) {
+    static SERVER: OnceLock<(String, Pki)> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let pki = Pki::new();
+        let addr = common::free_addr();
+        let file = |name: &str| pki.dir.join(name).display().to_string();
+        let vars = [
+            ("HTTP_ADDR", addr.as_str()),
+            ("HTTP_TLS_CERT", &file("cert.pem")),
+            ("HTTP_TLS_KEY", &file("key.pem")),
+            ("HTTP_TLS_CLIENT_CA", &file("ca.pem")),
+            ("HTTP_TLS_RELOAD_INTERVAL_SECS", "1"),
+        ];
+        common::serve(&addr, &vars, "tls");
+        (addr, pki)
+    })
+}
+
+// A client trusting the CA, presenting the client certificate if `identify`
+// is set. Each client makes its own connections.
+fn client(identify: bool) -> Client {
+    let (addr, pki) = server();
+    let ca = Certificate::from_pem(pki.ca.pem().as_bytes()).unwrap();
+    let mut builder = Client::builder()
+        .tls_certs_only([ca])
+        .tls_info(true)
+        .resolve("localhost", addr.parse().unwrap());
+    if identify {
+        builder = builder.identity(pki.client.clone());
+    }
+    builder.build().unwrap()
+}
+
+fn url() -> String {
+    let (addr, _) = server();
+    let port = addr.rsplit(':').next().unwrap();
+    format!("https://localhost:{port}/echo")
+}
+
+#[tokio::test]
+async fn serves_verified_clients() -> anyhow::Result<()> {
+    let response = client(true).post(url()).body("hi").send().await?;
+
+    // HTTP/2 is negotiated with ALPN
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.version(), Version::HTTP_2);
+    let expected = format!("POST\n{}\nx-client-cert-subject: CN=orders\nhi", url());
+    assert_eq!(response.text().await?, expected);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_clients_without_certificate() {
+    let result = client(false).post(url()).body("hi").send().await;
+    assert!(result.is_err(), "{result:?}");
+}
+
+#[tokio::test]
+async fn strips_spoofed_client_subject() -> anyhow::Result<()> {
+    let response =
+        client(true).post(url()).header("x-client-cert-subject", "CN=admin").send().await?;
+
+    let body = response.text().await?;
+    assert!(body.contains("x-client-cert-subject: CN=orders"), "{body}");
+    assert!(!body.contains("CN=admin"), "{body}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reloads_rotated_certificate() -> anyhow::Result<()> {
+    let (_, pki) = server();
+    let reissued = pki.issue_server_cert(2);
+
+    // new connections use the certificate once reloaded
+    let started = Instant::now();
+    loop {
+        let response = client(true).get(url()).send().await?;
+        let info = response.extensions().get::<TlsInfo>().expect("should have tls info");
+        if info.peer_certificate() == Some(reissued.as_slice()) {
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "certificate not reloaded");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    Ok(())
+}
+
+// The runtime started by `common::serve`.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "started by `common::serve`"]
+async fn serve() -> anyhow::Result<()> {
+    match common::served() {
+        Some(guest) => runtime::run(vec![guest]).await,
+        None => Ok(()),
+    }
+}
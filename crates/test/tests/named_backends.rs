@@ -0,0 +1,117 @@
+//! Runtimes routing a host's resources to different backends by name.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+
+use omnia::Backend;
+use omnia_test::{Harness, KeyValueRecorder, SqlRecorder};
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{Bucket, FutureResult, KeyValueDefault, WasiKeyValue, WasiKeyValueCtx};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::WasiSql;
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+
+// the `cache` bucket is recorded, the `messages` bucket is scoped to each
+// component and other buckets use the default backend, while only the
+// `orders` connection is available
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: {
+            "cache": KeyValueRecorder,
+            "messages": ComponentBuckets,
+            _: KeyValueDefault,
+        },
+        WasiMessaging: MessagingDefault,
+        WasiSql: { "orders": SqlRecorder },
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// Key-value backend recording entries in buckets prefixed with the name of
+// the component using them.
+#[derive(Debug, Clone)]
+struct ComponentBuckets {
+    inner: KeyValueRecorder,
+    component: Option<String>,
+}
+
+impl Backend for ComponentBuckets {
+    type ConnectOptions = <KeyValueRecorder as Backend>::ConnectOptions;
+
+    async fn connect_with(options: Self::ConnectOptions) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: KeyValueRecorder::connect_with(options).await?,
+            component: None,
+        })
+    }
+
+    fn for_component(&self, component: &str) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            component: Some(component.to_string()),
+        }
+    }
+}
+
+impl WasiKeyValueCtx for ComponentBuckets {
+    fn open_bucket(&self, identifier: String) -> FutureResult<Arc<dyn Bucket>> {
+        match &self.component {
+            Some(component) => self.inner.open_bucket(format!("{component}.{identifier}")),
+            None => self.inner.open_bucket(identifier),
+        }
+    }
+}
+
+// Each named backend is connected once and exposed on the runtime's state.
+#[allow(dead_code)]
+const fn backends(
+    context: &runtime::Context,
+) -> (&KeyValueRecorder, &ComponentBuckets, &KeyValueDefault, &SqlRecorder) {
+    (
+        &context.key_value_recorder,
+        &context.component_buckets,
+        &context.key_value_default,
+        &context.sql_recorder,
+    )
+}
+
+#[test]
+fn reads_named_backend_options() {
+    let vars = runtime::env_vars();
+    for var in ["SQL_DATABASE", "HTTP_ADDR", "GUEST_FUEL"] {
+        assert!(vars.iter().any(|v| v == var), "{var} missing from {vars:?}");
+    }
+}
+
+#[tokio::test]
+async fn scopes_named_backends_to_components() -> anyhow::Result<()> {
+    let Some(orders) = common::isolate(&[], "orders") else {
+        return Ok(());
+    };
+
+    // a second copy of the guest, named `payments`
+    let payments = orders.with_file_name("payments.wasm");
+    fs::copy(&orders, &payments)?;
+    let harness = Harness::new(runtime::components(&[orders, payments]).await?);
+    let recording = harness.components()[0].component_buckets.inner.recording().clone();
+
+    // both components store the message in their own `messages` bucket
+    harness.message("invoices", "invoice 1").await?;
+    assert_eq!(recording.key_value("orders.messages", "invoices"), Some(b"invoice 1".to_vec()));
+    assert_eq!(recording.key_value("payments.messages", "invoices"), Some(b"invoice 1".to_vec()));
+    assert_eq!(recording.key_value("messages", "invoices"), None);
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Instantiating guests for HTTP requests from the pooling allocator.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use omnia_test::Harness;
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// The guest, instantiated from a pool holding a single instance when run in
+// the test's own process (see `common::isolate`).
+async fn harness() -> anyhow::Result<Option<Harness<runtime::Context>>> {
+    let Some(guest) = common::isolate(&[("GUEST_POOL_INSTANCES", "1")], "pooling") else {
+        return Ok(None);
+    };
+    Ok(Some(Harness::new(runtime::components(&[guest]).await?)))
+}
+
+async fn get(harness: &Harness<runtime::Context>, path: &str) -> anyhow::Result<u16> {
+    let request = http::Request::get(format!("http://localhost{path}")).body("")?;
+    Ok(harness.http(request).await?.status().as_u16())
+}
+
+// Get `path` once the pooled instance is free. The instance is returned to
+// the pool when the guest finishes, shortly after the response is sent.
+async fn get_when_free(harness: &Harness<runtime::Context>, path: &str) -> anyhow::Result<u16> {
+    let started = Instant::now();
+    loop {
+        let status = get(harness, path).await?;
+        if status != 500 || started.elapsed() > Duration::from_secs(2) {
+            return Ok(status);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn reuses_pooled_instance() -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+
+    // each request's instance is returned to the pool once it completes
+    for _ in 0..3 {
+        assert_eq!(get_when_free(&harness, "/echo").await?, 200);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn requests_share_the_pool() -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+
+    // the pool is exhausted while a request holds its only instance
+    let busy = {
+        let harness = harness.clone();
+        tokio::spawn(async move { get(&harness, "/sleep/500").await })
+    };
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(get(&harness, "/echo").await?, 500);
+
+    assert_eq!(busy.await??, 200);
+    assert_eq!(get_when_free(&harness, "/echo").await?, 200);
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Preempting guests that never yield, so they cannot starve other requests.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use omnia_test::Harness;
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+use tokio::sync::oneshot;
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// The guest, trapped after a second of CPU time, when run in the test's own
+// process (see `common::isolate`).
+async fn harness() -> anyhow::Result<Option<Harness<runtime::Context>>> {
+    let Some(guest) = common::isolate(&[("GUEST_CPU_BUDGET_MS", "1000")], "preemption") else {
+        return Ok(None);
+    };
+    Ok(Some(Harness::new(runtime::components(&[guest]).await?)))
+}
+
+async fn get(harness: &Harness<runtime::Context>, path: &str) -> anyhow::Result<(u16, String)> {
+    let request = http::Request::get(format!("http://localhost{path}")).body("")?;
+    let response = harness.http(request).await?;
+    Ok((response.status().as_u16(), String::from_utf8_lossy(response.body()).to_string()))
+}
+
+#[tokio::test]
+async fn spinning_guest_is_preempted() -> anyhow::Result<()> {
+    let Some(harness) = harness().await? else {
+        return Ok(());
+    };
+
+    // serve both requests on a single worker, which the spinning guest would
+    // block if it were never preempted
+    let worker = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let (done, finished) = oneshot::channel();
+    thread::spawn(move || {
+        let result = worker.block_on(async {
+            let spinning = tokio::spawn({
+                let harness = harness.clone();
+                async move { get(&harness, "/spin").await }
+            });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            let echo = get(&harness, "/echo").await?;
+            let served_while_spinning = !spinning.is_finished();
+            anyhow::Ok((echo, served_while_spinning, spinning.await??))
+        });
+        let _ = done.send(result);
+    });
+
+    // fail, rather than hang, if the guest is never interrupted
+    let finished = tokio::time::timeout(Duration::from_secs(30), finished)
+        .await
+        .context("requests should finish while the guest spins")?;
+    let (echo, served_while_spinning, spin) = finished.context("worker should finish")??;
+
+    assert_eq!(echo.0, 200, "{}", echo.1);
+    assert!(served_while_spinning, "request should be served while the guest spins");
+    assert_eq!(spin.0, 503, "{}", spin.1);
+    assert!(spin.1.contains("Guest deadline exceeded"), "{}", spin.1);
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Tracing host calls within the invocation's span.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use omnia_test::Harness;
+use omnia_wasi_blobstore::{BlobstoreDefault, WasiBlobstore};
+use omnia_wasi_http::{HttpDefault, WasiHttp};
+use omnia_wasi_identity::{IdentityDefault, WasiIdentity};
+use omnia_wasi_keyvalue::{KeyValueDefault, WasiKeyValue};
+use omnia_wasi_messaging::{MessagingDefault, WasiMessaging};
+use omnia_wasi_sql::{SqlDefault, WasiSql};
+use omnia_wasi_vault::{VaultDefault, WasiVault};
+use omnia_wasi_websocket::{WasiWebSocket, WebSocketDefault};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+omnia::runtime!({
+    main: false,
+    hosts: {
+        WasiHttp: HttpDefault,
+        WasiBlobstore: BlobstoreDefault,
+        WasiIdentity: IdentityDefault,
+        WasiKeyValue: KeyValueDefault,
+        WasiMessaging: MessagingDefault,
+        WasiSql: SqlDefault,
+        WasiVault: VaultDefault,
+        WasiWebSocket: WebSocketDefault,
+    }
+});
+
+// Records the spans enclosing each event emitted by the key-value backend,
+// from the root.
+#[derive(Clone, Default)]
+struct BackendScopes(Arc<Mutex<Vec<Vec<&'static str>>>>);
+
+impl<S> Layer<S> for BackendScopes
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !event.metadata().target().starts_with("omnia_wasi_keyvalue") {
+            return;
+        }
+        let scope = ctx
+            .event_scope(event)
+            .map(|scope| scope.from_root().map(|span| span.name()).collect())
+            .unwrap_or_default();
+        self.0.lock().unwrap().push(scope);
+    }
+}
+
+#[tokio::test]
+async fn backend_calls_are_within_invocation_span() -> anyhow::Result<()> {
+    let Some(guest) = common::isolate(&[], "spans") else {
+        return Ok(());
+    };
+
+    // the servers' debug spans are disabled, as in production
+    let scopes = BackendScopes::default();
+    let filter = EnvFilter::new("info,omnia_wasi_keyvalue=debug");
+    let subscriber = Registry::default().with(filter).with(scopes.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let harness = Harness::new(runtime::components(&[guest]).await?);
+    scopes.0.lock().unwrap().clear();
+
+    let request = http::Request::post("http://localhost/keyvalue/orders").body("order 1")?;
+    assert_eq!(harness.http(request).await?.status(), 200);
+
+    let scopes = std::mem::take(&mut *scopes.0.lock().unwrap());
+    assert!(!scopes.is_empty(), "the backend emitted no events");
+    assert!(scopes.iter().all(|scope| scope.first() == Some(&"guest")), "{scopes:?}");
+
+    Ok(())
+}
@@ -37,7 +37,7 @@ use std::sync::Arc;
 
 use bytes::Bytes;
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{Backends, Capabilities, Host, Invocation, NamedHost, Server, State};
 pub use resource::*;
 use wasmtime::component::{HasData, Linker, ResourceTable};
 use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
@@ -127,6 +127,10 @@ where
 
 impl<S> Server<S> for WasiBlobstore where S: State {}
 
+impl NamedHost for WasiBlobstore {
+    type Ctx = dyn WasiBlobstoreCtx;
+}
+
 /// A trait which provides internal WASI Blobstore state.
 ///
 /// This is implemented by the `T` in `Linker<T>` — a single type shared across
@@ -143,6 +147,12 @@ pub struct WasiBlobstoreCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// Resources the guest may access.
+    pub capabilities: &'a Capabilities,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI Blobstore context.
@@ -163,6 +173,37 @@ pub trait WasiBlobstoreCtx: Debug + Send + Sync + 'static {
     fn container_exists(&self, name: String) -> FutureResult<bool>;
 }
 
+/// Routes each container name to its backend.
+impl WasiBlobstoreCtx for Backends<dyn WasiBlobstoreCtx> {
+    fn create_container(&self, name: String) -> FutureResult<Arc<dyn Container>> {
+        match self.get(&name) {
+            Ok(backend) => backend.create_container(name),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+
+    fn get_container(&self, name: String) -> FutureResult<Arc<dyn Container>> {
+        match self.get(&name) {
+            Ok(backend) => backend.get_container(name),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+
+    fn delete_container(&self, name: String) -> FutureResult<()> {
+        match self.get(&name) {
+            Ok(backend) => backend.delete_container(name),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+
+    fn container_exists(&self, name: String) -> FutureResult<bool> {
+        match self.get(&name) {
+            Ok(backend) => backend.container_exists(name),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+}
+
 /// Implementation of the `WasiBlobstoreView` trait for the store context.
 #[macro_export]
 macro_rules! omnia_wasi_view {
@@ -172,6 +213,8 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_blobstore::WasiBlobstoreCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    capabilities: &self.capabilities,
+                    invocation: &self.invocation,
                 }
             }
         }
@@ -1,3 +1,4 @@
+use omnia::Capability;
 use wasmtime::component::{Accessor, Resource};
 
 use crate::host::generated::wasi::blobstore::blobstore::{Host, HostWithStore, ObjectId};
@@ -8,11 +9,20 @@ fn same_object(src: &ObjectId, dest: &ObjectId) -> bool {
     src.container == dest.container && src.object == dest.object
 }
 
+// Check the capability policy allows the guest to use the container.
+// `wasi:blobstore` errors are strings, so a denial is marked by its message.
+fn check_container<T>(accessor: &Accessor<T, WasiBlobstore>, name: &str) -> Result<()> {
+    accessor
+        .with(|mut store| store.get().capabilities.check(Capability::Container, name))
+        .map_err(|e| e.to_string())
+}
+
 impl HostWithStore for WasiBlobstore {
     async fn create_container<T>(
         accessor: &Accessor<T, Self>, name: String,
     ) -> Result<Resource<ContainerProxy>> {
         tracing::trace!("create_container: {name}");
+        check_container(accessor, &name)?;
         let container = accessor
             .with(|mut store| store.get().ctx.create_container(name))
             .await
@@ -25,6 +35,7 @@ impl HostWithStore for WasiBlobstore {
         accessor: &Accessor<T, Self>, name: String,
     ) -> Result<Resource<ContainerProxy>> {
         tracing::trace!("get_container: {name}");
+        check_container(accessor, &name)?;
         let container = accessor
             .with(|mut store| store.get().ctx.get_container(name))
             .await
@@ -35,6 +46,7 @@ impl HostWithStore for WasiBlobstore {
 
     async fn delete_container<T>(accessor: &Accessor<T, Self>, name: String) -> Result<()> {
         tracing::trace!("delete_container: {name}");
+        check_container(accessor, &name)?;
         accessor
             .with(|mut store| store.get().ctx.delete_container(name))
             .await
@@ -43,6 +55,7 @@ impl HostWithStore for WasiBlobstore {
 
     async fn container_exists<T>(accessor: &Accessor<T, Self>, name: String) -> Result<bool> {
         tracing::trace!("container_exists: {name}");
+        check_container(accessor, &name)?;
         accessor
             .with(|mut store| store.get().ctx.container_exists(name))
             .await
@@ -59,6 +72,8 @@ impl HostWithStore for WasiBlobstore {
             dest.container,
             dest.object
         );
+        check_container(accessor, &src.container)?;
+        check_container(accessor, &dest.container)?;
 
         let src_container = accessor
             .with(|mut store| store.get().ctx.get_container(src.container.clone()))
@@ -89,6 +104,8 @@ impl HostWithStore for WasiBlobstore {
             dest.container,
             dest.object
         );
+        check_container(accessor, &src.container)?;
+        check_container(accessor, &dest.container)?;
 
         if same_object(&src, &dest) {
             // No-op for identical source and destination; deleting would corrupt data.
@@ -50,6 +50,16 @@ impl Backend for ConfigDefault {
             config_vars: Arc::new(config_vars),
         })
     }
+
+    // Variables prefixed with the component's name in upper snake case are
+    // given to the component without the prefix, replacing any variable of
+    // the same name, e.g. `ORDER_SKILL_API_URL` as `API_URL`.
+    fn for_component(&self, component: &str) -> Self {
+        let prefix = format!("{}_", omnia::env_prefix(component));
+        Self {
+            config_vars: Arc::new(scoped(env::vars(), &prefix).into_iter().collect()),
+        }
+    }
 }
 
 impl WasiConfigCtx for ConfigDefault {
@@ -57,3 +67,32 @@ impl WasiConfigCtx for ConfigDefault {
         &self.config_vars
     }
 }
+
+// The variables given to a component, its own (prefixed) variables replacing
+// those shared by every component.
+fn scoped(vars: impl Iterator<Item = (String, String)>, prefix: &str) -> Vec<(String, String)> {
+    let (own, mut scoped): (Vec<_>, Vec<_>) = vars.partition(|(name, _)| name.starts_with(prefix));
+    scoped.extend(own.into_iter().filter_map(|(name, value)| {
+        let name = name.strip_prefix(prefix)?;
+        (!name.is_empty()).then(|| (name.to_string(), value))
+    }));
+    scoped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn component_variables() {
+        let vars = [("API_URL", "shared"), ("ORDERS_API_URL", "orders"), ("TIMEOUT", "5")]
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+        let scoped = scoped(vars.into_iter(), "ORDERS_").into_iter().collect::<HashMap<_, _>>();
+
+        assert_eq!(scoped.get("API_URL").map(String::as_str), Some("orders"));
+        assert_eq!(scoped.get("TIMEOUT").map(String::as_str), Some("5"));
+        assert!(!scoped.contains_key("ORDERS_API_URL"));
+    }
+}
@@ -0,0 +1,18 @@
+//! # WASI Cron WIT implementation
+
+// Bindings for the `omnia:cron` world.
+mod generated {
+    #![allow(missing_docs)]
+
+    wit_bindgen::generate!({
+        world: "cron",
+        path: "wit",
+        additional_derives: [Clone],
+        generate_all,
+        pub_export_macro: true,
+        default_bindings_module: "omnia_wasi_cron",
+    });
+}
+
+pub use self::generated::exports::omnia::cron::*;
+pub use self::generated::*;
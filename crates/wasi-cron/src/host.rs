@@ -0,0 +1,107 @@
+//! # WASI Cron Service
+//!
+//! This module implements a runtime server firing a guest's scheduled
+//! handler on cron expressions.
+
+mod default_impl;
+mod schedule;
+mod server;
+
+mod generated {
+    #![allow(missing_docs)]
+
+    wasmtime::component::bindgen!({
+        world: "cron",
+        path: "wit",
+        exports: {
+            default: store | tracing | trappable,
+        },
+    });
+}
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use omnia::{Host, Invocation, Server, Shutdown, State};
+use wasmtime::component::Linker;
+
+pub use self::default_impl::CronDefault;
+pub use self::generated::Cron;
+pub use self::generated::exports::omnia::cron::handler::{Error, Tick};
+pub use self::schedule::Schedule;
+
+/// Host-side service for `omnia:cron`.
+#[derive(Clone, Debug)]
+pub struct WasiCron;
+
+impl<T> Host<T> for WasiCron
+where
+    T: WasiCronView + 'static,
+{
+    // the guest only exports a handler, so there is nothing to link
+    fn add_to_linker(_linker: &mut Linker<T>) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S> Server<S> for WasiCron
+where
+    S: State,
+    S::StoreCtx: WasiCronView,
+{
+    async fn run(&self, components: &[S], shutdown: &Shutdown) -> anyhow::Result<()> {
+        server::run(components, shutdown).await
+    }
+}
+
+/// A trait which provides internal WASI Cron state.
+///
+/// This is implemented by the `T` in `Linker<T>` — a single type shared across
+/// all WASI components for the runtime build.
+pub trait WasiCronView: Send {
+    /// Return a [`WasiCronCtxView`] from mutable reference to self.
+    fn cron(&mut self) -> WasiCronCtxView<'_>;
+}
+
+/// View into [`WasiCronCtx`] implementation.
+pub struct WasiCronCtxView<'a> {
+    /// Mutable reference to the WASI Cron context.
+    pub ctx: &'a mut dyn WasiCronCtx,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
+}
+
+/// A trait which provides internal WASI Cron context.
+///
+/// This is implemented by the provider of each component's schedules. For
+/// example, configuration loaded from the environment.
+pub trait WasiCronCtx: Debug + Send + Sync + 'static {
+    /// The schedules the named component's handler is fired on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the component's schedules are invalid.
+    fn schedules(&self, component: &str) -> anyhow::Result<Vec<Schedule>>;
+
+    /// The maximum random delay added to each tick, spreading the load of
+    /// schedules firing at the same moment.
+    fn jitter(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Implementation of the `WasiCronView` trait for the store context.
+#[macro_export]
+macro_rules! omnia_wasi_view {
+    ($store_ctx:ty, $field_name:ident) => {
+        impl omnia_wasi_cron::WasiCronView for $store_ctx {
+            fn cron(&mut self) -> omnia_wasi_cron::WasiCronCtxView<'_> {
+                omnia_wasi_cron::WasiCronCtxView {
+                    ctx: &mut self.$field_name,
+                    invocation: &self.invocation,
+                }
+            }
+        }
+    };
+}
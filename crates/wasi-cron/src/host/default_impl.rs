@@ -0,0 +1,71 @@
+//! Default implementation for wasi-cron
+//!
+//! Schedules are configured per component using an environment variable
+//! prefixed with the component's name in upper snake case. For example, a
+//! component named `order-skill` is configured with
+//! `ORDER_SKILL_CRON_SCHEDULES`, a `;`-separated list of cron expressions.
+
+use std::env;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use fromenv::FromEnv;
+use omnia::Backend;
+use tracing::instrument;
+
+use crate::host::WasiCronCtx;
+use crate::host::schedule::Schedule;
+
+/// Options used to configure schedules.
+#[derive(Debug, Clone, FromEnv)]
+pub struct ConnectOptions {
+    /// The maximum random delay (in seconds) added to each tick.
+    #[env(from = "CRON_JITTER_SECS", default = "0")]
+    pub jitter_secs: u64,
+}
+
+impl omnia::FromEnv for ConnectOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading connection options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        omnia::requirement_vars(&Self::requirements())
+    }
+}
+
+/// Default implementation for `omnia:cron`.
+#[derive(Debug, Clone)]
+pub struct CronDefault {
+    jitter: Duration,
+}
+
+impl Backend for CronDefault {
+    type ConnectOptions = ConnectOptions;
+
+    #[instrument]
+    async fn connect_with(options: Self::ConnectOptions) -> Result<Self> {
+        Ok(Self {
+            jitter: Duration::from_secs(options.jitter_secs),
+        })
+    }
+}
+
+impl WasiCronCtx for CronDefault {
+    fn schedules(&self, component: &str) -> Result<Vec<Schedule>> {
+        let var = format!("{}_CRON_SCHEDULES", omnia::env_prefix(component));
+        let Ok(value) = env::var(&var) else {
+            return Ok(Vec::new());
+        };
+        value
+            .split(';')
+            .map(str::trim)
+            .filter(|expression| !expression.is_empty())
+            .map(|expression| expression.parse().with_context(|| format!("loading `{var}`")))
+            .collect()
+    }
+
+    fn jitter(&self) -> Duration {
+        self.jitter
+    }
+}
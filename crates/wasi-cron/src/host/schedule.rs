@@ -0,0 +1,88 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use anyhow::{Context, Error};
+use chrono::{DateTime, Utc};
+
+/// A cron schedule.
+///
+/// Expressions have five fields (minute, hour, day of month, month and day of
+/// week), optionally preceded by a seconds field and followed by a year
+/// field. For example, `*/5 * * * *` fires every five minutes.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    expression: String,
+    inner: cron::Schedule,
+}
+
+impl Schedule {
+    /// The schedule's cron expression.
+    #[must_use]
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// The first time the schedule fires after `after`.
+    #[must_use]
+    pub fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.inner.after(after).next()
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = expression.trim();
+
+        // the parser expects a leading seconds field
+        let normalized = if expression.split_whitespace().count() == 5 {
+            format!("0 {expression}")
+        } else {
+            expression.to_string()
+        };
+        let inner = cron::Schedule::from_str(&normalized)
+            .with_context(|| format!("invalid cron expression `{expression}`"))?;
+
+        Ok(Self {
+            expression: expression.to_string(),
+            inner,
+        })
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn five_fields() {
+        let schedule = "*/5 * * * *".parse::<Schedule>().expect("should parse");
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 10, 3, 20).unwrap();
+        let next = schedule.next_after(&now).expect("should fire");
+        assert_eq!(next, Utc.with_ymd_and_hms(2026, 1, 1, 10, 5, 0).unwrap());
+        assert_eq!(schedule.expression(), "*/5 * * * *");
+    }
+
+    #[test]
+    fn seconds_field() {
+        let schedule = "30 0 * * * *".parse::<Schedule>().expect("should parse");
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 10, 3, 20).unwrap();
+        let next = schedule.next_after(&now).expect("should fire");
+        assert_eq!(next, Utc.with_ymd_and_hms(2026, 1, 1, 11, 0, 30).unwrap());
+    }
+
+    #[test]
+    fn invalid() {
+        let err = "every five minutes".parse::<Schedule>().expect_err("should not parse");
+        assert!(err.to_string().contains("invalid cron expression `every five minutes`"));
+    }
+}
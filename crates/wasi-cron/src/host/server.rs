@@ -0,0 +1,417 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use omnia::{Concurrency, Invocation, Shutdown, State};
+use tracing::{Instrument, info_span, instrument};
+
+use crate::host::WasiCronView;
+use crate::host::generated::Cron;
+use crate::host::generated::exports::omnia::cron::handler::Tick;
+use crate::host::schedule::Schedule;
+
+/// Each component's handler is fired on the component's schedules. A tick is
+/// skipped while the handler is still running for the schedule's previous
+/// tick, or while too many guest invocations are in flight.
+#[instrument("cron-server", skip(components, shutdown))]
+pub async fn run<S>(components: &[S], shutdown: &Shutdown) -> Result<()>
+where
+    S: State,
+    S::StoreCtx: WasiCronView,
+{
+    let concurrency = shutdown.concurrency().for_server("cron");
+    let mut timers = Vec::new();
+    for state in components {
+        let handler = Handler {
+            state: state.clone(),
+            component: state.name().to_string(),
+        };
+        let (schedules, jitter) = handler.schedules()?;
+        for schedule in schedules {
+            tracing::info!("scheduling {} on: {schedule}", handler.component);
+            timers.push(handler.clone().timer(
+                schedule,
+                jitter,
+                concurrency.clone(),
+                shutdown.clone(),
+            ));
+        }
+    }
+    shutdown.listening();
+
+    // fire ticks until shutdown is signalled, then drain in-flight invocations
+    join_all(timers).await;
+    tracing::info!("cron server shutting down");
+    shutdown.drain().await;
+
+    Ok(())
+}
+
+#[derive(Clone)]
+struct Handler<S>
+where
+    S: State,
+    S::StoreCtx: WasiCronView,
+{
+    state: S,
+    component: String,
+}
+
+impl<S> Handler<S>
+where
+    S: State,
+    S::StoreCtx: WasiCronView,
+{
+    /// Fire the handler each time the schedule is due.
+    async fn timer(
+        self, schedule: Schedule, jitter: Duration, concurrency: Concurrency, shutdown: Shutdown,
+    ) {
+        let timer = Timer {
+            component: self.component.clone(),
+            schedule,
+            jitter,
+            concurrency,
+            shutdown,
+            clock: Utc::now,
+        };
+        timer
+            .run(move |tick| {
+                let handler = self.clone();
+                async move { handler.handle(tick).await }
+            })
+            .await;
+    }
+
+    /// Forward the tick to the wasm guest.
+    async fn handle(&self, tick: Tick) -> Result<()> {
+        let invocation = Invocation::background(&self.component);
+        let mut store = self.state.new_store(invocation.clone())?;
+        let instance_pre = self.state.instance_pre();
+        let instance = self
+            .state
+            .instantiate(&instance_pre, &mut store)
+            .instrument(invocation.span.clone())
+            .await?;
+        let cron = Cron::new(&mut store, &instance)?;
+
+        store
+            .run_concurrent(async |store| {
+                let guest = cron.omnia_cron_handler();
+                guest
+                    .call_handle(store, tick)
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .context("issue handling tick")?
+                    .map_err(|e| anyhow!("guest failed to handle tick: {e:?}"))
+            })
+            .instrument(invocation.span)
+            .await?
+    }
+
+    /// The component's schedules and the jitter applied to each tick.
+    fn schedules(&self) -> Result<(Vec<Schedule>, Duration)> {
+        let mut store = self.state.new_store(Invocation::background(&self.component))?;
+        let cron = store.data_mut().cron();
+        let schedules = cron
+            .ctx
+            .schedules(&self.component)
+            .with_context(|| format!("loading schedules for {}", self.component))?;
+        Ok((schedules, cron.ctx.jitter()))
+    }
+}
+
+/// Fires ticks for a single schedule.
+struct Timer<C> {
+    component: String,
+    schedule: Schedule,
+    jitter: Duration,
+    concurrency: Concurrency,
+    shutdown: Shutdown,
+
+    // the current time, e.g. `Utc::now`
+    clock: C,
+}
+
+impl<C: Fn() -> DateTime<Utc>> Timer<C> {
+    /// Call `handle` with each tick, within the tick's own span, until
+    /// shutdown is signalled.
+    async fn run<F, Fut>(self, handle: F)
+    where
+        F: Fn(Tick) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let Self {
+            component,
+            schedule,
+            jitter,
+            concurrency,
+            shutdown,
+            clock,
+        } = self;
+        let running = Arc::new(AtomicBool::new(false));
+        let mut last = clock();
+
+        loop {
+            let now = clock();
+            let Some(scheduled_at) = next_tick(&schedule, &last, &now) else {
+                tracing::info!("schedule {schedule} for {component} has no more ticks");
+                return;
+            };
+            last = scheduled_at;
+            let delay = (scheduled_at - now).to_std().unwrap_or_default() + random_delay(jitter);
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {}
+                () = shutdown.signalled() => return,
+            }
+
+            if running.swap(true, Ordering::AcqRel) {
+                tracing::warn!(
+                    monotonic_counter.ticks_skipped = 1,
+                    service = %component,
+                    schedule = %schedule,
+                    "previous tick still running",
+                );
+                continue;
+            }
+            let Some(permit) = concurrency.try_acquire() else {
+                tracing::warn!(
+                    monotonic_counter.ticks_skipped = 1,
+                    service = %component,
+                    schedule = %schedule,
+                    "too many invocations in flight",
+                );
+                running.store(false, Ordering::Release);
+                continue;
+            };
+
+            let component = component.clone();
+            let running = Arc::clone(&running);
+            let tick = tick(&schedule, scheduled_at);
+            let span = info_span!(
+                "cron-tick",
+                service = %component,
+                schedule = %tick.schedule,
+                scheduled_at = %scheduled_at,
+            );
+            let handled = handle(tick).instrument(span);
+            shutdown.spawn(async move {
+                let _permit = permit;
+                tracing::info!(monotonic_counter.tick_counter = 1, service = %component);
+
+                if let Err(e) = handled.await {
+                    if omnia::is_deadline_exceeded(&e) {
+                        tracing::warn!(
+                            monotonic_counter.deadline_exceeded = 1,
+                            service = %component,
+                            "guest deadline exceeded",
+                        );
+                    } else if omnia::is_fuel_exhausted(&e) {
+                        tracing::warn!(
+                            monotonic_counter.fuel_exhausted = 1,
+                            service = %component,
+                            "guest fuel exhausted",
+                        );
+                    } else if let Some(exceeded) = omnia::limit_exceeded(&e) {
+                        tracing::warn!(
+                            monotonic_counter.limit_exceeded = 1,
+                            service = %component,
+                            resource = %exceeded.resource,
+                            "{exceeded}",
+                        );
+                    }
+                    tracing::error!(
+                        monotonic_counter.processing_errors = 1,
+                        service = %component,
+                        error = %e,
+                    );
+                }
+                running.store(false, Ordering::Release);
+            });
+        }
+    }
+}
+
+// The tick following `last`, the previous tick (or when the timer started).
+// Ticks are counted from the previous tick rather than the current time so a
+// timer waking early does not fire the same tick twice. Ticks missed while
+// the runtime could not fire them (e.g. while suspended) are collapsed into
+// the latest missed tick.
+fn next_tick(
+    schedule: &Schedule, last: &DateTime<Utc>, now: &DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let mut next = schedule.next_after(last)?;
+    while let Some(after) = schedule.next_after(&next).filter(|after| after <= now) {
+        next = after;
+    }
+    Some(next)
+}
+
+fn tick(schedule: &Schedule, scheduled_at: DateTime<Utc>) -> Tick {
+    Tick {
+        schedule: schedule.expression().to_string(),
+        scheduled_at: u64::try_from(scheduled_at.timestamp_millis()).unwrap_or_default(),
+    }
+}
+
+// A random delay of up to `jitter`.
+fn random_delay(jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return Duration::ZERO;
+    }
+    let millis = u64::try_from(jitter.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(rand::random_range(0..=millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::sync::atomic::AtomicUsize;
+
+    use chrono::TimeDelta;
+    use tokio::time::Instant;
+    use tracing::Span;
+    use tracing_subscriber::Registry;
+
+    use super::*;
+
+    const EVERY_SECOND: &str = "* * * * * *";
+
+    // A timer whose clock follows tokio's (paused) clock.
+    fn timer(jitter: Duration, shutdown: &Shutdown) -> Timer<impl Fn() -> DateTime<Utc>> {
+        let (wall, started) = (Utc::now(), Instant::now());
+        Timer {
+            component: "cron-test".to_string(),
+            schedule: EVERY_SECOND.parse().expect("should parse"),
+            jitter,
+            concurrency: Concurrency::default(),
+            shutdown: shutdown.clone(),
+            clock: move || wall + TimeDelta::from_std(started.elapsed()).expect("should convert"),
+        }
+    }
+
+    // Run the timer for ten (simulated) seconds.
+    async fn run_for_ten_seconds<F, Fut>(jitter: Duration, handle: F)
+    where
+        F: Fn(Tick) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let (shutdown, trigger) = Shutdown::new(Duration::from_secs(1));
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            trigger.trigger();
+        });
+        timer(jitter, &shutdown).run(handle).await;
+    }
+
+    #[test]
+    fn next_tick_follows_previous_tick() {
+        let schedule: Schedule = EVERY_SECOND.parse().expect("should parse");
+        let last = DateTime::parse_from_rfc3339("2026-10-17T10:00:00Z").unwrap().to_utc();
+
+        // waking just before the previous tick does not fire it again
+        let early = last - TimeDelta::milliseconds(100);
+        assert_eq!(next_tick(&schedule, &last, &early), Some(last + TimeDelta::seconds(1)));
+
+        // missed ticks are collapsed into the latest
+        let late = last + TimeDelta::milliseconds(5500);
+        assert_eq!(next_tick(&schedule, &last, &late), Some(last + TimeDelta::seconds(5)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_ticks_while_running() {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let overlapped = Arc::new(AtomicBool::new(false));
+
+        run_for_ten_seconds(Duration::ZERO, |_tick| {
+            let (fired, in_flight, overlapped) =
+                (Arc::clone(&fired), Arc::clone(&in_flight), Arc::clone(&overlapped));
+            async move {
+                fired.fetch_add(1, Ordering::SeqCst);
+                if in_flight.fetch_add(1, Ordering::SeqCst) > 0 {
+                    overlapped.store(true, Ordering::SeqCst);
+                }
+                // each invocation spans the next two ticks
+                tokio::time::sleep(Duration::from_millis(2500)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })
+        .await;
+
+        assert!(!overlapped.load(Ordering::SeqCst));
+        let fired = fired.load(Ordering::SeqCst);
+        assert!((3..=4).contains(&fired), "fired {fired} of 10 ticks");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn jitter_delays_ticks() {
+        let jitter = Duration::from_millis(500);
+        let (wall, started) = (Utc::now(), Instant::now());
+        let late = Arc::new(Mutex::new(Vec::new()));
+
+        run_for_ten_seconds(jitter, |tick| {
+            let late = Arc::clone(&late);
+            async move {
+                // how long after its scheduled time the tick fired
+                let now = wall + TimeDelta::from_std(started.elapsed()).unwrap();
+                let scheduled_at = i64::try_from(tick.scheduled_at).unwrap();
+                late.lock().unwrap().push(now.timestamp_millis() - scheduled_at);
+                Ok(())
+            }
+        })
+        .await;
+
+        let late = std::mem::take(&mut *late.lock().unwrap());
+        assert!(late.len() >= 8, "fired {} ticks", late.len());
+        // allow for the clocks of the test and timer being read separately
+        assert!(late.iter().all(|late| (-10..=510).contains(late)), "{late:?}");
+        assert!(late.windows(2).any(|pair| pair[0] != pair[1]), "{late:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn each_tick_has_its_own_span() {
+        let _guard = tracing::subscriber::set_default(Registry::default());
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+
+        run_for_ten_seconds(Duration::ZERO, |tick| {
+            let ticks = Arc::clone(&ticks);
+            async move {
+                let span = Span::current();
+                ticks.lock().unwrap().push((
+                    span.metadata().map(tracing::Metadata::name),
+                    span.id(),
+                    tick,
+                ));
+                Ok(())
+            }
+        })
+        .await;
+
+        let ticks = std::mem::take(&mut *ticks.lock().unwrap());
+        assert!(ticks.len() >= 9, "fired {} ticks", ticks.len());
+        for (i, (name, id, tick)) in ticks.iter().enumerate() {
+            assert_eq!(*name, Some("cron-tick"));
+            assert!(ticks[..i].iter().all(|(_, other, _)| other != id), "span reused");
+            assert_eq!(tick.schedule, EVERY_SECOND);
+            if i > 0 {
+                // consecutive ticks follow the previous tick
+                assert_eq!(tick.scheduled_at - ticks[i - 1].2.scheduled_at, 1000);
+            }
+        }
+    }
+
+    #[test]
+    fn delays_within_jitter() {
+        assert_eq!(random_delay(Duration::ZERO), Duration::ZERO);
+
+        let jitter = Duration::from_secs(2);
+        for _ in 0..100 {
+            assert!(random_delay(jitter) <= jitter);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+#![doc = include_str!("../README.md")]
+
+//! # WASI Cron Service
+//!
+//! This module implements a runtime service for `omnia:cron`, firing a
+//! guest's scheduled handler on cron expressions.
+
+#![forbid(unsafe_code)]
+
+#[cfg(target_arch = "wasm32")]
+mod guest;
+#[cfg(target_arch = "wasm32")]
+pub use guest::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod host;
+#[cfg(not(target_arch = "wasm32"))]
+pub use host::*;
@@ -3,13 +3,18 @@
 //! This module implements a host-side service for `wasi:http`
 
 mod default_impl;
+mod egress;
 mod server;
+mod tls;
 
 use anyhow::Result;
 pub use default_impl::HttpDefault;
-use omnia::{Host, Server, State};
+use omnia::{Host, Server, Shutdown, State};
+pub use server::{OutgoingBody, handle};
+pub use tls::CLIENT_CERT_SUBJECT_HEADER;
 use wasmtime::component::Linker;
 pub use wasmtime_wasi_http::WasiHttpCtx;
+pub use wasmtime_wasi_http::p3::bindings::http::types::ErrorCode;
 pub use wasmtime_wasi_http::p3::{WasiHttpCtxView, WasiHttpView};
 
 /// Host-side service for `wasi:http`.
@@ -30,8 +35,12 @@ where
     S: State,
     S::StoreCtx: WasiHttpView,
 {
-    async fn run(&self, state: &S) -> Result<()> {
-        server::serve(state).await
+    async fn run(&self, components: &[S], shutdown: &Shutdown) -> Result<()> {
+        server::serve(components, shutdown).await
+    }
+
+    fn env_vars(&self) -> Vec<String> {
+        server::env_vars()
     }
 }
 
@@ -41,7 +50,7 @@ macro_rules! omnia_wasi_view {
     ($store_ctx:ty, $field_name:ident) => {
         impl omnia_wasi_http::WasiHttpView for $store_ctx {
             fn http(&mut self) -> omnia_wasi_http::WasiHttpCtxView<'_> {
-                self.$field_name.as_view(&mut self.table)
+                self.$field_name.as_view(&mut self.table, &self.capabilities)
             }
         }
     };
@@ -1,4 +1,8 @@
+use std::error::Error;
 use std::fmt::Display;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context as TaskContext, Poll, ready};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
@@ -13,7 +17,12 @@ use http::header::{
 use http::{Request, Response};
 use http_body_util::BodyExt;
 use http_body_util::combinators::UnsyncBoxBody;
-use omnia::Backend;
+use hyper::body::{Body, Frame, SizeHint};
+use moka::sync::Cache;
+use omnia::{Backend, Capabilities, Capability};
+use reqwest::redirect;
+use tokio::sync::oneshot;
+use tokio::time::{Instant, Sleep};
 use tracing::instrument;
 use wasmtime::component::ResourceTable;
 use wasmtime_wasi::TrappableError;
@@ -21,6 +30,8 @@ use wasmtime_wasi_http::WasiHttpCtx;
 use wasmtime_wasi_http::p3::bindings::http::types::ErrorCode;
 use wasmtime_wasi_http::p3::{self, RequestOptions, WasiHttpCtxView};
 
+use crate::host::egress::{self, Egress, EgressResolver};
+
 pub type HttpResult<T> = Result<T, HttpError>;
 pub type HttpError = TrappableError<ErrorCode>;
 pub type FutureResult<T> = Box<dyn Future<Output = Result<T, ErrorCode>> + Send>;
@@ -44,19 +55,45 @@ pub struct ConnectOptions {
     pub addr: String,
     #[env(from = "HTTP_CONNECT_TIMEOUT_SECS", default = "10")]
     pub connect_timeout_secs: u64,
+    /// Comma-separated hosts (`*.` prefixed for subdomains), IP addresses and
+    /// CIDRs guests may always send requests to.
+    #[env(from = "HTTP_EGRESS_ALLOW", default = "")]
+    pub egress_allow: String,
+    /// Deny requests to destinations not in `HTTP_EGRESS_ALLOW`.
+    #[env(from = "HTTP_EGRESS_DENY_BY_DEFAULT", default = "false")]
+    pub egress_deny_by_default: bool,
+    /// Deny requests to loopback, private, link-local and other non-public
+    /// addresses not in `HTTP_EGRESS_ALLOW`.
+    #[env(from = "HTTP_EGRESS_BLOCK_PRIVATE", default = "true")]
+    pub egress_block_private: bool,
 }
 
 impl omnia::FromEnv for ConnectOptions {
     fn from_env() -> Result<Self> {
         Self::from_env().finalize().context("issue loading connection options")
     }
+
+    fn env_vars() -> Vec<String> {
+        omnia::requirement_vars(&Self::requirements())
+    }
 }
 
+/// Maximum number of clients kept for requests with a non-default connect
+/// timeout.
+const MAX_TIMEOUT_CLIENTS: u64 = 16;
+
 /// Reqwest-based HTTP hooks for outbound `wasi:http` requests.
 #[derive(Debug, Clone)]
 struct HttpHooks {
     client: reqwest::Client,
     connect_timeout: Duration,
+    /// Clients for requests with a non-default connect timeout, keyed by
+    /// timeout, so their connections are pooled too.
+    timeout_clients: Cache<Duration, reqwest::Client>,
+    egress: Arc<Egress>,
+    /// The guest's capabilities, set when its store is first viewed.
+    /// Unrestricted until then.
+    capabilities: Option<Capabilities>,
 }
 
 /// Default implementation for `wasi:http`.
@@ -68,7 +105,15 @@ pub struct HttpDefault {
 
 impl HttpDefault {
     /// Produce a [`WasiHttpCtxView`] by splitting borrows on inner fields.
-    pub fn as_view<'a>(&'a mut self, table: &'a mut ResourceTable) -> WasiHttpCtxView<'a> {
+    ///
+    /// Outbound requests are restricted to the hosts allowed by the guest's
+    /// `capabilities`. `wasmtime`'s view has no field for them, so they are
+    /// kept by the view's hooks, which belong to a single store and are only
+    /// given them when first viewed.
+    pub fn as_view<'a>(
+        &'a mut self, table: &'a mut ResourceTable, capabilities: &Capabilities,
+    ) -> WasiHttpCtxView<'a> {
+        self.hooks.capabilities.get_or_insert_with(|| capabilities.clone());
         WasiHttpCtxView {
             hooks: &mut self.hooks,
             ctx: &mut self.ctx,
@@ -83,17 +128,23 @@ impl Backend for HttpDefault {
     #[instrument]
     async fn connect_with(options: Self::ConnectOptions) -> Result<Self> {
         let connect_timeout = Duration::from_secs(options.connect_timeout_secs);
-
-        let builder = reqwest::Client::builder().connect_timeout(connect_timeout);
-
-        #[cfg(test)]
-        let builder = builder.no_proxy();
-
-        let client = builder.build().context("building HTTP client")?;
+        let egress = Egress::new(
+            &options.egress_allow,
+            options.egress_deny_by_default,
+            options.egress_block_private,
+        )
+        .context("parsing egress rules")?;
+        let egress = Arc::new(egress);
+
+        let client =
+            client_builder(connect_timeout, &egress).build().context("building HTTP client")?;
         Ok(Self {
             hooks: HttpHooks {
                 client,
                 connect_timeout,
+                timeout_clients: Cache::new(MAX_TIMEOUT_CLIENTS),
+                egress,
+                capabilities: None,
             },
             ctx: WasiHttpCtx::default(),
         })
@@ -103,55 +154,88 @@ impl Backend for HttpDefault {
 impl p3::WasiHttpHooks for HttpHooks {
     fn send_request(
         &mut self, request: Request<UnsyncBoxBody<Bytes, ErrorCode>>,
-        _options: Option<RequestOptions>, fut: FutureResult<()>,
+        options: Option<RequestOptions>, fut: FutureResult<()>,
     ) -> Box<
         dyn Future<
                 Output = HttpResult<(Response<UnsyncBoxBody<Bytes, ErrorCode>>, FutureResult<()>)>,
             > + Send,
     > {
         let shared_client = self.client.clone();
-        let connect_timeout = self.connect_timeout;
+        let timeout_clients = self.timeout_clients.clone();
+        let options = options.unwrap_or_default();
+        let connect_timeout = options.connect_timeout.unwrap_or(self.connect_timeout);
+        let pooled = connect_timeout == self.connect_timeout;
+        let egress = Arc::clone(&self.egress);
+        let host = request.uri().host().unwrap_or_default();
+        let allowed = self
+            .capabilities
+            .as_ref()
+            .is_none_or(|capabilities| capabilities.check(Capability::HttpHost, host).is_ok())
+            && egress.check_host(host).is_ok();
 
         Box::new(async move {
+            if !allowed {
+                return Err(ErrorCode::HttpRequestDenied.into());
+            }
             let (mut parts, body) = request.into_parts();
 
             // remove "Host" headers (`reqwest` adds its own)
             parts.headers.remove(HOST);
 
-            // use a one-off client when a client certificate is required, otherwise
-            // reuse the shared client for connection pooling
+            // use a one-off client when a client certificate is required,
+            // otherwise reuse a client with the required connect timeout for
+            // connection pooling
             let client = if let Some(encoded_cert) = parts.headers.remove("Client-Cert") {
                 tracing::debug!("using client certificate");
                 let encoded = encoded_cert.to_str().map_err(internal_err)?;
                 let bytes = Base64::decode_vec(encoded).map_err(internal_err)?;
                 let identity = reqwest::Identity::from_pem(&bytes).map_err(internal_err)?;
-                let builder =
-                    reqwest::Client::builder().connect_timeout(connect_timeout).identity(identity);
-
-                #[cfg(test)]
-                let builder = builder.no_proxy();
-
-                builder.build().map_err(reqwest_err)?
-            } else {
+                client_builder(connect_timeout, &egress)
+                    .identity(identity)
+                    .build()
+                    .map_err(reqwest_err)?
+            } else if pooled {
                 shared_client
+            } else {
+                timeout_clients
+                    .try_get_with(connect_timeout, || {
+                        client_builder(connect_timeout, &egress).build()
+                    })
+                    .map_err(|e| internal_err(&e))?
             };
 
-            let collected = body.collect().await.map_err(internal_err)?;
-
-            // make request
+            // make request, streaming the guest's body as it is written
             let url = parts.uri.to_string();
-            let resp = client
+            let (sent_tx, sent_rx) = oneshot::channel();
+            let send = client
                 .request(parts.method, &url)
                 .headers(parts.headers)
-                .body(collected.to_bytes())
-                .send()
-                .await
-                .map_err(reqwest_err)?;
+                .body(reqwest::Body::wrap(SyncBody::new(body, sent_tx)))
+                .send();
+
+            // the first byte timeout starts once the request has been sent
+            let resp = match options.first_byte_timeout {
+                Some(timeout) => {
+                    tokio::pin!(send);
+                    tokio::select! {
+                        resp = &mut send => resp,
+                        _ = sent_rx => tokio::time::timeout(timeout, send)
+                            .await
+                            .map_err(|_elapsed| ErrorCode::ConnectionReadTimeout)?,
+                    }
+                }
+                None => send.await,
+            };
+            let resp = resp.map_err(reqwest_err)?;
 
             // process response
             let converted: Response<reqwest::Body> = resp.into();
             let (parts, body) = converted.into_parts();
             let body = body.map_err(reqwest_err).boxed_unsync();
+            let body = match options.between_bytes_timeout {
+                Some(timeout) => BetweenBytesTimeout::new(body, timeout).boxed_unsync(),
+                None => body,
+            };
             let mut response = Response::from_parts(parts, body);
 
             // remove forbidden headers (disallowed by `wasmtime-wasi-http`)
@@ -165,13 +249,138 @@ impl p3::WasiHttpHooks for HttpHooks {
     }
 }
 
+/// An outgoing request body that can be shared between threads, as required
+/// by `reqwest`.
+///
+/// The body is only ever polled through a mutable reference, so the lock is
+/// never contended. `sent` is signalled once the body has been written in
+/// full, or dropped by the client.
+struct SyncBody {
+    body: Mutex<UnsyncBoxBody<Bytes, ErrorCode>>,
+    sent: Option<oneshot::Sender<()>>,
+}
+
+impl SyncBody {
+    const fn new(body: UnsyncBoxBody<Bytes, ErrorCode>, sent: oneshot::Sender<()>) -> Self {
+        Self {
+            body: Mutex::new(body),
+            sent: Some(sent),
+        }
+    }
+}
+
+impl Body for SyncBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        self: Pin<&mut Self>, cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let body = this.body.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let frame = ready!(Pin::new(body).poll_frame(cx));
+        if frame.is_none()
+            && let Some(sent) = this.sent.take()
+        {
+            let _ = sent.send(());
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.lock().is_ok_and(|body| body.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.lock().map(|body| body.size_hint()).unwrap_or_default()
+    }
+}
+
+impl Drop for SyncBody {
+    fn drop(&mut self) {
+        if let Some(sent) = self.sent.take() {
+            let _ = sent.send(());
+        }
+    }
+}
+
+/// A response body that fails when the time between frames exceeds the
+/// request's `between-bytes-timeout`.
+struct BetweenBytesTimeout {
+    body: UnsyncBoxBody<Bytes, ErrorCode>,
+    timeout: Duration,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl BetweenBytesTimeout {
+    fn new(body: UnsyncBoxBody<Bytes, ErrorCode>, timeout: Duration) -> Self {
+        Self {
+            body,
+            timeout,
+            sleep: Box::pin(tokio::time::sleep(timeout)),
+        }
+    }
+}
+
+impl Body for BetweenBytesTimeout {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.body).poll_frame(cx) {
+            let deadline = Instant::now() + self.timeout;
+            self.sleep.as_mut().reset(deadline);
+            return Poll::Ready(frame);
+        }
+        ready!(self.sleep.as_mut().poll(cx));
+        Poll::Ready(Some(Err(ErrorCode::ConnectionReadTimeout)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+// Build an HTTP client that enforces the egress rules.
+//
+// Redirects are returned to the guest rather than followed, so that each hop
+// is checked against the guest's capabilities as a new request.
+//
+// Proxies configured using `HTTP(S)_PROXY` are ignored: requests sent via a
+// proxy would resolve the proxy's address rather than the destination's,
+// bypassing the egress rules.
+fn client_builder(connect_timeout: Duration, egress: &Arc<Egress>) -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .dns_resolver(Arc::new(EgressResolver(Arc::clone(egress))))
+        .redirect(redirect::Policy::none())
+        .no_proxy()
+}
+
 fn internal_err(e: impl Display) -> ErrorCode {
     ErrorCode::InternalError(Some(e.to_string()))
 }
 
 #[allow(clippy::needless_pass_by_value)]
 fn reqwest_err(e: reqwest::Error) -> ErrorCode {
-    if e.is_timeout() {
+    // an error reading the guest's body is returned as it was raised
+    let mut source = e.source();
+    while let Some(err) = source {
+        if let Some(code) = err.downcast_ref::<ErrorCode>() {
+            return code.clone();
+        }
+        source = err.source();
+    }
+
+    if egress::is_denied(&e) {
+        ErrorCode::HttpRequestDenied
+    } else if e.is_timeout() {
         ErrorCode::ConnectionTimeout
     } else if e.is_connect() {
         ErrorCode::ConnectionRefused
@@ -184,12 +393,20 @@ fn reqwest_err(e: reqwest::Error) -> ErrorCode {
 
 #[cfg(test)]
 mod tests {
-    use std::pin::Pin;
-
-    use http::header::{AUTHORIZATION, CONTENT_TYPE};
-    use http::{Method, StatusCode};
-    use http_body_util::{Empty, Full};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use futures::StreamExt;
+    use http::header::{AUTHORIZATION, CONTENT_TYPE, LOCATION, TRAILER};
+    use http::{HeaderMap, HeaderValue, Method, StatusCode};
+    use http_body_util::{Empty, Full, StreamBody};
+    use hyper::body::Incoming;
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
     use p3::WasiHttpHooks;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpSocket, TcpStream};
     use wiremock::matchers::{body_string, header, method};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
@@ -199,6 +416,9 @@ mod tests {
         let options = ConnectOptions {
             addr: String::new(),
             connect_timeout_secs: 10,
+            egress_allow: String::new(),
+            egress_deny_by_default: false,
+            egress_block_private: false,
         };
         HttpDefault::connect_with(options).await.unwrap()
     }
@@ -365,6 +585,375 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn egress_denied() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+
+        let options = ConnectOptions {
+            addr: String::new(),
+            connect_timeout_secs: 10,
+            egress_allow: String::new(),
+            egress_deny_by_default: false,
+            egress_block_private: true,
+        };
+        let mut client = HttpDefault::connect_with(options).await.unwrap();
+        let port = server.address().port();
+
+        // blocked before sending (IP literal) and after resolution (host name)
+        for uri in [format!("http://127.0.0.1:{port}"), format!("http://localhost:{port}")] {
+            let request =
+                Request::get(&uri).body(Empty::new().map_err(internal_err).boxed_unsync()).unwrap();
+            let Err(err) = client.handle(request).await else {
+                panic!("request to {uri} should be denied");
+            };
+            assert!(matches!(err.downcast(), Ok(ErrorCode::HttpRequestDenied)), "{uri}");
+        }
+
+        let requests = server.received_requests().await.expect("should have requests");
+        assert!(requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn denied_host() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+
+        let mut client = test_client().await;
+        client.hooks.capabilities = Some(Capabilities::granted(omnia::Grants {
+            http_hosts: vec!["api.example.com".into()],
+            ..omnia::Grants::default()
+        }));
+
+        let request = Request::get(server.uri())
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let Err(err) = client.handle(request).await else {
+            panic!("request should be denied");
+        };
+        assert!(matches!(err.downcast(), Ok(ErrorCode::HttpRequestDenied)));
+
+        let requests = server.received_requests().await.expect("should have requests");
+        assert!(requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn view_keeps_store_capabilities() {
+        let mut client = test_client().await;
+        let mut table = ResourceTable::new();
+        let granted = Capabilities::granted(omnia::Grants {
+            http_hosts: vec!["api.example.com".into()],
+            ..omnia::Grants::default()
+        });
+
+        // the store's hooks are given its capabilities when first viewed
+        client.as_view(&mut table, &granted);
+        let view = client.as_view(&mut table, &Capabilities::unrestricted());
+
+        let request = Request::get("http://localhost:1/")
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let boxed = view.hooks.send_request(request, None, Box::new(async { Ok(()) }));
+        let Err(err) = Pin::from(boxed).await else {
+            panic!("request should be denied");
+        };
+        assert!(matches!(err.downcast(), Ok(ErrorCode::HttpRequestDenied)));
+    }
+
+    #[tokio::test]
+    async fn redirect_not_followed() {
+        let other = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200)).mount(&other).await;
+
+        // a granted host redirecting to a host that is not granted
+        let server = MockServer::start().await;
+        let location = format!("http://localhost:{}/", other.address().port());
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(302).insert_header(LOCATION, location.as_str()))
+            .mount(&server)
+            .await;
+
+        let mut client = test_client().await;
+        client.hooks.capabilities = Some(Capabilities::granted(omnia::Grants {
+            http_hosts: vec!["127.0.0.1".into()],
+            ..omnia::Grants::default()
+        }));
+
+        let request = Request::get(server.uri())
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let (response, _) = client.handle(request).await.expect("should send");
+        assert_eq!(response.status(), StatusCode::FOUND);
+
+        // following the redirect is a new request, checked again
+        let request = Request::get(&location)
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let Err(err) = client.handle(request).await else {
+            panic!("redirect should be denied");
+        };
+        assert!(matches!(err.downcast(), Ok(ErrorCode::HttpRequestDenied)));
+
+        let requests = other.received_requests().await.expect("should have requests");
+        assert!(requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn streamed_body() {
+        const CHUNK: u64 = 64 * 1024;
+        const TOTAL: u64 = 256 * 1024 * 1024;
+
+        let received = Arc::new(AtomicU64::new(0));
+        let addr = sink(Arc::clone(&received)).await;
+
+        // track how far the guest's body gets ahead of the server reading it
+        let produced = Arc::new(AtomicU64::new(0));
+        let max_ahead = Arc::new(AtomicU64::new(0));
+        let chunk = Bytes::from(vec![0; usize::try_from(CHUNK).unwrap()]);
+        let stream = futures::stream::iter(0..TOTAL / CHUNK).map({
+            let produced = Arc::clone(&produced);
+            let max_ahead = Arc::clone(&max_ahead);
+            let received = Arc::clone(&received);
+            move |_| {
+                let ahead = produced.fetch_add(CHUNK, Ordering::SeqCst) + CHUNK
+                    - received.load(Ordering::SeqCst);
+                max_ahead.fetch_max(ahead, Ordering::SeqCst);
+                Ok(Frame::data(chunk.clone()))
+            }
+        });
+
+        let request = Request::post(format!("http://{addr}"))
+            .body(StreamBody::new(stream).boxed_unsync())
+            .unwrap();
+        let (response, _) = test_client().await.handle(request).await.expect("should send");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, TOTAL.to_string());
+
+        // the body is never buffered in full
+        let max_ahead = max_ahead.load(Ordering::SeqCst);
+        assert!(max_ahead < 32 * 1024 * 1024, "{max_ahead} bytes buffered");
+    }
+
+    #[tokio::test]
+    async fn streamed_trailers() {
+        let addr = sink(Arc::new(AtomicU64::new(0))).await;
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("abc123"));
+        let frames = [Ok(Frame::data(Bytes::from("hello"))), Ok(Frame::trailers(trailers))];
+        let request = Request::post(format!("http://{addr}"))
+            .header(TRAILER, "x-checksum")
+            .body(StreamBody::new(futures::stream::iter(frames)).boxed_unsync())
+            .unwrap();
+
+        let (response, _) = test_client().await.handle(request).await.expect("should send");
+        assert_eq!(response.headers()["x-checksum"], "abc123");
+    }
+
+    #[tokio::test]
+    async fn streamed_body_error() {
+        let addr = sink(Arc::new(AtomicU64::new(0))).await;
+
+        let frames =
+            [Ok(Frame::data(Bytes::from("hello"))), Err(ErrorCode::HttpRequestBodySize(Some(5)))];
+        let request = Request::post(format!("http://{addr}"))
+            .body(StreamBody::new(futures::stream::iter(frames)).boxed_unsync())
+            .unwrap();
+
+        let Err(err) = test_client().await.handle(request).await else {
+            panic!("request should fail");
+        };
+        assert!(matches!(err.downcast(), Ok(ErrorCode::HttpRequestBodySize(Some(5)))));
+    }
+
+    #[tokio::test]
+    async fn connect_timeout() {
+        // a listener that never accepts, with its backlog filled so further
+        // connection attempts are left waiting
+        let socket = TcpSocket::new_v4().expect("should create socket");
+        socket.bind("127.0.0.1:0".parse().unwrap()).expect("should bind");
+        let listener = socket.listen(1).expect("should listen");
+        let addr = listener.local_addr().expect("should have address");
+        let mut backlog = Vec::new();
+        for _ in 0..8 {
+            let Ok(Ok(stream)) =
+                tokio::time::timeout(Duration::from_millis(100), TcpStream::connect(addr)).await
+            else {
+                break;
+            };
+            backlog.push(stream);
+        }
+
+        let options = RequestOptions {
+            connect_timeout: Some(Duration::from_millis(200)),
+            ..RequestOptions::default()
+        };
+        let request = Request::get(format!("http://{addr}"))
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let Err(err) = test_client().await.handle_with(request, options).await else {
+            panic!("request should time out");
+        };
+        assert!(matches!(err.downcast(), Ok(ErrorCode::ConnectionTimeout)));
+
+        // the backlog is kept full until the request has timed out
+        drop(backlog);
+    }
+
+    #[tokio::test]
+    async fn first_byte_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+            .mount(&server)
+            .await;
+
+        let options = RequestOptions {
+            first_byte_timeout: Some(Duration::from_millis(200)),
+            ..RequestOptions::default()
+        };
+        let request = Request::get(server.uri())
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let Err(err) = test_client().await.handle_with(request, options).await else {
+            panic!("request should time out");
+        };
+        assert!(matches!(err.downcast(), Ok(ErrorCode::ConnectionReadTimeout)));
+    }
+
+    #[tokio::test]
+    async fn first_byte_timeout_after_upload() {
+        let addr = sink(Arc::new(AtomicU64::new(0))).await;
+
+        // the upload takes longer than the first byte timeout
+        let stream = futures::stream::iter(0..5).then(|_| async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(Frame::data(Bytes::from("hello")))
+        });
+        let options = RequestOptions {
+            first_byte_timeout: Some(Duration::from_millis(200)),
+            ..RequestOptions::default()
+        };
+        let request = Request::post(format!("http://{addr}"))
+            .body(StreamBody::new(stream).boxed_unsync())
+            .unwrap();
+        let (response, _) =
+            test_client().await.handle_with(request, options).await.expect("should respond");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "25");
+    }
+
+    #[tokio::test]
+    async fn timeout_clients_reused() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+
+        let mut client = test_client().await;
+        for timeout in [5, 5, 6] {
+            let options = RequestOptions {
+                connect_timeout: Some(Duration::from_secs(timeout)),
+                ..RequestOptions::default()
+            };
+            let request = Request::get(server.uri())
+                .body(Empty::new().map_err(internal_err).boxed_unsync())
+                .unwrap();
+            let (_response, _) =
+                client.handle_with(request, options).await.expect("should respond");
+        }
+        client.hooks.timeout_clients.run_pending_tasks();
+        assert_eq!(client.hooks.timeout_clients.entry_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn within_timeouts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("Hello, World!")
+                    .set_delay(Duration::from_millis(100)),
+            )
+            .mount(&server)
+            .await;
+
+        let options = RequestOptions {
+            connect_timeout: Some(Duration::from_secs(5)),
+            first_byte_timeout: Some(Duration::from_secs(5)),
+            between_bytes_timeout: Some(Duration::from_secs(5)),
+        };
+        let request = Request::get(server.uri())
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let (response, _) =
+            test_client().await.handle_with(request, options).await.expect("should respond");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, Bytes::from("Hello, World!"));
+    }
+
+    #[tokio::test]
+    async fn between_bytes_timeout() {
+        // a server that stalls after sending the first chunk of the body
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("should bind");
+        let addr = listener.local_addr().expect("should have address");
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("should accept");
+            let head = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n";
+            stream.write_all(head.as_bytes()).await.expect("should write");
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let options = RequestOptions {
+            between_bytes_timeout: Some(Duration::from_millis(200)),
+            ..RequestOptions::default()
+        };
+        let request = Request::get(format!("http://{addr}"))
+            .body(Empty::new().map_err(internal_err).boxed_unsync())
+            .unwrap();
+        let (response, _) =
+            test_client().await.handle_with(request, options).await.expect("should respond");
+
+        let mut body = response.into_body();
+        let frame = body.frame().await.expect("should have frame").expect("should read");
+        assert_eq!(frame.into_data().expect("should be data"), Bytes::from("hello"));
+        let err = body.frame().await.expect("should have frame").expect_err("should time out");
+        assert!(matches!(err, ErrorCode::ConnectionReadTimeout));
+    }
+
+    // Start a server that discards request bodies, responding with the number
+    // of bytes received and echoing any trailers as headers.
+    async fn sink(received: Arc<AtomicU64>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("should bind");
+        let addr = listener.local_addr().expect("should have address");
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let received = Arc::clone(&received);
+                let service = service_fn(move |request: Request<Incoming>| {
+                    let received = Arc::clone(&received);
+                    async move {
+                        let mut body = request.into_body();
+                        let mut response = Response::builder();
+                        while let Some(Ok(frame)) = body.frame().await {
+                            if let Some(data) = frame.data_ref() {
+                                received.fetch_add(data.len() as u64, Ordering::SeqCst);
+                            }
+                            for (name, value) in frame.trailers_ref().into_iter().flatten() {
+                                response = response.header(name, value);
+                            }
+                        }
+                        let total = received.load(Ordering::SeqCst).to_string();
+                        Ok::<_, Infallible>(response.body(Full::new(Bytes::from(total))).unwrap())
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service),
+                );
+            }
+        });
+        addr
+    }
+
     impl HttpDefault {
         async fn handle(
             &mut self, request: Request<UnsyncBoxBody<Bytes, ErrorCode>>,
@@ -372,5 +961,12 @@ mod tests {
             let boxed = self.hooks.send_request(request, None, Box::new(async { Ok(()) }));
             Pin::from(boxed).await
         }
+
+        async fn handle_with(
+            &mut self, request: Request<UnsyncBoxBody<Bytes, ErrorCode>>, options: RequestOptions,
+        ) -> HttpResult<(Response<UnsyncBoxBody<Bytes, ErrorCode>>, FutureResult<()>)> {
+            let boxed = self.hooks.send_request(request, Some(options), Box::new(async { Ok(()) }));
+            Pin::from(boxed).await
+        }
     }
 }
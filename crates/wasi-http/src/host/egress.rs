@@ -0,0 +1,378 @@
+//! # Egress Rules
+//!
+//! Restricts the destinations guests may send outbound HTTP requests to.
+//!
+//! Destinations are checked by host name before a request is sent and by IP
+//! address once the host has been resolved, so a permitted name cannot be
+//! used to reach a blocked address. Hosts are parsed as `reqwest` parses
+//! them, so numeric forms such as `127.1` or `0x7f.0.0.1` are checked as the
+//! address they denote. NAT64, 6to4, IPv4-compatible and Teredo addresses are
+//! checked as the addresses they embed, which is where they are routed.
+//! Redirects are
+//! not followed automatically: guests follow them with a new request, which
+//! is checked again.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::Host;
+
+/// Egress rules applied to outbound requests.
+#[derive(Debug, Clone, Default)]
+pub struct Egress {
+    /// Host names (or `*.`-prefixed domain suffixes) that are always allowed.
+    hosts: Vec<String>,
+
+    /// Networks that are always allowed.
+    nets: Vec<IpNet>,
+
+    /// Deny destinations that are not explicitly allowed.
+    deny_by_default: bool,
+
+    /// Deny loopback, private, link-local and other non-public addresses.
+    block_private: bool,
+}
+
+impl Egress {
+    /// Create egress rules from a comma-separated allow-list of hosts and
+    /// CIDRs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry looks like a CIDR but cannot be parsed.
+    pub fn new(allow: &str, deny_by_default: bool, block_private: bool) -> anyhow::Result<Self> {
+        let mut egress = Self {
+            deny_by_default,
+            block_private,
+            ..Self::default()
+        };
+
+        for entry in allow.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            if entry.contains('/') {
+                egress.nets.push(entry.parse().map_err(|e| anyhow::anyhow!("{entry}: {e}"))?);
+            } else if let Ok(ip) = entry.parse::<IpAddr>() {
+                egress.nets.push(IpNet::from(ip));
+            } else {
+                egress.hosts.push(entry.to_ascii_lowercase());
+            }
+        }
+
+        Ok(egress)
+    }
+
+    /// Check the request's destination host, before resolution.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EgressDenied`] if requests to the host are not permitted.
+    pub fn check_host(&self, host: &str) -> Result<(), EgressDenied> {
+        self.check_name(host).inspect_err(EgressDenied::record)
+    }
+
+    fn check_name(&self, host: &str) -> Result<(), EgressDenied> {
+        // IP literals are not resolved, so must be checked here, including
+        // the numeric forms the URL parser accepts (e.g. `2130706433`)
+        match Host::parse(host) {
+            Ok(Host::Ipv4(ip)) => return self.check_ip(host, IpAddr::V4(ip)),
+            Ok(Host::Ipv6(ip)) => return self.check_ip(host, IpAddr::V6(ip)),
+            Ok(Host::Domain(_)) => {}
+            Err(_) => return Err(EgressDenied(host.to_string())),
+        }
+        if self.deny_by_default && self.nets.is_empty() && !self.allows_name(host) {
+            return Err(EgressDenied(host.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check a resolved address for the host.
+    fn check_ip(&self, host: &str, ip: IpAddr) -> Result<(), EgressDenied> {
+        if self.allows_name(host) || self.nets.iter().any(|net| net.contains(&ip)) {
+            return Ok(());
+        }
+        if self.deny_by_default || (self.block_private && !is_public(ip)) {
+            return Err(EgressDenied(host.to_string()));
+        }
+        Ok(())
+    }
+
+    // Hosts allowed by name are trusted regardless of the address they
+    // resolve to.
+    fn allows_name(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.hosts.iter().any(|allowed| {
+            allowed.strip_prefix("*.").map_or(*allowed == host, |domain| {
+                host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.'))
+            })
+        })
+    }
+}
+
+/// Resolves host names, removing addresses that egress rules do not permit.
+#[derive(Debug)]
+pub struct EgressResolver(pub Arc<Egress>);
+
+impl Resolve for EgressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let egress = Arc::clone(&self.0);
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let resolved = tokio::net::lookup_host((host.as_str(), 0)).await?;
+
+            let mut denied = None;
+            let permitted = resolved
+                .filter(|addr| match egress.check_ip(&host, addr.ip()) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        denied = Some(e);
+                        false
+                    }
+                })
+                .collect::<Vec<SocketAddr>>();
+
+            // addresses are filtered individually, so the request is only
+            // denied when none remain
+            if permitted.is_empty()
+                && let Some(denied) = denied
+            {
+                denied.record();
+                return Err(denied.into());
+            }
+            let addrs: Addrs = Box::new(permitted.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Returned when egress rules do not permit a request's destination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EgressDenied(pub String);
+
+impl Display for EgressDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "outbound request to {} denied by egress rules", self.0)
+    }
+}
+
+impl Error for EgressDenied {}
+
+impl EgressDenied {
+    // Record a denied request.
+    fn record(&self) {
+        tracing::warn!(monotonic_counter.egress_denied = 1, host = %self.0, "outbound request denied");
+    }
+}
+
+/// Returns `true` if the error (or any of its sources) is an [`EgressDenied`].
+pub fn is_denied(err: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err.is::<EgressDenied>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+// Returns `true` if the address is publicly routable.
+const fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_v4(ip);
+            }
+            is_public_v6(ip)
+        }
+    }
+}
+
+const fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    let shared = a == 100 && b & 0xc0 == 64; // 100.64.0.0/10
+    let protocol = a == 192 && b == 0 && c == 0; // 192.0.0.0/24
+    let benchmarking = a == 198 && b & 0xfe == 18; // 198.18.0.0/15
+    let reserved = a >= 240; // 240.0.0.0/4, including broadcast
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || shared
+        || protocol
+        || benchmarking
+        || reserved
+        || a == 0)
+}
+
+const fn is_public_v6(ip: Ipv6Addr) -> bool {
+    // translation prefixes route to the IPv4 address they embed
+    if let Some(ip) = embedded_v4(ip) {
+        return is_public_v4(ip);
+    }
+    if let Some((server, client)) = teredo(ip) {
+        return is_public_v4(server) && is_public_v4(client);
+    }
+
+    let [first, second, third, ..] = ip.segments();
+    let unique_local = first & 0xfe00 == 0xfc00; // fc00::/7
+    let link_local = first & 0xffc0 == 0xfe80; // fe80::/10
+    let local_nat64 = first == 0x64 && second == 0xff9b && third == 1; // 64:ff9b:1::/48
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || unique_local
+        || link_local
+        || local_nat64)
+}
+
+// The IPv4 address embedded in a NAT64 (64:ff9b::/96), IPv4-compatible
+// (::/96) or 6to4 (2002::/16) address.
+const fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let octets = ip.octets();
+    let segments = ip.segments();
+    let nat64 = segments[0] == 0x64 && segments[1] == 0xff9b;
+    let compatible = segments[0] == 0 && segments[1] == 0;
+    if (nat64 || compatible)
+        && segments[2] == 0
+        && segments[3] == 0
+        && segments[4] == 0
+        && segments[5] == 0
+    {
+        return Some(Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]));
+    }
+    if segments[0] == 0x2002 {
+        return Some(Ipv4Addr::new(octets[2], octets[3], octets[4], octets[5]));
+    }
+    None
+}
+
+// The server and (obfuscated) client IPv4 addresses embedded in a Teredo
+// (2001::/32) address.
+const fn teredo(ip: Ipv6Addr) -> Option<(Ipv4Addr, Ipv4Addr)> {
+    let octets = ip.octets();
+    let segments = ip.segments();
+    if segments[0] != 0x2001 || segments[1] != 0 {
+        return None;
+    }
+    let server = Ipv4Addr::new(octets[4], octets[5], octets[6], octets[7]);
+    let client = Ipv4Addr::new(!octets[12], !octets[13], !octets[14], !octets[15]);
+    Some((server, client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_ranges() {
+        let egress = Egress::new("", false, true).expect("should parse");
+
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::7f00:1",
+            "64:ff9b:1::a00:1",
+            "2002:a9fe:a9fe::1",
+            "2002:c0a8:101::1",
+        ] {
+            egress.check_ip("host", ip.parse().unwrap()).expect_err(ip);
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1::1", "64:ff9b::5db8:d822", "2002:5db8:d822::1"]
+        {
+            egress.check_ip("host", ip.parse().unwrap()).expect(ip);
+        }
+
+        // literals are checked before resolution
+        egress.check_host("169.254.169.254").expect_err("metadata address");
+        egress.check_host("[::1]").expect_err("loopback address");
+        egress.check_host("example.com").expect("resolved later");
+    }
+
+    #[test]
+    fn reserved_ranges() {
+        let egress = Egress::new("", false, true).expect("should parse");
+
+        for (ip, public) in [
+            ("192.0.0.8", false),
+            ("192.0.1.1", true),
+            ("198.18.0.1", false),
+            ("198.19.255.255", false),
+            ("198.20.0.1", true),
+            ("240.0.0.1", false),
+            ("255.255.255.255", false),
+            ("::7f00:1", false),
+            ("::a9fe:a9fe", false),
+            ("::5db8:d822", true),
+            // server 65.54.227.120, client 127.0.0.1 / 93.184.216.34
+            ("2001:0:4136:e378:8000:63bf:80ff:fffe", false),
+            ("2001:0:4136:e378:8000:63bf:a247:27dd", true),
+            // server 10.0.0.1, client 93.184.216.34
+            ("2001:0:a00:1:8000:63bf:a247:27dd", false),
+        ] {
+            let result = egress.check_ip("host", ip.parse().unwrap());
+            assert_eq!(result.is_ok(), public, "{ip}");
+        }
+    }
+
+    #[test]
+    fn numeric_hosts() {
+        let egress = Egress::new("", false, true).expect("should parse");
+
+        // hosts the URL parser reads as IPv4 addresses
+        for host in ["2130706433", "127.1", "0x7f.0.0.1", "0177.0.0.1", "0x7f000001", "0xa9fea9fe"]
+        {
+            egress.check_host(host).expect_err(host);
+        }
+        egress.check_host("1572395042").expect("public address");
+        egress.check_host("[::7f00:1]").expect_err("IPv4-compatible loopback");
+    }
+
+    #[test]
+    fn allow_list() {
+        let egress = Egress::new("api.example.com, *.internal.test, 10.0.0.0/8", true, true)
+            .expect("should parse");
+
+        // allowed names are trusted whatever they resolve to
+        egress.check_ip("api.example.com", "127.0.0.1".parse().unwrap()).expect("allowed name");
+        egress.check_ip("svc.internal.test", "192.168.0.1".parse().unwrap()).expect("suffix");
+        egress.check_ip("internal.test", "93.184.216.34".parse().unwrap()).expect_err("bare");
+
+        // other names must resolve to an allowed network
+        egress.check_ip("other.com", "10.1.1.1".parse().unwrap()).expect("allowed network");
+        egress.check_ip("other.com", "93.184.216.34".parse().unwrap()).expect_err("default");
+    }
+
+    #[test]
+    fn deny_by_default() {
+        let egress = Egress::new("api.example.com", true, false).expect("should parse");
+        egress.check_host("api.example.com").expect("allowed");
+        egress.check_host("other.example.com").expect_err("not allowed");
+        egress.check_host("93.184.216.34").expect_err("not allowed");
+    }
+
+    #[test]
+    fn denied_source() {
+        let err: Box<dyn Error + Send + Sync> = EgressDenied("host".into()).into();
+        assert!(is_denied(err.as_ref()));
+        assert!(!is_denied(&fmt::Error));
+    }
+
+    #[test]
+    fn invalid_cidr() {
+        Egress::new("10.0.0.0/33", false, true).expect_err("should reject");
+    }
+}
@@ -1,92 +1,384 @@
 //! #HTTP Server
 
+mod limits;
+
 use std::clone::Clone;
 use std::convert::Infallible;
 use std::env;
+use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow};
 use bytes::Bytes;
-use http::StatusCode;
-use http::uri::{PathAndQuery, Uri};
+use fromenv::FromEnv;
+use http::uri::{PathAndQuery, Scheme, Uri};
+use http::{HeaderValue, StatusCode};
 use http_body_util::combinators::UnsyncBoxBody;
 use http_body_util::{BodyExt, Full};
 use hyper::body::{Body, Frame, Incoming, SizeHint};
-use hyper::header::{FORWARDED, HOST};
-use hyper::server::conn::http1;
+use hyper::header::{FORWARDED, HOST, RETRY_AFTER};
 use hyper::service::service_fn;
-use omnia::State;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use ipnet::IpNet;
+use omnia::{Concurrency, Invocation, Permit, Shutdown, State};
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
 use tracing::{Instrument, debug_span};
 use wasmtime::Store;
-use wasmtime_wasi_http::io::TokioIo;
 use wasmtime_wasi_http::p3::WasiHttpView;
 use wasmtime_wasi_http::p3::bindings::ServiceIndices;
 use wasmtime_wasi_http::p3::bindings::http::types::{self as wasi, ErrorCode};
 
-type OutgoingBody = UnsyncBoxBody<Bytes, anyhow::Error>;
+use self::limits::{Deadline, RequestLimits, RequestTimedOut};
+use crate::host::tls::{CLIENT_CERT_SUBJECT_HEADER, Tls, TlsOptions};
+
+/// The body of a response returned by a guest.
+pub type OutgoingBody = UnsyncBoxBody<Bytes, anyhow::Error>;
 
 const HTTP_ADDR: &str = "0.0.0.0:8080";
 
-pub async fn serve<S>(state: &S) -> Result<()>
+/// Options used to configure HTTP/2 connections to the inbound server.
+#[derive(Debug, Clone, FromEnv)]
+pub struct Http2Options {
+    /// The maximum number of concurrent streams on each HTTP/2 connection.
+    #[env(from = "HTTP_H2_MAX_CONCURRENT_STREAMS")]
+    pub max_concurrent_streams: Option<u32>,
+    /// The interval (in seconds) at which HTTP/2 pings are sent to keep idle
+    /// connections alive. Pings are not sent when unset.
+    #[env(from = "HTTP_H2_KEEP_ALIVE_INTERVAL_SECS")]
+    pub keep_alive_interval_secs: Option<u64>,
+    /// The time (in seconds) to wait for a ping to be acknowledged before
+    /// closing the connection.
+    #[env(from = "HTTP_H2_KEEP_ALIVE_TIMEOUT_SECS", default = "20")]
+    pub keep_alive_timeout_secs: u64,
+}
+
+impl omnia::FromEnv for Http2Options {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading http/2 options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        omnia::requirement_vars(&Self::requirements())
+    }
+}
+
+/// Options identifying the proxies the inbound server is deployed behind.
+#[derive(Debug, Clone, FromEnv)]
+pub struct ProxyOptions {
+    /// Comma-separated IP addresses and CIDRs of the proxies trusted to set
+    /// the request's host and scheme using the `Forwarded` header. The
+    /// header is removed from requests sent by any other client.
+    #[env(from = "HTTP_TRUSTED_PROXIES", default = "")]
+    pub trusted_proxies: String,
+}
+
+impl omnia::FromEnv for ProxyOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading proxy options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        omnia::requirement_vars(&Self::requirements())
+    }
+}
+
+/// The proxies trusted to set the `Forwarded` header.
+#[derive(Debug, Clone, Default)]
+struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    fn new(options: &ProxyOptions) -> Result<Self> {
+        let entries = options.trusted_proxies.split(',').map(str::trim);
+        entries
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .with_context(|| format!("parsing trusted proxy `{entry}`"))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    fn trusts(&self, addr: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener have IPv4-mapped addresses
+        let addr = addr.to_canonical();
+        self.0.iter().any(|net| net.contains(&addr))
+    }
+}
+
+// Serve HTTP/1.1 and HTTP/2, detecting the protocol from the connection
+// preface so prior-knowledge h2c is served without TLS.
+fn builder(http2: &Http2Options, limits: &RequestLimits) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(true).timer(TokioTimer::new());
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(http2.max_concurrent_streams)
+        .keep_alive_interval(http2.keep_alive_interval_secs.map(Duration::from_secs))
+        .keep_alive_timeout(Duration::from_secs(http2.keep_alive_timeout_secs));
+    limits.configure(&mut builder);
+    builder
+}
+
+// The environment variables read by the server.
+pub fn env_vars() -> Vec<String> {
+    use omnia::FromEnv;
+
+    let mut vars = vec!["HTTP_ADDR".to_string()];
+    vars.extend(Http2Options::env_vars());
+    vars.extend(ProxyOptions::env_vars());
+    vars.extend(RequestLimits::env_vars());
+    vars.extend(TlsOptions::env_vars());
+    vars
+}
+
+pub async fn serve<S>(components: &[S], shutdown: &Shutdown) -> Result<()>
 where
     S: State,
     S::StoreCtx: WasiHttpView,
 {
-    let component = env::var("COMPONENT").unwrap_or_else(|_| "unknown".into());
     let addr = env::var("HTTP_ADDR").unwrap_or_else(|_| HTTP_ADDR.into());
+    let http2 = <Http2Options as omnia::FromEnv>::from_env()?;
+    let proxies = TrustedProxies::new(&<ProxyOptions as omnia::FromEnv>::from_env()?)?;
+    let limits = Arc::new(<RequestLimits as omnia::FromEnv>::from_env()?);
+    let builder = Arc::new(builder(&http2, &limits));
+    let tls = Tls::new(&<TlsOptions as omnia::FromEnv>::from_env()?, shutdown)?;
 
     let listener = TcpListener::bind(&addr).await?;
-    tracing::info!("{component} http server listening on: {addr}");
+    let names = components.iter().map(State::name).collect::<Vec<_>>();
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    tracing::info!("{} {scheme} server listening on: {addr}", names.join(", "));
+    shutdown.listening();
 
-    let handler = Handler {
-        state: Arc::new(state.clone()),
-        component,
-    };
+    let concurrency = shutdown.concurrency().for_server("http");
+    let router = Router::new(components, shutdown, concurrency);
 
-    // listen for requests until terminated
+    // listen for requests until shutdown is signalled
     loop {
-        let (stream, _) = listener.accept().await?;
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            () = shutdown.signalled() => break,
+        };
         stream.set_nodelay(true)?;
-        let stream = TokioIo::new(stream);
-        let handler = handler.clone();
-
-        tokio::spawn(async move {
-            let mut http1 = http1::Builder::new();
-            http1.keep_alive(true);
-
-            if let Err(e) = http1
-                .serve_connection(
-                    stream,
-                    service_fn(move |request| {
-                        let handler = handler.clone();
-                        async move {
-                            let response = handler.handle(request).await.unwrap_or_else(|e| {
-                                tracing::error!("Error proxying request: {e}");
-                                internal_error()
-                            });
-
-                            // track server error responses
-                            if response.status() >= StatusCode::INTERNAL_SERVER_ERROR {
-                                tracing::error!(
-                                    monotonic_counter.processing_errors = 1,
-                                    service = %handler.component,
-                                    error = format!("{response:?}"),
-                                );
-                            }
-                            Ok::<_, Infallible>(response)
-                        }
-                    }),
-                )
-                .await
-            {
-                tracing::error!("connection error: {e:?}");
+        let router = router.clone();
+        let builder = Arc::clone(&builder);
+        let limits = Arc::clone(&limits);
+        let tls = tls.clone();
+        let signal = shutdown.clone();
+        let trusted_proxy = proxies.trusts(remote.ip());
+
+        shutdown.spawn(async move {
+            let Some(tls) = tls else {
+                let io = TokioIo::new(stream);
+                let peer = Peer {
+                    trusted_proxy,
+                    ..Peer::default()
+                };
+                return serve_connection(&builder, io, router, limits, peer, &signal).await;
+            };
+            match tls.accept(stream).await {
+                Ok((stream, client_subject)) => {
+                    let peer = Peer {
+                        tls: true,
+                        client_subject: client_subject
+                            .and_then(|subject| HeaderValue::from_bytes(subject.as_bytes()).ok()),
+                        trusted_proxy,
+                    };
+                    let io = TokioIo::new(stream);
+                    serve_connection(&builder, io, router, limits, peer, &signal).await;
+                }
+                Err(e) => tracing::debug!("{e:#}"),
             }
         });
     }
+
+    // stop accepting connections and drain in-flight requests
+    drop(listener);
+    tracing::info!("http server shutting down");
+    shutdown.drain().await;
+
+    Ok(())
+}
+
+/// The connection a request was received on.
+#[derive(Clone, Default)]
+struct Peer {
+    tls: bool,
+    client_subject: Option<HeaderValue>,
+    trusted_proxy: bool,
+}
+
+// Serve requests on the connection until it is closed, or shutdown is
+// signalled.
+async fn serve_connection<S, I>(
+    builder: &auto::Builder<TokioExecutor>, io: I, router: Router<S>, limits: Arc<RequestLimits>,
+    peer: Peer, shutdown: &Shutdown,
+) where
+    S: State,
+    S::StoreCtx: WasiHttpView,
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let connection = builder.serve_connection(
+        io,
+        service_fn(move |request| {
+            let router = router.clone();
+            let limits = Arc::clone(&limits);
+            let peer = peer.clone();
+            async move { Ok::<_, Infallible>(router.dispatch(request, &limits, &peer).await) }
+        }),
+    );
+    tokio::pin!(connection);
+
+    // finish in-flight requests and close the connection on shutdown
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        () = shutdown.signalled() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+    if let Err(e) = result {
+        tracing::error!("connection error: {e:?}");
+    }
+}
+
+/// Route a request to one of the `components` and forward it to the
+/// component's `wasi:http` handler without a listener, e.g. when driving
+/// guests from tests.
+///
+/// Errors are returned as responses, as they would be by the server.
+pub async fn handle<S, B>(
+    components: &[S], request: http::Request<B>,
+) -> http::Response<OutgoingBody>
+where
+    S: State,
+    S::StoreCtx: WasiHttpView,
+    B: Body<Data = Bytes, Error = ErrorCode> + Send + 'static,
+{
+    // in-flight work is not drained
+    let (shutdown, _trigger) = Shutdown::new(Duration::ZERO);
+    Router::new(components, &shutdown, Concurrency::default()).forward(request).await
+}
+
+/// Routes requests to the hosted components.
+#[derive(Clone)]
+struct Router<S>
+where
+    S: State,
+    S::StoreCtx: WasiHttpView,
+{
+    handlers: Arc<[Handler<S>]>,
+    concurrency: Concurrency,
+}
+
+impl<S> Router<S>
+where
+    S: State,
+    S::StoreCtx: WasiHttpView,
+{
+    fn new(components: &[S], shutdown: &Shutdown, concurrency: Concurrency) -> Self {
+        Self {
+            handlers: components
+                .iter()
+                .map(|state| Handler {
+                    state: Arc::new(state.clone()),
+                    component: state.name().to_string(),
+                    route: state.routes().path_prefix.clone().unwrap_or_else(|| "/".into()),
+                    shutdown: shutdown.clone(),
+                })
+                .collect(),
+            concurrency,
+        }
+    }
+
+    // Forward the request to the component it is routed to.
+    async fn dispatch(
+        &self, request: hyper::Request<Incoming>, limits: &RequestLimits, peer: &Peer,
+    ) -> hyper::Response<OutgoingBody> {
+        let request = request.map(|body| body.map_err(ErrorCode::from_hyper_request_error));
+
+        limits
+            .apply(request, |mut request| {
+                // only a verified certificate may identify the client
+                let headers = request.headers_mut();
+                headers.remove(CLIENT_CERT_SUBJECT_HEADER);
+                if let Some(subject) = &peer.client_subject {
+                    headers.insert(CLIENT_CERT_SUBJECT_HEADER, subject.clone());
+                }
+                // only a trusted proxy may say where the request was sent
+                if !peer.trusted_proxy {
+                    headers.remove(FORWARDED);
+                }
+                if peer.tls {
+                    request.extensions_mut().insert(Scheme::HTTPS);
+                }
+                self.forward(request)
+            })
+            .await
+    }
+
+    async fn forward<B>(&self, request: http::Request<B>) -> hyper::Response<OutgoingBody>
+    where
+        B: Body<Data = Bytes, Error = ErrorCode> + Send + 'static,
+    {
+        let request = match fix_request(request).context("preparing request") {
+            Ok(request) => request,
+            Err(e) => {
+                tracing::error!(monotonic_counter.processing_errors = 1, "{e:#}");
+                return internal_error();
+            }
+        };
+        let Some(handler) = self.route(request.uri()) else {
+            tracing::debug!("no component routed for: {}", request.uri());
+            return not_found();
+        };
+
+        // shed requests over the concurrency limit
+        let Some(permit) = self.concurrency.try_acquire() else {
+            tracing::warn!(
+                monotonic_counter.requests_rejected = 1,
+                service = %handler.component,
+                "too many requests in flight",
+            );
+            return unavailable(self.concurrency.retry_after());
+        };
+
+        let response =
+            handler.handle(request, permit).await.unwrap_or_else(|e| handler.error_response(&e));
+
+        // track server error responses
+        if response.status() >= StatusCode::INTERNAL_SERVER_ERROR {
+            tracing::error!(
+                monotonic_counter.processing_errors = 1,
+                service = %handler.component,
+                error = format!("{response:?}"),
+            );
+        }
+        response
+    }
+
+    // Select the component whose routes most specifically match the request,
+    // preferring the first component configured when several match equally.
+    fn route(&self, uri: &Uri) -> Option<&Handler<S>> {
+        let mut routed = None;
+        for handler in self.handlers.iter() {
+            let Some(score) = handler.state.routes().match_request(uri.host(), uri.path()) else {
+                continue;
+            };
+            if routed.as_ref().is_none_or(|(best, _)| score > *best) {
+                routed = Some((score, handler));
+            }
+        }
+        routed.map(|(_, handler)| handler)
+    }
 }
 
 #[derive(Clone)]
@@ -97,6 +389,9 @@ where
 {
     state: Arc<S>,
     component: String,
+    // the component's path prefix, a bounded label for metrics
+    route: String,
+    shutdown: Shutdown,
 }
 
 impl<S> Handler<S>
@@ -105,49 +400,71 @@ where
     S::StoreCtx: WasiHttpView,
 {
     // Forward request to the wasm Guest.
-    async fn handle(
-        &self, request: hyper::Request<Incoming>,
-    ) -> Result<hyper::Response<OutgoingBody>> {
-        tracing::debug!("handling request: {request:?}");
-
-        // prepare wasmtime http request and response
-        let request = fix_request(request).context("preparing request")?;
+    async fn handle<B>(
+        &self, request: http::Request<B>, permit: Permit,
+    ) -> Result<hyper::Response<OutgoingBody>>
+    where
+        B: Body<Data = Bytes, Error = ErrorCode> + Send + 'static,
+    {
+        tracing::debug!(
+            "{} handling request: {} {} {:?}",
+            self.component,
+            request.method(),
+            request.uri(),
+            request.headers()
+        );
+
+        let deadline = request.extensions().get::<Deadline>().copied();
 
         // instantiate the guest and get the proxy
         let instance_pre = self.state.instance_pre();
-        let store_data = self.state.store();
-        let mut store = Store::new(instance_pre.engine(), store_data);
-        let indices = ServiceIndices::new(instance_pre)?;
-        let instance = instance_pre.instantiate_async(&mut store).await?;
-        let service = indices.load(&mut store, &instance)?;
+        let invocation = Invocation::new(&self.component, |name| {
+            request.headers().get(name).and_then(|v| v.to_str().ok()).map(ToString::to_string)
+        });
+        let span = invocation.span.clone();
+        let mut store = self.state.new_store(invocation)?;
+        let instantiated = async {
+            let indices = ServiceIndices::new(&instance_pre)?;
+            let instance = self.state.instantiate(&instance_pre, &mut store).await?;
+            anyhow::Ok(indices.load(&mut store, &instance)?)
+        }
+        .instrument(span.clone())
+        .await;
+        let service = match instantiated {
+            Ok(service) => service,
+            Err(e) => {
+                // start functions may consume fuel before instantiation fails
+                self.record_fuel(&store);
+                return Err(e);
+            }
+        };
 
         let (sender, receiver) = oneshot::channel::<Result<hyper::Response<OutgoingBody>>>();
+        let handler = self.clone();
 
-        tokio::spawn(async move {
-            let send_err = |sender: oneshot::Sender<_>, e: anyhow::Error| {
-                _ = sender.send(Err(e));
-            };
+        self.shutdown.spawn(async move {
+            // the invocation counts against the limit until the guest is done
+            let _permit = permit;
+            // taken once the response (or an error) has been sent
+            let mut sender = Some(sender);
 
-            let result = store
+            let run = store
                 .run_concurrent(async |store| {
                     // convert hyper::Request to wasi::Request
-                    let (parts, body) = request.into_parts();
-                    let body = body.map_err(ErrorCode::from_hyper_request_error);
-                    let http_req = http::Request::from_parts(parts, body);
-                    let (request, io) = wasi::Request::from_http(http_req);
+                    let (request, io) = wasi::Request::from_http(request);
 
                     // forward request to guest
                     let wasi_resp = match service.handle(store, request).await? {
                         Ok(resp) => resp,
                         Err(e) => {
-                            send_err(sender, anyhow!("guest error: {e}"));
+                            respond(&mut sender, Err(anyhow!("guest error: {e}")));
                             return anyhow::Ok(());
                         }
                     };
                     let resp = match store.with(|mut store| wasi_resp.into_http(&mut store, io)) {
                         Ok(resp) => resp,
                         Err(e) => {
-                            send_err(sender, anyhow!("converting guest response: {e}"));
+                            respond(&mut sender, Err(anyhow!("converting guest response: {e}")));
                             return anyhow::Ok(());
                         }
                     };
@@ -164,19 +481,39 @@ where
 
                     // send the streaming response to hyper, then keep
                     // run_concurrent alive until hyper finishes reading
-                    if sender.send(Ok(resp)).is_ok() {
+                    if respond(&mut sender, Ok(resp)) {
                         _ = body_done_rx.await;
                     }
 
                     anyhow::Ok(())
                 })
-                .instrument(debug_span!("http-request"))
-                .await;
+                .instrument(debug_span!(parent: &span, "http-request"))
+                .instrument(span.clone());
+
+            // cancel the guest once the request's deadline has passed
+            let result = match deadline {
+                Some(Deadline(deadline)) => {
+                    tokio::time::timeout_at(deadline, run).await.map_err(|_elapsed| RequestTimedOut)
+                }
+                None => Ok(run.await),
+            };
 
+            handler.record_fuel(&store);
+
+            // forward errors (including traps) if no response has been sent
             match result {
-                Err(e) => tracing::error!("run_concurrent error: {e:?}"),
-                Ok(Err(e)) => tracing::error!("guest error: {e:#}"),
-                Ok(Ok(())) => {}
+                Err(timed_out) => {
+                    respond(&mut sender, Err(timed_out.into()));
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("run_concurrent error: {e:?}");
+                    respond(&mut sender, Err(e.into()));
+                }
+                Ok(Ok(Err(e))) => {
+                    tracing::error!("guest error: {e:#}");
+                    respond(&mut sender, Err(e));
+                }
+                Ok(Ok(Ok(()))) => {}
             }
         });
 
@@ -185,10 +522,65 @@ where
 
         Ok(response)
     }
+
+    // Record the fuel consumed by the guest, when fuel metering is enabled.
+    fn record_fuel(&self, store: &Store<S::StoreCtx>) {
+        if let Some(fuel) = self.state.limits().fuel_consumed(store) {
+            tracing::info!(
+                histogram.fuel_consumed = fuel,
+                service = %self.component,
+                route = %self.route,
+            );
+        }
+    }
+
+    // Map a failed request to the response returned to the client.
+    fn error_response(&self, e: &anyhow::Error) -> hyper::Response<OutgoingBody> {
+        if e.is::<RequestTimedOut>() {
+            return RequestTimedOut.response();
+        }
+        if omnia::is_deadline_exceeded(e) {
+            tracing::warn!(
+                monotonic_counter.deadline_exceeded = 1,
+                service = %self.component,
+                "guest deadline exceeded",
+            );
+            return service_unavailable("Guest deadline exceeded");
+        }
+        if omnia::is_fuel_exhausted(e) {
+            tracing::warn!(
+                monotonic_counter.fuel_exhausted = 1,
+                service = %self.component,
+                "guest fuel exhausted",
+            );
+            return service_unavailable("Guest fuel exhausted");
+        }
+        if let Some(exceeded) = omnia::limit_exceeded(e) {
+            tracing::warn!(
+                monotonic_counter.limit_exceeded = 1,
+                service = %self.component,
+                resource = %exceeded.resource,
+                "{exceeded}",
+            );
+        }
+
+        tracing::error!("Error proxying request: {e}");
+        internal_error()
+    }
+}
+
+/// Send the guest's response to hyper, unless a response has already been
+/// sent. Returns `true` if the response was delivered.
+fn respond(
+    sender: &mut Option<oneshot::Sender<Result<hyper::Response<OutgoingBody>>>>,
+    response: Result<hyper::Response<OutgoingBody>>,
+) -> bool {
+    sender.take().is_some_and(|sender| sender.send(response).is_ok())
 }
 
 // Prepare the request for the guest.
-fn fix_request(mut request: hyper::Request<Incoming>) -> Result<hyper::Request<Incoming>> {
+fn fix_request<B>(mut request: http::Request<B>) -> Result<http::Request<B>> {
+    let scheme = request.extensions().get::<Scheme>().cloned().unwrap_or(Scheme::HTTP);
     // let req_id = self.next_id.fetch_add(1, Ordering::Relaxed);
 
     // rebuild Uri with scheme and authority explicitly set so they are passed to the Guest
@@ -197,7 +589,7 @@ fn fix_request(mut request: hyper::Request<Incoming>) -> Result<hyper::Request<I
     let mut uri_builder = Uri::builder().path_and_query(p_and_q);
 
     if let Some(forwarded) = request.headers().get(FORWARDED) {
-        // running behind a proxy (that we have configured)
+        // running behind a trusted proxy (see `Router::dispatch`)
         for tuple in forwarded.to_str()?.split(';') {
             let tuple = tuple.trim();
             if let Some(host) = tuple.strip_prefix("host=") {
@@ -206,19 +598,22 @@ fn fix_request(mut request: hyper::Request<Incoming>) -> Result<hyper::Request<I
                 uri_builder = uri_builder.scheme(proto);
             }
         }
-    } else {
+    } else if let Some(host) = request.headers().get(HOST) {
         // running locally
-        let Some(host) = request.headers().get(HOST) else {
-            return Err(anyhow!("missing host header"));
-        };
         uri_builder = uri_builder.authority(host.to_str()?);
-        uri_builder = uri_builder.scheme("http");
+        uri_builder = uri_builder.scheme(scheme);
+    } else if let Some(authority) = request.uri().authority() {
+        // HTTP/2 requests carry the host in the `:authority` pseudo-header
+        uri_builder = uri_builder.authority(authority.clone());
+        uri_builder = uri_builder.scheme(scheme);
+    } else {
+        return Err(anyhow!("missing host header"));
     }
 
     // update the uri with the new scheme and authority
     let (mut parts, body) = request.into_parts();
     parts.uri = uri_builder.build()?;
-    let request = hyper::Request::from_parts(parts, body);
+    let request = http::Request::from_parts(parts, body);
 
     Ok(request)
 }
@@ -255,26 +650,101 @@ where
     }
 }
 
-const BODY: &str = r"<!doctype html>
+fn internal_error() -> hyper::Response<OutgoingBody> {
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Guest error")
+}
+
+fn not_found() -> hyper::Response<OutgoingBody> {
+    error_response(StatusCode::NOT_FOUND, "No component serves this route")
+}
+
+fn service_unavailable(detail: &str) -> hyper::Response<OutgoingBody> {
+    error_response(StatusCode::SERVICE_UNAVAILABLE, detail)
+}
+
+// Ask the client to retry once fewer requests are in flight.
+fn unavailable(retry_after: Duration) -> hyper::Response<OutgoingBody> {
+    let mut response =
+        error_response(StatusCode::SERVICE_UNAVAILABLE, "Too many requests in flight");
+    response.headers_mut().insert(RETRY_AFTER, retry_after.as_secs().max(1).into());
+    response
+}
+
+fn error_response(status: StatusCode, detail: &str) -> hyper::Response<OutgoingBody> {
+    let title =
+        format!("{} {}", status.as_u16(), status.canonical_reason().unwrap_or("Unknown Error"));
+    let html = format!(
+        r"<!doctype html>
 <html>
 <head>
-    <title>500 Internal Server Error</title>
+    <title>{title}</title>
 </head>
 <body>
     <center>
-        <h1>500 Internal Server Error</h1>
+        <h1>{title}</h1>
         <hr>
-        <pre>Guest error</pre>
+        <pre>{detail}</pre>
     </center>
 </body>
-</html>";
-
-fn internal_error() -> hyper::Response<OutgoingBody> {
-    let body = Full::new(Bytes::from(BODY)).map_err(Into::into).boxed_unsync();
+</html>"
+    );
+    let body = Full::new(Bytes::from(html)).map_err(Into::into).boxed_unsync();
 
     hyper::Response::builder()
-        .status(500)
+        .status(status)
         .header("Content-Type", "text/html; charset=UTF-8")
         .body(body)
-        .expect("should build internal error response")
+        .expect("should build error response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unavailable_retry_after() {
+        let response = unavailable(Duration::from_secs(5));
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER], "5");
+
+        // clients are never told to retry immediately
+        let response = unavailable(Duration::ZERO);
+        assert_eq!(response.headers()[RETRY_AFTER], "1");
+    }
+
+    #[test]
+    fn trusted_proxies() {
+        let options = ProxyOptions {
+            trusted_proxies: "10.0.0.0/8, 192.168.1.1".into(),
+        };
+        let proxies = TrustedProxies::new(&options).expect("should parse");
+        assert!(proxies.trusts("10.1.2.3".parse().unwrap()));
+        assert!(proxies.trusts("::ffff:192.168.1.1".parse().unwrap()));
+        assert!(!proxies.trusts("192.168.1.2".parse().unwrap()));
+
+        // no proxies are trusted by default
+        let options = ProxyOptions {
+            trusted_proxies: String::new(),
+        };
+        let proxies = TrustedProxies::new(&options).expect("should parse");
+        assert!(!proxies.trusts("127.0.0.1".parse().unwrap()));
+
+        let options = ProxyOptions {
+            trusted_proxies: "proxy.internal".into(),
+        };
+        TrustedProxies::new(&options).expect_err("should reject host names");
+    }
+
+    #[test]
+    fn http2_authority() {
+        // HTTP/2 requests have no `Host` header
+        let request = http::Request::builder()
+            .version(http::Version::HTTP_2)
+            .uri("http://example.com/orders?id=1")
+            .body(())
+            .expect("should build request");
+
+        let request = fix_request(request).expect("should fix request");
+        assert_eq!(request.uri(), "http://example.com/orders?id=1");
+    }
 }
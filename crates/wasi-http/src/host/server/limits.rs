@@ -0,0 +1,338 @@
+//! Limits protecting the inbound server from slow or hostile clients.
+
+use std::fmt::{self, Display};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context as TaskContext, Poll, ready};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use fromenv::FromEnv;
+use http::StatusCode;
+use hyper::body::{Body, Frame, SizeHint};
+use hyper::header::CONTENT_LENGTH;
+use hyper_util::rt::TokioExecutor;
+use hyper_util::server::conn::auto;
+use tokio::time::Instant;
+use wasmtime_wasi_http::p3::bindings::http::types::ErrorCode;
+
+use super::{OutgoingBody, error_response};
+
+/// Limits applied to each inbound request.
+#[derive(Debug, Clone, FromEnv)]
+pub struct RequestLimits {
+    /// The maximum size (in bytes) of a request body. Larger requests are
+    /// rejected with `413 Payload Too Large`.
+    #[env(from = "HTTP_MAX_BODY_BYTES")]
+    pub max_body_bytes: Option<u64>,
+    /// The maximum number of headers in a request. Requests with more are
+    /// rejected with `431 Request Header Fields Too Large`.
+    #[env(from = "HTTP_MAX_HEADERS", default = "100")]
+    pub max_headers: usize,
+    /// The maximum time (in seconds) an HTTP/1.1 client may take to send a
+    /// request's headers before the connection is closed.
+    #[env(from = "HTTP_HEADER_READ_TIMEOUT_SECS", default = "30")]
+    pub header_read_timeout_secs: u64,
+    /// The maximum time (in seconds) to handle a request. The guest is
+    /// cancelled and `504 Gateway Timeout` returned once it has elapsed.
+    /// The deadline also covers streaming the response body: a response
+    /// still streaming when it passes is cut off.
+    #[env(from = "HTTP_REQUEST_TIMEOUT_SECS")]
+    pub request_timeout_secs: Option<u64>,
+}
+
+impl omnia::FromEnv for RequestLimits {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading request limits")
+    }
+
+    fn env_vars() -> Vec<String> {
+        omnia::requirement_vars(&Self::requirements())
+    }
+}
+
+impl RequestLimits {
+    /// Apply the limits enforced while reading requests from a connection.
+    pub fn configure(&self, builder: &mut auto::Builder<TokioExecutor>) {
+        builder
+            .http1()
+            .max_headers(self.max_headers)
+            .header_read_timeout(Duration::from_secs(self.header_read_timeout_secs));
+    }
+
+    /// Handle the request using `handle` when it is within limits, cutting
+    /// its body off at the maximum size and abandoning it at the deadline.
+    pub async fn apply<B, F, Fut>(
+        &self, request: http::Request<B>, handle: F,
+    ) -> hyper::Response<OutgoingBody>
+    where
+        B: Body<Data = Bytes, Error = ErrorCode> + Unpin,
+        F: FnOnce(http::Request<LimitedBody<B>>) -> Fut,
+        Fut: Future<Output = hyper::Response<OutgoingBody>>,
+    {
+        // HTTP/1.1 requests are also limited by the connection
+        if request.headers().len() > self.max_headers {
+            return error_response(
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                "Too many request headers",
+            );
+        }
+        if let Some(max) = self.max_body_bytes
+            && content_length(&request).is_some_and(|len| len > max)
+        {
+            return payload_too_large();
+        }
+
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let mut request = request.map(|body| LimitedBody {
+            body,
+            remaining: self.max_body_bytes.unwrap_or(u64::MAX),
+            limit: self.max_body_bytes,
+            exceeded: Arc::clone(&exceeded),
+        });
+
+        let response = if let Some(secs) = self.request_timeout_secs {
+            let deadline = Deadline(Instant::now() + Duration::from_secs(secs));
+            request.extensions_mut().insert(deadline);
+            let Ok(response) = tokio::time::timeout_at(deadline.0, handle(request)).await else {
+                return RequestTimedOut.response();
+            };
+            response
+        } else {
+            handle(request).await
+        };
+
+        // the guest could not read the request's body
+        if exceeded.load(Ordering::Acquire) {
+            return payload_too_large();
+        }
+        response
+    }
+}
+
+/// The time by which a request must be handled.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(pub Instant);
+
+/// Error used when a request is not handled by its deadline.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestTimedOut;
+
+impl RequestTimedOut {
+    /// The response returned to the client.
+    pub fn response(self) -> hyper::Response<OutgoingBody> {
+        tracing::warn!(monotonic_counter.requests_timed_out = 1, "{self}");
+        error_response(StatusCode::GATEWAY_TIMEOUT, "Request timed out")
+    }
+}
+
+impl Display for RequestTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request deadline exceeded")
+    }
+}
+
+impl std::error::Error for RequestTimedOut {}
+
+/// A request body that fails once more than the maximum size has been read.
+pub struct LimitedBody<B> {
+    body: B,
+    remaining: u64,
+    limit: Option<u64>,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl<B> Body for LimitedBody<B>
+where
+    B: Body<Data = Bytes, Error = ErrorCode> + Unpin,
+{
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.body).poll_frame(cx));
+
+        if let Some(Ok(frame)) = &frame
+            && let Some(data) = frame.data_ref()
+        {
+            let len = data.len() as u64;
+            if len > self.remaining {
+                self.exceeded.store(true, Ordering::Release);
+                return Poll::Ready(Some(Err(ErrorCode::HttpRequestBodySize(self.limit))));
+            }
+            self.remaining -= len;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+fn content_length<B>(request: &http::Request<B>) -> Option<u64> {
+    request.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+fn payload_too_large() -> hyper::Response<OutgoingBody> {
+    error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::host::server::{Http2Options, builder};
+
+    fn limits() -> RequestLimits {
+        RequestLimits {
+            max_body_bytes: Some(16),
+            max_headers: 8,
+            header_read_timeout_secs: 1,
+            request_timeout_secs: Some(1),
+        }
+    }
+
+    // Serve requests using the server's connection builder, with a handler
+    // that reads the body and stalls for requests to `/slow`.
+    async fn listen(limits: RequestLimits) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("should bind");
+        let addr = listener.local_addr().expect("should have address");
+        let http2 = Http2Options {
+            max_concurrent_streams: None,
+            keep_alive_interval_secs: None,
+            keep_alive_timeout_secs: 20,
+        };
+        let builder = Arc::new(builder(&http2, &limits));
+        let limits = Arc::new(limits);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.expect("should accept");
+                let builder = Arc::clone(&builder);
+                let limits = Arc::clone(&limits);
+                tokio::spawn(async move {
+                    let service =
+                        service_fn(move |request: hyper::Request<hyper::body::Incoming>| {
+                            let limits = Arc::clone(&limits);
+                            async move {
+                                let request = request
+                                    .map(|body| body.map_err(ErrorCode::from_hyper_request_error));
+                                Ok::<_, std::convert::Infallible>(
+                                    limits.apply(request, handle).await,
+                                )
+                            }
+                        });
+                    let _ = builder.serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+        addr
+    }
+
+    async fn handle<B>(request: http::Request<B>) -> hyper::Response<OutgoingBody>
+    where
+        B: Body<Data = Bytes, Error = ErrorCode>,
+    {
+        if request.uri().path() == "/slow" {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+        let Ok(body) = request.into_body().collect().await else {
+            return error_response(StatusCode::BAD_REQUEST, "Unreadable body");
+        };
+        let len = body.to_bytes().len().to_string();
+        hyper::Response::new(Full::new(Bytes::from(len)).map_err(Into::into).boxed_unsync())
+    }
+
+    // Send a raw HTTP/1.1 request, returning the response (if any) sent
+    // before the connection is closed.
+    async fn send(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.expect("should connect");
+        stream.write_all(request.as_bytes()).await.expect("should write");
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[tokio::test]
+    async fn within_limits() {
+        let addr = listen(limits()).await;
+        let response = send(
+            addr,
+            "POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("\r\n\r\n5"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn body_too_large() {
+        let addr = listen(limits()).await;
+
+        // rejected from the declared length
+        let response = send(
+            addr,
+            "POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 17\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{response}");
+
+        // rejected once the chunks read exceed the limit
+        let response = send(
+            addr,
+            "POST / HTTP/1.1\r\nhost: localhost\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n\
+             a\r\n0123456789\r\na\r\n0123456789\r\n0\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn too_many_headers() {
+        let addr = listen(limits()).await;
+        let headers = (0..10).map(|i| format!("x-header-{i}: {i}\r\n")).collect::<Vec<_>>();
+        let headers = headers.concat();
+        let response =
+            send(addr, &format!("GET / HTTP/1.1\r\nhost: localhost\r\n{headers}\r\n")).await;
+        assert!(response.starts_with("HTTP/1.1 431"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn header_read_timeout() {
+        let addr = listen(limits()).await;
+
+        // the connection is closed without a response to the partial request
+        let response = tokio::time::timeout(
+            Duration::from_secs(5),
+            send(addr, "GET / HTTP/1.1\r\nhost: localhost\r\n"),
+        )
+        .await
+        .expect("connection should be closed");
+        assert!(!response.starts_with("HTTP/1.1 200"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        let addr = listen(limits()).await;
+        let response =
+            send(addr, "GET /slow HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 504"), "{response}");
+    }
+}
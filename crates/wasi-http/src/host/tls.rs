@@ -0,0 +1,278 @@
+//! # TLS Termination
+//!
+//! Terminates TLS for the inbound server when a certificate and key are
+//! configured, advertising HTTP/2 and HTTP/1.1 using ALPN.
+//!
+//! When a CA bundle is configured, clients must present a certificate issued
+//! by one of its CAs. The verified certificate's subject is passed to the
+//! guest in the `x-client-cert-subject` request header.
+//!
+//! The certificate, key and CA bundle are checked for changes periodically
+//! and reloaded without restarting, so rotated certificates are used for new
+//! connections. A failed reload leaves the previous certificate in use.
+
+mod subject;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result, bail};
+use arc_swap::ArcSwap;
+use fromenv::FromEnv;
+use omnia::Shutdown;
+use rustls::RootCertStore;
+use rustls::crypto::{CryptoProvider, aws_lc_rs};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ServerConfig, WebPkiClientVerifier};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+/// The request header carrying the subject of a client's verified
+/// certificate.
+pub const CLIENT_CERT_SUBJECT_HEADER: &str = "x-client-cert-subject";
+
+// The maximum time a client may take to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Options used to configure TLS termination.
+#[derive(Debug, Clone, FromEnv)]
+pub struct TlsOptions {
+    /// Path to the PEM-encoded certificate chain presented to clients. TLS
+    /// is terminated when set.
+    #[env(from = "HTTP_TLS_CERT")]
+    pub cert: Option<PathBuf>,
+    /// Path to the PEM-encoded private key for the certificate.
+    #[env(from = "HTTP_TLS_KEY")]
+    pub key: Option<PathBuf>,
+    /// Path to a PEM-encoded bundle of CA certificates used to verify client
+    /// certificates. Clients must present a certificate when set.
+    #[env(from = "HTTP_TLS_CLIENT_CA")]
+    pub client_ca: Option<PathBuf>,
+    /// The interval (in seconds) at which the certificate, key and CA bundle
+    /// are checked for changes.
+    #[env(from = "HTTP_TLS_RELOAD_INTERVAL_SECS", default = "60")]
+    pub reload_interval_secs: u64,
+}
+
+impl omnia::FromEnv for TlsOptions {
+    fn from_env() -> Result<Self> {
+        Self::from_env().finalize().context("issue loading tls options")
+    }
+
+    fn env_vars() -> Vec<String> {
+        omnia::requirement_vars(&Self::requirements())
+    }
+}
+
+/// Terminates TLS for inbound connections.
+#[derive(Clone)]
+pub struct Tls {
+    config: Arc<ArcSwap<ServerConfig>>,
+}
+
+impl Tls {
+    /// Load the configured certificate, reloading it when it changes until
+    /// shutdown is signalled.
+    ///
+    /// Returns `None` when TLS is not configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the options are incomplete or the certificate,
+    /// key or CA bundle cannot be loaded.
+    pub fn new(options: &TlsOptions, shutdown: &Shutdown) -> Result<Option<Self>> {
+        let Some(mut files) = Files::new(options)? else {
+            return Ok(None);
+        };
+        let tls = Self {
+            config: Arc::new(ArcSwap::from_pointee(files.load()?)),
+        };
+
+        let interval = Duration::from_secs(options.reload_interval_secs.max(1));
+        let config = Arc::clone(&tls.config);
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    () = shutdown.signalled() => return,
+                }
+                if !files.changed() {
+                    continue;
+                }
+                match files.load() {
+                    Ok(reloaded) => {
+                        config.store(Arc::new(reloaded));
+                        tracing::info!(
+                            monotonic_counter.tls_reloads = 1,
+                            "reloaded tls certificate"
+                        );
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            monotonic_counter.tls_reload_errors = 1,
+                            "issue reloading tls certificate: {e:#}"
+                        );
+                    }
+                }
+            }
+        });
+
+        Ok(Some(tls))
+    }
+
+    /// Complete the TLS handshake, returning the stream and the subject of
+    /// the client's verified certificate, if it presented one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the handshake fails or times out.
+    pub async fn accept(
+        &self, stream: TcpStream,
+    ) -> Result<(TlsStream<TcpStream>, Option<String>)> {
+        let acceptor = TlsAcceptor::from(self.config.load_full());
+        let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+            .await
+            .context("tls handshake timed out")?
+            .context("tls handshake failed")?;
+
+        let (_, connection) = stream.get_ref();
+        let subject =
+            connection.peer_certificates().and_then(|certs| certs.first()).and_then(|cert| {
+                let subject = subject::subject(cert);
+                if subject.is_none() {
+                    tracing::warn!("issue reading client certificate subject");
+                }
+                subject
+            });
+        Ok((stream, subject))
+    }
+}
+
+/// The files TLS is configured from.
+struct Files {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+}
+
+impl Files {
+    fn new(options: &TlsOptions) -> Result<Option<Self>> {
+        let (cert, key) = match (&options.cert, &options.key) {
+            (Some(cert), Some(key)) => (cert.clone(), key.clone()),
+            (None, None) if options.client_ca.is_some() => {
+                bail!("`HTTP_TLS_CLIENT_CA` requires `HTTP_TLS_CERT` and `HTTP_TLS_KEY`")
+            }
+            (None, None) => return Ok(None),
+            _ => bail!("`HTTP_TLS_CERT` and `HTTP_TLS_KEY` must be set together"),
+        };
+
+        let mut files = Self {
+            cert,
+            key,
+            client_ca: options.client_ca.clone(),
+            modified: Vec::new(),
+        };
+        files.modified = files.modified();
+        Ok(Some(files))
+    }
+
+    fn load(&self) -> Result<ServerConfig> {
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+
+        let builder = if let Some(client_ca) = &self.client_ca {
+            let mut roots = RootCertStore::empty();
+            for cert in certs(client_ca)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("adding CA from {}", client_ca.display()))?;
+            }
+            builder.with_client_cert_verifier(client_verifier(roots, provider)?)
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .with_context(|| format!("reading key from {}", self.key.display()))?;
+        let mut config = builder
+            .with_single_cert(certs(&self.cert)?, key)
+            .context("configuring tls certificate")?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(config)
+    }
+
+    // Returns `true` if any of the files have been modified since last checked.
+    fn changed(&mut self) -> bool {
+        let modified = self.modified();
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [Some(&self.cert), Some(&self.key), self.client_ca.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|path| path.metadata().and_then(|metadata| metadata.modified()).ok())
+            .collect()
+    }
+}
+
+fn certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .with_context(|| format!("reading certificates from {}", path.display()))?;
+    if certs.is_empty() {
+        bail!("no certificates found in {}", path.display());
+    }
+    Ok(certs)
+}
+
+fn client_verifier(
+    roots: RootCertStore, provider: Arc<CryptoProvider>,
+) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .context("configuring client certificate verification")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(cert: Option<&str>, key: Option<&str>, client_ca: Option<&str>) -> TlsOptions {
+        TlsOptions {
+            cert: cert.map(PathBuf::from),
+            key: key.map(PathBuf::from),
+            client_ca: client_ca.map(PathBuf::from),
+            reload_interval_secs: 60,
+        }
+    }
+
+    #[test]
+    fn incomplete_options() {
+        assert!(Files::new(&options(None, None, None)).expect("should be valid").is_none());
+        assert!(Files::new(&options(Some("cert.pem"), None, None)).is_err());
+        assert!(Files::new(&options(None, None, Some("ca.pem"))).is_err());
+    }
+
+    #[test]
+    fn missing_files() {
+        let files = Files::new(&options(Some("missing.pem"), Some("missing.key"), None))
+            .expect("should be valid")
+            .expect("should be configured");
+        let err = files.load().expect_err("should fail to load");
+        assert!(format!("{err:#}").contains("missing"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Reads the subject of a DER-encoded X.509 certificate.
+//!
+//! The certificate has already been verified by the TLS handshake, so it is
+//! only parsed to format its subject.
+
+use std::fmt::Write;
+
+use x509_parser::asn1_rs::{BmpString, Tag, ToDer};
+use x509_parser::prelude::{AttributeTypeAndValue, FromDer, X509Certificate};
+
+/// The certificate's subject, formatted as an RFC 4514 distinguished name,
+/// e.g. `CN=orders,O=Acme,C=NZ`.
+///
+/// Returns `None` if the certificate cannot be decoded.
+pub fn subject(cert: &[u8]) -> Option<String> {
+    let (_, certificate) = X509Certificate::from_der(cert).ok()?;
+
+    let mut rdns = Vec::new();
+    for rdn in certificate.subject().iter_rdn() {
+        let attributes = rdn.iter().map(format_attribute).collect::<Option<Vec<_>>>()?;
+        rdns.push(attributes.join("+"));
+    }
+
+    // relative distinguished names are formatted most specific first
+    rdns.reverse();
+    Some(rdns.join(","))
+}
+
+fn format_attribute(attribute: &AttributeTypeAndValue) -> Option<String> {
+    let oid = attribute.attr_type().to_id_string();
+    let name = match oid.as_str() {
+        "2.5.4.3" => "CN",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.9" => "STREET",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "0.9.2342.19200300.100.1.1" => "UID",
+        "0.9.2342.19200300.100.1.25" => "DC",
+        _ => oid.as_str(),
+    };
+
+    // values that are not strings are formatted as hex-encoded DER
+    let Some(text) = decode_string(attribute) else {
+        let hex = attribute.attr_value().to_der_vec().ok()?.iter().fold(
+            String::new(),
+            |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            },
+        );
+        return Some(format!("{name}=#{hex}"));
+    };
+    Some(format!("{name}={}", escape(&text)))
+}
+
+fn decode_string(attribute: &AttributeTypeAndValue) -> Option<String> {
+    let value = attribute.attr_value();
+    match value.tag() {
+        Tag::Utf8String | Tag::PrintableString | Tag::Ia5String => {
+            attribute.as_str().ok().map(ToString::to_string)
+        }
+        // TeletexString, treated as Latin-1
+        Tag::TeletexString => Some(value.data.iter().map(|&byte| char::from(byte)).collect()),
+        Tag::BmpString => BmpString::try_from(value).ok().map(|string| string.string()),
+        _ => None,
+    }
+}
+
+// Escape the characters RFC 4514 requires to be escaped in a value.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+    for (i, c) in value.chars().enumerate() {
+        let special = matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';')
+            || (i == 0 && matches!(c, '#' | ' '))
+            || (i == last && c == ' ');
+        if special {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::pki_types::CertificateDer;
+    use rustls::pki_types::pem::PemObject;
+
+    use super::*;
+
+    // issued by `CN=Test CA` to `/C=NZ/O=Acme, Inc./OU=Orders/CN=orders-service`
+    const CLIENT_CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBojCCAUigAwIBAgIUc3DKeshPemomhooJlPKnY3HV23owCgYIKoZIzj0EAwIw
+EjEQMA4GA1UEAwwHVGVzdCBDQTAeFw0yNjEwMTcwNDIwNDVaFw0zNjEwMTQwNDIw
+NDVaMEwxCzAJBgNVBAYTAk5aMRMwEQYDVQQKDApBY21lLCBJbmMuMQ8wDQYDVQQL
+DAZPcmRlcnMxFzAVBgNVBAMMDm9yZGVycy1zZXJ2aWNlMFkwEwYHKoZIzj0CAQYI
+KoZIzj0DAQcDQgAEuV97KdjcXUdqr+JcaUTfrovJsre4L7vyr1mgsMdRK7poCWWc
+pVQWUucXP2xlWkO6ok6SiIszndktp2x1I3Fu96NCMEAwHQYDVR0OBBYEFDYl7B3h
+pftit5VYPUEbDH1o11utMB8GA1UdIwQYMBaAFHtpa4BWfOLBf/740C5PdJuYuQAH
+MAoGCCqGSM49BAMCA0gAMEUCIQCcl1G6p2OJpBoGq9w8b0vzqGsHEhC8cbJ/Zs0M
+lTVZgAIgb5Z9R3Z7YS8eZmDTHnQXhaPSOxiMjfAEiEuaAYIa39Y=
+-----END CERTIFICATE-----";
+
+    #[test]
+    fn certificate_subject() {
+        let cert = CertificateDer::from_pem_slice(CLIENT_CERT.as_bytes()).expect("should parse");
+        assert_eq!(
+            subject(&cert).as_deref(),
+            Some(r"CN=orders-service,OU=Orders,O=Acme\, Inc.,C=NZ")
+        );
+    }
+
+    #[test]
+    fn invalid_certificate() {
+        assert_eq!(subject(&[]), None);
+        assert_eq!(subject(&[0x30, 0x82, 0xff]), None);
+        assert_eq!(subject(b"not a certificate"), None);
+    }
+
+    #[test]
+    fn escapes_values() {
+        assert_eq!(escape(" #a+b "), r"\ #a\+b\ ");
+        assert_eq!(escape("#x"), r"\#x");
+    }
+}
@@ -30,7 +30,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{AccessDenied, Capabilities, Host, Invocation, Server, State};
 use wasmtime::component::{HasData, Linker, ResourceTableError};
 use wasmtime_wasi::ResourceTable;
 
@@ -77,6 +77,12 @@ pub struct WasiIdentityCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// Resources the guest may access.
+    pub capabilities: &'a Capabilities,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI Identity context.
@@ -95,6 +101,16 @@ impl From<anyhow::Error> for Error {
     }
 }
 
+/// `AccessDenied` to `Error` mapping
+///
+/// `omnia:identity@0.1.0` has no access denied case, so denials are reported
+/// as an internal failure whose message starts with [`AccessDenied::PREFIX`].
+impl From<AccessDenied> for Error {
+    fn from(err: AccessDenied) -> Self {
+        Self::InternalFailure(err.to_string())
+    }
+}
+
 /// `ResourceTableError` to `Error` mapping
 impl From<ResourceTableError> for Error {
     fn from(err: ResourceTableError) -> Self {
@@ -111,6 +127,8 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_identity::WasiIdentityCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    capabilities: &self.capabilities,
+                    invocation: &self.invocation,
                 }
             }
         }
@@ -1,4 +1,5 @@
 use anyhow::Context;
+use omnia::Capability;
 use wasmtime::component::{Access, Accessor, Resource};
 
 use crate::host::generated::omnia::identity::credentials::{
@@ -12,6 +13,7 @@ impl HostWithStore for WasiIdentity {
     async fn get_identity<T>(
         accessor: &Accessor<T, Self>, name: String,
     ) -> Result<Resource<IdentityProxy>> {
+        accessor.with(|mut store| store.get().capabilities.check(Capability::Identity, &name))?;
         let identity = accessor.with(|mut store| store.get().ctx.get_identity(name)).await?;
         let proxy = IdentityProxy(identity);
         Ok(accessor.with(|mut store| store.get().table.push(proxy))?)
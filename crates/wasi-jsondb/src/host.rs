@@ -28,6 +28,13 @@ impl std::fmt::Display for JsonDbError {
 
 impl std::error::Error for JsonDbError {}
 
+/// `AccessDenied` to `JsonDbError` mapping
+impl From<AccessDenied> for JsonDbError {
+    fn from(_err: AccessDenied) -> Self {
+        Self::AccessDenied
+    }
+}
+
 mod generated {
     #![allow(missing_docs)]
 
@@ -51,7 +58,7 @@ mod generated {
 use std::fmt::Debug;
 
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{AccessDenied, Capabilities, Host, Invocation, Server, State};
 use wasmtime::component::{HasData, Linker};
 use wasmtime_wasi::ResourceTable;
 
@@ -95,6 +102,12 @@ pub struct WasiJsonDbCtxView<'a> {
 
     /// Resource table for `filter` handles.
     pub table: &'a mut ResourceTable,
+
+    /// Resources the guest may access.
+    pub capabilities: &'a Capabilities,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// Backend operations for JSON document storage.
@@ -141,6 +154,8 @@ macro_rules! omnia_wasi_view {
                 $crate::WasiJsonDbCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    capabilities: &self.capabilities,
+                    invocation: &self.invocation,
                 }
             }
         }
@@ -36,6 +36,10 @@ impl FromEnv for ConnectOptions {
         });
         Ok(Self { database })
     }
+
+    fn env_vars() -> Vec<String> {
+        vec!["JSONDB_DATABASE".to_string()]
+    }
 }
 
 /// Default [`WasiJsonDbCtx`] using `PoloDB`.
@@ -1,5 +1,6 @@
 //! `wasi:jsondb` `store` interface.
 
+use omnia::Capability;
 use wasmtime::component::Accessor;
 
 use crate::host::generated::wasi::jsondb::store::{
@@ -11,10 +12,19 @@ fn map_err(e: &anyhow::Error) -> JsonDbError {
     JsonDbError::Other(format!("{e:#}"))
 }
 
+// Check the capability policy allows the guest to use the collection.
+fn check_collection<T>(
+    accessor: &Accessor<T, WasiJsonDb>, collection: &str,
+) -> Result<(), JsonDbError> {
+    accessor
+        .with(|mut store| Ok(store.get().capabilities.check(Capability::Collection, collection)?))
+}
+
 impl HostWithStore for WasiJsonDb {
     async fn get<T>(
         accessor: &Accessor<T, Self>, collection: String, id: String,
     ) -> Result<Option<Document>, JsonDbError> {
+        check_collection(accessor, &collection)?;
         let fut = accessor.with(|mut store| store.get().ctx.get(collection, id));
         fut.await.map_err(|e| map_err(&e))
     }
@@ -22,6 +32,7 @@ impl HostWithStore for WasiJsonDb {
     async fn insert<T>(
         accessor: &Accessor<T, Self>, collection: String, doc: Document,
     ) -> Result<(), JsonDbError> {
+        check_collection(accessor, &collection)?;
         let fut = accessor.with(|mut store| store.get().ctx.insert(collection, doc));
         fut.await.map_err(|e| map_err(&e))
     }
@@ -29,6 +40,7 @@ impl HostWithStore for WasiJsonDb {
     async fn put<T>(
         accessor: &Accessor<T, Self>, collection: String, doc: Document,
     ) -> Result<(), JsonDbError> {
+        check_collection(accessor, &collection)?;
         let fut = accessor.with(|mut store| store.get().ctx.put(collection, doc));
         fut.await.map_err(|e| map_err(&e))
     }
@@ -36,6 +48,7 @@ impl HostWithStore for WasiJsonDb {
     async fn delete<T>(
         accessor: &Accessor<T, Self>, collection: String, id: String,
     ) -> Result<bool, JsonDbError> {
+        check_collection(accessor, &collection)?;
         let fut = accessor.with(|mut store| store.get().ctx.delete(collection, id));
         fut.await.map_err(|e| map_err(&e))
     }
@@ -52,6 +65,7 @@ impl HostWithStore for WasiJsonDb {
             continuation,
         } = options;
 
+        check_collection(accessor, &collection)?;
         let filter_tree = filter
             .map(|res| {
                 accessor
@@ -31,7 +31,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{AccessDenied, Backends, Capabilities, Host, Invocation, NamedHost, Server, State};
 use wasmtime::component::{HasData, Linker, ResourceTableError};
 use wasmtime_wasi::ResourceTable;
 
@@ -64,6 +64,10 @@ where
 
 impl<S> Server<S> for WasiKeyValue where S: State {}
 
+impl NamedHost for WasiKeyValue {
+    type Ctx = dyn WasiKeyValueCtx;
+}
+
 /// A trait which provides internal WASI Key-Value state.
 ///
 /// This is implemented by the `T` in `Linker<T>` — a single type shared across
@@ -80,6 +84,12 @@ pub struct WasiKeyValueCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// Resources the guest may access.
+    pub capabilities: &'a Capabilities,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI Key-Value context.
@@ -91,6 +101,16 @@ pub trait WasiKeyValueCtx: Debug + Send + Sync + 'static {
     fn open_bucket(&self, identifier: String) -> FutureResult<Arc<dyn Bucket>>;
 }
 
+/// Routes each bucket identifier to its backend.
+impl WasiKeyValueCtx for Backends<dyn WasiKeyValueCtx> {
+    fn open_bucket(&self, identifier: String) -> FutureResult<Arc<dyn Bucket>> {
+        match self.get(&identifier) {
+            Ok(backend) => backend.open_bucket(identifier),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+}
+
 /// `anyhow::Error` to `Error` mapping
 impl From<anyhow::Error> for Error {
     fn from(err: anyhow::Error) -> Self {
@@ -98,6 +118,13 @@ impl From<anyhow::Error> for Error {
     }
 }
 
+/// `AccessDenied` to `Error` mapping
+impl From<AccessDenied> for Error {
+    fn from(_err: AccessDenied) -> Self {
+        Self::AccessDenied
+    }
+}
+
 /// `ResourceTableError` to `Error` mapping
 impl From<ResourceTableError> for Error {
     fn from(err: ResourceTableError) -> Self {
@@ -114,6 +141,8 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_keyvalue::WasiKeyValueCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    capabilities: &self.capabilities,
+                    invocation: &self.invocation,
                 }
             }
         }
@@ -1,4 +1,5 @@
 use anyhow::Context;
+use omnia::Capability;
 use wasmtime::component::{Access, Accessor, Resource};
 
 use crate::host::generated::wasi::keyvalue::store::{
@@ -12,6 +13,8 @@ impl HostWithStore for WasiKeyValue {
     async fn open<T>(
         accessor: &Accessor<T, Self>, identifier: String,
     ) -> Result<Resource<BucketProxy>> {
+        accessor
+            .with(|mut store| store.get().capabilities.check(Capability::Bucket, &identifier))?;
         let bucket = accessor.with(|mut store| store.get().ctx.open_bucket(identifier)).await?;
         let proxy = BucketProxy(bucket);
         Ok(accessor.with(|mut store| store.get().table.push(proxy))?)
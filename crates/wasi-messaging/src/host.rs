@@ -38,7 +38,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{AccessDenied, Capabilities, Host, Invocation, Server, Shutdown, State};
 use wasmtime::component::{HasData, Linker};
 use wasmtime_wasi::{ResourceTable, ResourceTableError};
 
@@ -47,6 +47,7 @@ pub use self::generated::MessagingRequestReply;
 pub use self::generated::wasi::messaging::types::Error;
 use self::generated::wasi::messaging::{producer, request_reply, types};
 pub use self::resource::*;
+pub use self::server::deliver;
 
 /// Result type for messaging operations.
 pub type Result<T, E = Error> = anyhow::Result<T, E>;
@@ -75,8 +76,8 @@ where
     S: State,
     S::StoreCtx: WasiMessagingView,
 {
-    async fn run(&self, state: &S) -> anyhow::Result<()> {
-        server::run(state).await
+    async fn run(&self, components: &[S], shutdown: &Shutdown) -> anyhow::Result<()> {
+        server::run(components, shutdown).await
     }
 }
 
@@ -96,6 +97,12 @@ pub struct WasiMessagingCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// Resources the guest may access.
+    pub capabilities: &'a Capabilities,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI Messaging context.
@@ -171,6 +178,13 @@ impl From<anyhow::Error> for Error {
     }
 }
 
+/// `AccessDenied` to `Error` mapping
+impl From<AccessDenied> for Error {
+    fn from(err: AccessDenied) -> Self {
+        Self::PermissionDenied(err.to_string())
+    }
+}
+
 /// `ResourceTableError` to `Error` mapping
 impl From<ResourceTableError> for Error {
     fn from(err: ResourceTableError) -> Self {
@@ -194,6 +208,8 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_messaging::WasiMessagingCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    capabilities: &self.capabilities,
+                    invocation: &self.invocation,
                 }
             }
         }
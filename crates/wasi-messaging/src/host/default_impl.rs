@@ -295,4 +295,20 @@ mod tests {
         // Test send
         client.send("test-topic".to_string(), MessageProxy(message)).await.expect("send");
     }
+
+    #[tokio::test]
+    async fn subscribe_to_topics() {
+        let ctx = MessagingDefault::connect_with(ConnectOptions).await.expect("connect");
+        let client = ctx.connect().await.expect("connect client");
+        let mut subscriptions =
+            client.subscribe_to(vec!["orders.*".into()]).await.expect("subscribe");
+
+        for topic in ["users.created", "orders.created"] {
+            let message = ctx.new_message(topic.as_bytes().to_vec()).expect("new message");
+            client.send(topic.to_string(), MessageProxy(message)).await.expect("send");
+        }
+
+        let received = subscriptions.next().await.expect("should receive message");
+        assert_eq!(received.topic(), "orders.created");
+    }
 }
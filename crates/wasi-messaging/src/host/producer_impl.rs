@@ -1,3 +1,4 @@
+use omnia::Capability;
 use wasmtime::component::{Accessor, Resource};
 
 use crate::host::generated::wasi::messaging::producer::{Host, HostWithStore};
@@ -11,6 +12,7 @@ impl HostWithStore for WasiMessaging {
         accessor: &Accessor<T, Self>, c: Resource<ClientProxy>, topic: Topic,
         message: Resource<MessageProxy>,
     ) -> Result<()> {
+        accessor.with(|mut store| store.get().capabilities.check(Capability::Send, &topic))?;
         let client = get_client(accessor, &c)?;
         let msg = get_message(accessor, &message)?;
         client.send(topic, msg).await?;
@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use omnia::Capability;
 use wasmtime::component::{Access, Accessor, Resource};
 
 use crate::host::generated::wasi::messaging::request_reply::{
@@ -15,6 +16,7 @@ impl HostWithStore for WasiMessaging {
         accessor: &Accessor<T, Self>, c: Resource<ClientProxy>, topic: Topic,
         message: Resource<MessageProxy>, options: Option<Resource<RequestOptions>>,
     ) -> Result<Vec<Resource<MessageProxy>>> {
+        accessor.with(|mut store| store.get().capabilities.check(Capability::Send, &topic))?;
         let client = get_client(accessor, &c)?;
         let request = get_message(accessor, &message)?;
         let options = accessor.with(|mut access| {
@@ -1,12 +1,14 @@
 use std::any::Any;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::future;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
 use std::sync::Arc;
 
-use futures::Stream;
+use futures::{FutureExt, Stream, StreamExt};
 pub use omnia::FutureResult;
+use omnia::Routes;
 use serde::{Deserialize, Serialize};
 
 use crate::host::generated::wasi::messaging::types;
@@ -19,6 +21,31 @@ pub trait Client: Debug + Send + Sync + 'static {
     /// Subscribe to messages.
     fn subscribe(&self) -> FutureResult<Subscriptions>;
 
+    /// Subscribe to messages published to `topics`, where a trailing `*`
+    /// matches any topic with the preceding prefix. An empty list subscribes
+    /// to every topic the client is configured for.
+    ///
+    /// By default, messages from [`Client::subscribe`] published to other
+    /// topics are discarded. Clients able to subscribe by topic should
+    /// override this so unwanted messages are not received at all.
+    fn subscribe_to(&self, topics: Vec<String>) -> FutureResult<Subscriptions> {
+        let subscribe = self.subscribe();
+        async move {
+            let subscriptions = subscribe.await?;
+            if topics.is_empty() {
+                return Ok(subscriptions);
+            }
+            let routes = Routes {
+                topics,
+                ..Routes::default()
+            };
+            let filtered = subscriptions
+                .filter(move |message| future::ready(routes.matches_topic(&message.topic())));
+            Ok(Box::pin(filtered) as Subscriptions)
+        }
+        .boxed()
+    }
+
     /// Send a message to a topic.
     fn send(&self, topic: String, message: MessageProxy) -> FutureResult<()>;
 
@@ -1,50 +1,124 @@
-use std::env;
-
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use futures::StreamExt;
-use omnia::State;
+use omnia::{Capability, Invocation, Routes, Shutdown, State};
 use tracing::{Instrument, debug_span, instrument};
-use wasmtime::Store;
 
 use crate::host::WasiMessagingView;
 use crate::host::generated::MessagingRequestReply;
 use crate::host::resource::{MessageProxy, Subscriptions};
 
-#[instrument("messaging-server", skip(state))]
-pub async fn run<S>(state: &S) -> Result<()>
+#[instrument("messaging-server", skip(components, shutdown))]
+pub async fn run<S>(components: &[S], shutdown: &Shutdown) -> Result<()>
 where
     S: State,
     S::StoreCtx: WasiMessagingView,
 {
-    let component = env::var("COMPONENT").unwrap_or_else(|_| "unknown".into());
-    tracing::info!("starting messaging server for: {component}");
-
-    let handler = Handler {
-        state: state.clone(),
-        component,
+    let handlers = components
+        .iter()
+        .map(|state| Handler {
+            state: state.clone(),
+            component: state.name().to_string(),
+        })
+        .collect::<Vec<_>>();
+    let Some(subscriber) = handlers.first() else {
+        return Ok(());
     };
-    let mut stream = handler.subscriptions().await?;
+    let names = handlers.iter().map(|h| h.component.as_str()).collect::<Vec<_>>();
+    tracing::info!("starting messaging server for: {}", names.join(", "));
+    let concurrency = shutdown.concurrency().for_server("messaging");
+
+    // subscribe to the topics of every component, using the first to connect
+    // (backends are shared between components)
+    let topics = topics(components.iter().map(State::routes));
+    let subscriptions = subscriber.subscriptions(topics).await?;
+
+    // process messages until shutdown is signalled
+    let mut stream = Box::pin(subscriptions.take_until(shutdown.signalled()));
+    shutdown.listening();
 
     while let Some(message) = stream.next().await {
-        let handler = handler.clone();
-        tokio::spawn(async move {
-            tracing::info!(monotonic_counter.message_counter = 1, service = %handler.component);
-
-            if let Err(e) = handler.handle(message.clone()).await {
-                tracing::error!("issue processing message: {e}");
-                tracing::error!(
-                    monotonic_counter.processing_errors = 1,
-                    service = %handler.component,
-                    topic = %message.topic(),
-                    error = %e,
-                );
-            }
-        });
+        // deliver the message to each component handling (and allowed to
+        // receive) the topic
+        for handler in routed(&handlers, &message.topic()) {
+            // no more messages are pulled while at the concurrency limit
+            let permit = concurrency.acquire().await;
+            let handler = handler.clone();
+            let message = message.clone();
+            shutdown.spawn(async move {
+                let _permit = permit;
+                handler.process(message).await;
+            });
+        }
     }
 
+    // stop receiving messages and drain in-flight invocations
+    drop(stream);
+    tracing::info!("messaging server shutting down");
+    shutdown.drain().await;
+
     Ok(())
 }
 
+/// Deliver a message to the `incoming-handler` of each of the `components`
+/// handling (and allowed to receive) its topic, without subscribing, e.g.
+/// when driving guests from tests.
+///
+/// # Errors
+///
+/// Returns an error if no component handles the topic, or the first error
+/// returned by a component.
+pub async fn deliver<S>(components: &[S], message: MessageProxy) -> Result<()>
+where
+    S: State,
+    S::StoreCtx: WasiMessagingView,
+{
+    let handlers = components
+        .iter()
+        .map(|state| Handler {
+            state: state.clone(),
+            component: state.name().to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    let topic = message.topic();
+    let mut delivered = false;
+    for handler in routed(&handlers, &topic) {
+        handler.handle(message.clone()).await?;
+        delivered = true;
+    }
+    if !delivered {
+        bail!("no component handles topic `{topic}`");
+    }
+    Ok(())
+}
+
+// The topics handled by any of the components, without duplicates. Empty when
+// a component handles every topic.
+fn topics<'a>(routes: impl IntoIterator<Item = &'a Routes>) -> Vec<String> {
+    let mut topics = Vec::new();
+    for routes in routes {
+        if routes.topics.is_empty() {
+            return Vec::new();
+        }
+        topics.extend(routes.topics.iter().cloned());
+    }
+    topics.sort();
+    topics.dedup();
+    topics
+}
+
+// Handlers for components handling (and allowed to receive) the topic.
+fn routed<'a, S>(handlers: &'a [Handler<S>], topic: &'a str) -> impl Iterator<Item = &'a Handler<S>>
+where
+    S: State,
+    S::StoreCtx: WasiMessagingView,
+{
+    handlers.iter().filter(move |h| {
+        h.state.routes().matches_topic(topic)
+            && h.state.capabilities().check(Capability::Subscribe, topic).is_ok()
+    })
+}
+
 #[derive(Clone)]
 struct Handler<S>
 where
@@ -60,21 +134,66 @@ where
     S: State,
     S::StoreCtx: WasiMessagingView,
 {
+    // Handle the message, recording the outcome.
+    async fn process(&self, message: MessageProxy) {
+        tracing::info!(monotonic_counter.message_counter = 1, service = %self.component);
+
+        if let Err(e) = self.handle(message.clone()).await {
+            if omnia::is_deadline_exceeded(&e) {
+                tracing::warn!(
+                    monotonic_counter.deadline_exceeded = 1,
+                    service = %self.component,
+                    topic = %message.topic(),
+                    "guest deadline exceeded",
+                );
+            } else if omnia::is_fuel_exhausted(&e) {
+                tracing::warn!(
+                    monotonic_counter.fuel_exhausted = 1,
+                    service = %self.component,
+                    topic = %message.topic(),
+                    "guest fuel exhausted",
+                );
+            } else if let Some(exceeded) = omnia::limit_exceeded(&e) {
+                tracing::warn!(
+                    monotonic_counter.limit_exceeded = 1,
+                    service = %self.component,
+                    topic = %message.topic(),
+                    resource = %exceeded.resource,
+                    "{exceeded}",
+                );
+            }
+            tracing::error!("issue processing message: {e}");
+            tracing::error!(
+                monotonic_counter.processing_errors = 1,
+                service = %self.component,
+                topic = %message.topic(),
+                error = %e,
+            );
+        }
+    }
+
     // Forward message to the wasm guest.
     async fn handle(&self, message: MessageProxy) -> Result<()> {
-        let mut store_data = self.state.store();
-        let msg_res = store_data
+        let topic = message.topic();
+        let metadata = message.metadata().unwrap_or_default();
+        let invocation = Invocation::new(&self.component, |key| metadata.get(key).cloned());
+        let mut store = self.state.new_store(invocation.clone())?;
+        let msg_res = store
+            .data_mut()
             .messaging()
             .table
             .push(message)
             .map_err(|e| anyhow!("failed to push message: {e}"))?;
 
         let instance_pre = self.state.instance_pre();
-        let mut store = Store::new(instance_pre.engine(), store_data);
-        let instance = instance_pre.instantiate_async(&mut store).await?;
+        let instance = self
+            .state
+            .instantiate(&instance_pre, &mut store)
+            .instrument(invocation.span.clone())
+            .await?;
         let messaging = MessagingRequestReply::new(&mut store, &instance)?;
 
-        store
+        let result = store
             .run_concurrent(async |store| {
                 let guest = messaging.wasi_messaging_incoming_handler();
                 guest
@@ -84,21 +203,53 @@ where
                     .map_err(anyhow::Error::from)
                     .context("issue sending message")
             })
-            .instrument(debug_span!("messaging-handle"))
-            .await?
+            .instrument(debug_span!(parent: &invocation.span, "messaging-handle"))
+            .instrument(invocation.span.clone())
+            .await;
+
+        // record fuel consumed by the guest
+        if let Some(fuel) = self.state.limits().fuel_consumed(&store) {
+            tracing::info!(
+                histogram.fuel_consumed = fuel,
+                service = %self.component,
+                topic = %topic,
+            );
+        }
+
+        result?
     }
 
-    // Get subscriptions for the topics configured in the wasm component.
-    async fn subscriptions(&self) -> Result<Subscriptions> {
-        let instance_pre = self.state.instance_pre();
-        let store_data = self.state.store();
-        let mut store = Store::new(instance_pre.engine(), store_data);
+    // Subscribe to the specified topics.
+    async fn subscriptions(&self, topics: Vec<String>) -> Result<Subscriptions> {
+        let mut store = self.state.new_store(Invocation::background(&self.component))?;
 
         store
             .run_concurrent(async |store| {
                 let client = store.with(|mut store| store.get().messaging().ctx.connect()).await?;
-                client.subscribe().await
+                client.subscribe_to(topics).await
             })
             .await?
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes(topics: &[&str]) -> Routes {
+        Routes {
+            topics: topics.iter().map(ToString::to_string).collect(),
+            ..Routes::default()
+        }
+    }
+
+    #[test]
+    fn merged_topics() {
+        let components = [routes(&["orders.*", "users"]), routes(&["users", "payments"])];
+        assert_eq!(topics(&components), ["orders.*", "payments", "users"]);
+
+        // a component handling every topic needs every topic
+        let components = [routes(&["orders.*"]), routes(&[])];
+        assert_eq!(topics(&components), Vec::<String>::new());
+    }
+}
@@ -26,7 +26,7 @@ mod generated {
 
 use std::fmt::Debug;
 
-use omnia::{FutureResult, Host, Server, State};
+use omnia::{FutureResult, Host, Invocation, Server, State};
 use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
 use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
 use wasmtime::component::{HasData, Linker, ResourceTable};
@@ -72,6 +72,9 @@ pub struct WasiOtelCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI OpenTelemetry context.
@@ -101,6 +104,7 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_otel::WasiOtelCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    invocation: &self.invocation,
                 }
             }
         }
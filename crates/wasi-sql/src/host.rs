@@ -35,7 +35,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{Backends, Capabilities, Host, Invocation, NamedHost, Server, State};
 use wasmtime::component::{HasData, Linker};
 use wasmtime_wasi::ResourceTable;
 
@@ -64,6 +64,10 @@ where
 
 impl<S> Server<S> for WasiSql where S: State {}
 
+impl NamedHost for WasiSql {
+    type Ctx = dyn WasiSqlCtx;
+}
+
 /// A trait which provides internal WASI SQL state.
 ///
 /// This is implemented by the `T` in `Linker<T>` — a single type shared across
@@ -80,6 +84,12 @@ pub struct WasiSqlCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// Resources the guest may access.
+    pub capabilities: &'a Capabilities,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI SQL context.
@@ -91,6 +101,16 @@ pub trait WasiSqlCtx: Debug + Send + Sync + 'static {
     fn open(&self, name: String) -> FutureResult<Arc<dyn Connection>>;
 }
 
+/// Routes each connection name to its backend.
+impl WasiSqlCtx for Backends<dyn WasiSqlCtx> {
+    fn open(&self, name: String) -> FutureResult<Arc<dyn Connection>> {
+        match self.get(&name) {
+            Ok(backend) => backend.open(name),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+}
+
 /// Implementation of the `WasiSqlView` trait for the store context.
 #[macro_export]
 macro_rules! omnia_wasi_view {
@@ -100,6 +120,8 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_sql::WasiSqlCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    capabilities: &self.capabilities,
+                    invocation: &self.invocation,
                 }
             }
         }
@@ -8,6 +8,7 @@
 #![allow(clippy::cast_lossless)]
 #![allow(missing_docs)]
 
+use std::future::{self, Future};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
@@ -35,6 +36,10 @@ impl omnia::FromEnv for ConnectOptions {
     fn from_env() -> Result<Self> {
         Self::from_env().finalize().context("issue loading connection options")
     }
+
+    fn env_vars() -> Vec<String> {
+        omnia::requirement_vars(&Self::requirements())
+    }
 }
 
 /// Default implementation for `wasi:sql`.
@@ -59,6 +64,15 @@ impl Backend for SqlDefault {
 
         Ok(Self { conn })
     }
+
+    fn health(&self) -> impl Future<Output = Result<()>> + Send {
+        let result = self
+            .conn
+            .lock()
+            .query_row("SELECT 1", [], |_| Ok(()))
+            .context("SQLite database is unavailable");
+        future::ready(result)
+    }
 }
 
 impl WasiSqlCtx for SqlDefault {
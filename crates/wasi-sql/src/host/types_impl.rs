@@ -1,4 +1,5 @@
 use anyhow::Result;
+use omnia::Capability;
 use wasmtime::component::{Access, Accessor, Resource};
 
 use crate::host::generated::wasi::sql::types::{
@@ -12,6 +13,14 @@ impl HostConnectionWithStore for WasiSql {
     async fn open<T>(
         accessor: &Accessor<T, Self>, name: String,
     ) -> wasmtime::Result<Result<Resource<Connection>, Resource<Error>>> {
+        let allowed = accessor
+            .with(|mut store| store.get().capabilities.check(Capability::SqlConnection, &name));
+        if let Err(denied) = allowed {
+            // `wasi:sql` has no access denied error: the message marks the denial
+            let err = anyhow::Error::from(denied);
+            return Ok(Err(accessor.with(|mut store| store.get().table.push(err))?));
+        }
+
         let open_conn = accessor.with(|mut store| store.get().ctx.open(name)).await;
 
         let result = match open_conn {
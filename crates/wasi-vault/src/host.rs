@@ -30,7 +30,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{AccessDenied, Backends, Capabilities, Host, Invocation, NamedHost, Server, State};
 use wasmtime::component::{HasData, Linker, ResourceTableError};
 use wasmtime_wasi::ResourceTable;
 
@@ -61,6 +61,10 @@ where
 
 impl<S> Server<S> for WasiVault where S: State {}
 
+impl NamedHost for WasiVault {
+    type Ctx = dyn WasiVaultCtx;
+}
+
 /// A trait which provides internal WASI Vault state.
 ///
 /// This is implemented by the `T` in `Linker<T>` — a single type shared across
@@ -77,6 +81,12 @@ pub struct WasiVaultCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// Resources the guest may access.
+    pub capabilities: &'a Capabilities,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI Vault context.
@@ -88,6 +98,16 @@ pub trait WasiVaultCtx: Debug + Send + Sync + 'static {
     fn open_locker(&self, identifier: String) -> FutureResult<Arc<dyn Locker>>;
 }
 
+/// Routes each locker identifier to its backend.
+impl WasiVaultCtx for Backends<dyn WasiVaultCtx> {
+    fn open_locker(&self, identifier: String) -> FutureResult<Arc<dyn Locker>> {
+        match self.get(&identifier) {
+            Ok(backend) => backend.open_locker(identifier),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+}
+
 /// `anyhow::Error` to `Error` mapping
 impl From<anyhow::Error> for Error {
     fn from(err: anyhow::Error) -> Self {
@@ -95,6 +115,13 @@ impl From<anyhow::Error> for Error {
     }
 }
 
+/// `AccessDenied` to `Error` mapping
+impl From<AccessDenied> for Error {
+    fn from(_err: AccessDenied) -> Self {
+        Self::AccessDenied
+    }
+}
+
 /// `ResourceTableError` to `Error` mapping
 impl From<ResourceTableError> for Error {
     fn from(err: ResourceTableError) -> Self {
@@ -111,6 +138,8 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_vault::WasiVaultCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    capabilities: &self.capabilities,
+                    invocation: &self.invocation,
                 }
             }
         }
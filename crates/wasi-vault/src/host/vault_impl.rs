@@ -1,4 +1,5 @@
 use anyhow::Context;
+use omnia::Capability;
 use wasmtime::component::{Access, Accessor, Resource};
 
 use crate::host::generated::omnia::vault::vault::Error;
@@ -10,6 +11,8 @@ impl HostWithStore for WasiVault {
     async fn open<T>(
         accessor: &Accessor<T, Self>, locker_id: String,
     ) -> Result<Resource<LockerProxy>> {
+        accessor
+            .with(|mut store| store.get().capabilities.check(Capability::Locker, &locker_id))?;
         let locker = accessor.with(|mut store| store.get().ctx.open_locker(locker_id)).await?;
         let proxy = LockerProxy(locker);
         Ok(accessor.with(|mut store| store.get().table.push(proxy))?)
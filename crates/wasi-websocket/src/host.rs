@@ -37,7 +37,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 pub use omnia::FutureResult;
-use omnia::{Host, Server, State};
+use omnia::{Host, Invocation, Server, Shutdown, State};
 use wasmtime::component::{HasData, Linker};
 use wasmtime_wasi::{ResourceTable, ResourceTableError};
 
@@ -46,6 +46,7 @@ pub use self::generated::Duplex;
 pub use self::generated::omnia::websocket::types::Error;
 use self::generated::omnia::websocket::{client, types as generated_types};
 pub use self::resource::*;
+pub use self::server::deliver;
 
 /// Result type for WebSocket operations.
 pub type Result<T, E = Error> = anyhow::Result<T, E>;
@@ -73,8 +74,8 @@ where
     S: State,
     S::StoreCtx: WebSocketView,
 {
-    async fn run(&self, state: &S) -> anyhow::Result<()> {
-        server::run(state).await
+    async fn run(&self, components: &[S], shutdown: &Shutdown) -> anyhow::Result<()> {
+        server::run(components, shutdown).await
     }
 }
 
@@ -94,6 +95,9 @@ pub struct WasiWebSocketCtxView<'a> {
 
     /// Mutable reference to table used to manage resources.
     pub table: &'a mut ResourceTable,
+
+    /// The inbound request or message the guest is handling.
+    pub invocation: &'a Invocation,
 }
 
 /// A trait which provides internal WASI WebSocket context.
@@ -146,6 +150,7 @@ macro_rules! omnia_wasi_view {
                 omnia_wasi_websocket::WasiWebSocketCtxView {
                     ctx: &mut self.$field_name,
                     table: &mut self.table,
+                    invocation: &self.invocation,
                 }
             }
         }
@@ -1,37 +1,66 @@
-use std::env;
-
 use anyhow::{Context, Result, anyhow};
 use futures::StreamExt;
-use omnia::State;
+use omnia::{Invocation, Shutdown, State};
 use tracing::{Instrument, debug_span, instrument};
-use wasmtime::Store;
 
 use crate::host::WebSocketView;
 use crate::host::generated::Duplex;
 use crate::host::resource::{EventProxy, Events};
 
-#[instrument("websocket-server", skip(state))]
-pub async fn run<S>(state: &S) -> Result<()>
+/// Events are not addressed to a component, so when hosting several
+/// components, all events are handled by the first.
+#[instrument("websocket-server", skip(components, shutdown))]
+pub async fn run<S>(components: &[S], shutdown: &Shutdown) -> Result<()>
 where
     S: State,
     S::StoreCtx: WebSocketView,
 {
-    let component = env::var("COMPONENT").unwrap_or_else(|_| "unknown".into());
+    let Some(state) = components.first() else {
+        return Ok(());
+    };
+    let component = state.name().to_string();
     tracing::info!("starting websocket server for: {component}");
 
     let handler = Handler {
         state: state.clone(),
         component,
     };
+    let concurrency = shutdown.concurrency().for_server("websocket");
 
-    // handle events from the websocket clients
-    while let Some(event) = handler.events().await?.next().await {
+    // handle events from the websocket clients until shutdown is signalled
+    let mut events = Box::pin(handler.events().await?.take_until(shutdown.signalled()));
+    shutdown.listening();
+
+    while let Some(event) = events.next().await {
+        // no more events are pulled while at the concurrency limit
+        let permit = concurrency.acquire().await;
         let handler = handler.clone();
 
-        tokio::spawn(async move {
+        shutdown.spawn(async move {
+            let _permit = permit;
             tracing::info!(monotonic_counter.event_counter = 1, service = %handler.component);
 
             if let Err(e) = handler.handle(event.clone()).await {
+                if omnia::is_deadline_exceeded(&e) {
+                    tracing::warn!(
+                        monotonic_counter.deadline_exceeded = 1,
+                        service = %handler.component,
+                        "guest deadline exceeded",
+                    );
+                } else if omnia::is_fuel_exhausted(&e) {
+                    tracing::warn!(
+                        monotonic_counter.fuel_exhausted = 1,
+                        service = %handler.component,
+                        "guest fuel exhausted",
+                    );
+                } else if let Some(exceeded) = omnia::limit_exceeded(&e) {
+                    tracing::warn!(
+                        monotonic_counter.limit_exceeded = 1,
+                        service = %handler.component,
+                        resource = %exceeded.resource,
+                        "{exceeded}",
+                    );
+                }
                 tracing::error!(
                     monotonic_counter.processing_errors = 1,
                     service = %handler.component,
@@ -41,9 +70,36 @@ where
         });
     }
 
+    // stop receiving events and drain in-flight invocations
+    drop(events);
+    tracing::info!("websocket server shutting down");
+    shutdown.drain().await;
+
     Ok(())
 }
 
+/// Deliver an event to the first of the `components`, as the server does,
+/// without listening for clients, e.g. when driving guests from tests.
+///
+/// # Errors
+///
+/// Returns an error if there are no components or the component fails to
+/// handle the event.
+pub async fn deliver<S>(components: &[S], event: EventProxy) -> Result<()>
+where
+    S: State,
+    S::StoreCtx: WebSocketView,
+{
+    let Some(state) = components.first() else {
+        return Err(anyhow!("no component to handle websocket event"));
+    };
+    let handler = Handler {
+        state: state.clone(),
+        component: state.name().to_string(),
+    };
+    handler.handle(event).await
+}
+
 #[derive(Clone)]
 struct Handler<S>
 where
@@ -61,16 +117,22 @@ where
 {
     /// Forward event to the wasm guest.
     async fn handle(&self, event: EventProxy) -> Result<()> {
-        let mut store_data = self.state.store();
-        let event_res = store_data
+        // events carry no headers, so each is given a new request ID
+        let invocation = Invocation::new(&self.component, |_| None);
+        let mut store = self.state.new_store(invocation.clone())?;
+        let event_res = store
+            .data_mut()
             .websocket()
             .table
             .push(event)
             .map_err(|e| anyhow!("failed to push event: {e}"))?;
 
         let instance_pre = self.state.instance_pre();
-        let mut store = Store::new(instance_pre.engine(), store_data);
-        let instance = instance_pre.instantiate_async(&mut store).await?;
+        let instance = self
+            .state
+            .instantiate(&instance_pre, &mut store)
+            .instrument(invocation.span.clone())
+            .await?;
         let websocket = Duplex::new(&mut store, &instance)?;
 
         store
@@ -83,15 +145,14 @@ where
                     .map_err(anyhow::Error::from)
                     .context("issue handling event")
             })
-            .instrument(debug_span!("websocket-handle"))
+            .instrument(debug_span!(parent: &invocation.span, "websocket-handle"))
+            .instrument(invocation.span.clone())
             .await?
     }
 
     /// Get events for incoming WebSocket events.
     async fn events(&self) -> Result<Events> {
-        let instance_pre = self.state.instance_pre();
-        let store_data = self.state.store();
-        let mut store = Store::new(instance_pre.engine(), store_data);
+        let mut store = self.state.new_store(Invocation::background(&self.component))?;
 
         store
             .run_concurrent(async |store| {
@@ -0,0 +1,22 @@
+//! # Cron Wasm Guest
+//!
+//! This module demonstrates the `omnia:cron` interface. It shows how to:
+//! - Export a handler fired on the schedules configured for the guest
+//! - Use the tick to identify the schedule and when it was due
+
+#![cfg(target_arch = "wasm32")]
+
+use omnia_wasi_cron::handler::{Error, Guest, Tick};
+use tracing::Level;
+
+struct Cron;
+omnia_wasi_cron::export!(Cron);
+
+impl Guest for Cron {
+    /// Handles each scheduled tick.
+    #[omnia_wasi_otel::instrument(name = "cron_guest_handle", level = Level::INFO)]
+    async fn handle(tick: Tick) -> Result<(), Error> {
+        tracing::info!("running schedule `{}` due at {}", tick.schedule, tick.scheduled_at);
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+//! Cron example runtime.
+
+cfg_if::cfg_if! {
+    if #[cfg(not(target_arch = "wasm32"))] {
+        use omnia_wasi_cron::{WasiCron, CronDefault};
+        use omnia_wasi_otel::{WasiOtel, OtelDefault};
+
+        omnia::runtime!({
+            main: true,
+            hosts: {
+                WasiOtel: OtelDefault,
+                WasiCron: CronDefault,
+            }
+        });
+    } else {
+        fn main() {}
+    }
+}